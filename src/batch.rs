@@ -0,0 +1,645 @@
+use crate::file_dialog::{folder_dialog, SAVE_TYPES};
+use crate::{controls, l, long_path, msg_box, navigation, open_image, save_image, scaled};
+use anyhow::{anyhow, Context, Result};
+use image::imageops;
+use std::cell::RefCell;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::{Arc, Once};
+use std::thread;
+use windows::{
+    core::PCWSTR,
+    w,
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Graphics::Gdi::{COLOR_BTNFACE, HBRUSH, HFONT},
+        UI::{
+            Controls::{
+                InitCommonControlsEx, ICC_PROGRESS_CLASS, INITCOMMONCONTROLSEX, PBM_SETPOS,
+                PBM_SETRANGE32, PROGRESS_CLASSW, WC_BUTTONW, WC_COMBOBOXW, WC_EDITW, WC_LISTBOXW,
+                WC_STATICW,
+            },
+            Input::KeyboardAndMouse::EnableWindow,
+            WindowsAndMessaging::{
+                AdjustWindowRectEx, CreateWindowExW, DefWindowProcW, DestroyWindow,
+                GetWindowLongPtrW, GetWindowRect, IsDialogMessageW, LoadCursorW, PostMessageW,
+                RegisterClassW, SendMessageW, SetForegroundWindow, SetWindowLongPtrW,
+                SetWindowTextW, BN_CLICKED, BS_AUTOCHECKBOX, CBS_DROPDOWNLIST, CB_ADDSTRING,
+                CB_GETCURSEL, CB_SETCURSEL, CREATESTRUCTW, ES_AUTOHSCROLL, ES_NUMBER,
+                GWLP_USERDATA, IDC_ARROW, LBS_NOINTEGRALHEIGHT, LBS_NOSEL, LB_ADDSTRING,
+                LB_SETTOPINDEX, MSG, WINDOW_EX_STYLE, WINDOW_STYLE, WM_APP, WM_COMMAND, WM_CREATE,
+                WM_DESTROY, WM_NCCREATE, WM_NCDESTROY, WNDCLASSW, WS_BORDER, WS_CAPTION,
+                WS_SYSMENU, WS_TABSTOP, WS_VISIBLE, WS_VSCROLL,
+            },
+        },
+    },
+};
+
+/// How to convert each file of a batch.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub output_dir: PathBuf,
+    /// One of the extensions in `SAVE_TYPES`.
+    pub extension: &'static str,
+    /// Larger images are shrunk to fit a square of this size, keeping their aspect ratio.
+    pub max_dimension: Option<u32>,
+    /// JPEG quality from 1 to 100.
+    pub quality: u8,
+    /// Added to the name when a file of that name exists, followed by a number if needed.
+    pub suffix: String,
+}
+
+/// Where `source` is converted to, never an existing file.
+pub fn output_path(source: &Path, options: &Options) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = options.extension;
+    let mut path = options.output_dir.join(format!("{stem}.{ext}"));
+    let mut n = 1;
+    while long_path::extended(&path).exists() {
+        let number = if n == 1 { String::new() } else { n.to_string() };
+        path = options
+            .output_dir
+            .join(format!("{stem}{}{number}.{ext}", options.suffix));
+        n += 1;
+    }
+    path
+}
+
+/// Converts one file with the same decoder and encoder as opening and Save As. Returns the
+/// path written.
+pub fn convert(source: &Path, options: &Options) -> Result<PathBuf> {
+    let mut img = open_image(source, &mut |_, _| {})?;
+    if let Some(max) = options.max_dimension {
+        if img.width() > max || img.height() > max {
+            img = img.resize(max, max, imageops::Lanczos3);
+        }
+    }
+    let target = output_path(source, options);
+    save_image(&img, &long_path::extended(&target), options.quality)?;
+    Ok(target)
+}
+
+const CLASS_NAME: PCWSTR = w!("pinion_batch_class");
+
+const ID_USE_LIST: i32 = 3000;
+const ID_SOURCE: i32 = 3001;
+const ID_BROWSE_SOURCE: i32 = 3002;
+const ID_OUTPUT: i32 = 3003;
+const ID_BROWSE_OUTPUT: i32 = 3004;
+const ID_FORMAT: i32 = 3005;
+const ID_QUALITY: i32 = 3006;
+const ID_MAX_SIZE: i32 = 3007;
+const ID_SUFFIX: i32 = 3008;
+const ID_PROGRESS: i32 = 3009;
+const ID_LOG: i32 = 3010;
+const ID_CONVERT: i32 = 3011;
+const ID_CANCEL: i32 = 3012;
+
+/// Posted by the worker after each file, `WPARAM` is the number done and `LPARAM` owns a boxed
+/// `String` for the log.
+const WM_BATCH_PROGRESS: u32 = WM_APP + 1;
+/// Posted when the worker stopped, `LPARAM` owns a boxed summary like `WM_BATCH_PROGRESS`.
+const WM_BATCH_DONE: u32 = WM_APP + 2;
+
+/// Client size of the dialog at 96 DPI.
+const WIDTH: i32 = 520;
+const HEIGHT: i32 = 420;
+
+/// The open dialog, there is at most one.
+static DIALOG: AtomicIsize = AtomicIsize::new(0);
+
+struct Batch {
+    /// The navigation list of the window that opened the dialog.
+    files: Vec<PathBuf>,
+    font: HFONT,
+    /// Set to stop the running batch, `None` when idle.
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+/// Opens the batch conversion dialog, offering `files` as the source, or brings it to the
+/// front if it is open.
+pub fn show(owner: HWND, files: Vec<PathBuf>, font: HFONT) -> Result<()> {
+    let open = HWND(DIALOG.load(Ordering::Relaxed));
+    if open.0 != 0 {
+        unsafe { SetForegroundWindow(open) };
+        return Ok(());
+    }
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let icc = INITCOMMONCONTROLSEX {
+            dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as u32,
+            dwICC: ICC_PROGRESS_CLASS,
+        };
+        let wnd_class = WNDCLASSW {
+            lpszClassName: CLASS_NAME,
+            lpfnWndProc: Some(window_proc),
+            hCursor: unsafe { LoadCursorW(None, IDC_ARROW) }.unwrap_or_default(),
+            hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize),
+            ..Default::default()
+        };
+        unsafe {
+            InitCommonControlsEx(&icc);
+            RegisterClassW(&wnd_class);
+        }
+    });
+
+    let style = WS_CAPTION | WS_SYSMENU;
+    let mut rc = RECT {
+        left: 0,
+        top: 0,
+        right: scaled(WIDTH),
+        bottom: scaled(HEIGHT),
+    };
+    let mut owner_rc = RECT::default();
+    unsafe {
+        AdjustWindowRectEx(&mut rc, style, false, WINDOW_EX_STYLE::default());
+        GetWindowRect(owner, &mut owner_rc);
+    }
+    let (width, height) = (rc.right - rc.left, rc.bottom - rc.top);
+    let state = Box::new(RefCell::new(Batch {
+        files,
+        font,
+        cancel: None,
+    }));
+    let h_wnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            CLASS_NAME,
+            w!("Batch convert"),
+            style | WS_VISIBLE,
+            // centered on the owner.
+            (owner_rc.left + owner_rc.right - width) / 2,
+            (owner_rc.top + owner_rc.bottom - height) / 2,
+            width,
+            height,
+            owner,
+            None,
+            None,
+            Some(Box::into_raw(state) as *const _),
+        )
+    };
+    if h_wnd.0 == 0 {
+        return Err(anyhow!("failed to create the batch dialog."));
+    }
+    DIALOG.store(h_wnd.0, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Routes keyboard input for the dialog, so Tab moves between its controls. Returns whether
+/// `msg` was handled.
+pub fn is_dialog_message(msg: &MSG) -> bool {
+    let h_wnd = HWND(DIALOG.load(Ordering::Relaxed));
+    h_wnd.0 != 0 && unsafe { IsDialogMessageW(h_wnd, msg) }.as_bool()
+}
+
+fn with_state<R>(h_wnd: HWND, f: impl FnOnce(&mut Batch) -> R) -> Option<R> {
+    let state = unsafe { GetWindowLongPtrW(h_wnd, GWLP_USERDATA) } as *const RefCell<Batch>;
+    if state.is_null() {
+        return None;
+    }
+    Some(f(&mut unsafe { &*state }.borrow_mut()))
+}
+
+unsafe extern "system" fn window_proc(
+    h_wnd: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_NCCREATE => {
+            let create = &*(l_param.0 as *const CREATESTRUCTW);
+            SetWindowLongPtrW(h_wnd, GWLP_USERDATA, create.lpCreateParams as isize);
+            return DefWindowProcW(h_wnd, msg, w_param, l_param);
+        }
+        WM_NCDESTROY => {
+            let state = SetWindowLongPtrW(h_wnd, GWLP_USERDATA, 0) as *mut RefCell<Batch>;
+            if !state.is_null() {
+                drop(Box::from_raw(state));
+            }
+            return DefWindowProcW(h_wnd, msg, w_param, l_param);
+        }
+        WM_CREATE => create(h_wnd),
+        WM_COMMAND => command(h_wnd, w_param),
+        WM_BATCH_PROGRESS => {
+            let line = Box::from_raw(l_param.0 as *mut String);
+            log(h_wnd, &line);
+            SendMessageW(
+                controls::item(h_wnd, ID_PROGRESS),
+                PBM_SETPOS,
+                w_param,
+                LPARAM(0),
+            );
+            Ok(())
+        }
+        WM_BATCH_DONE => {
+            let line = Box::from_raw(l_param.0 as *mut String);
+            log(h_wnd, &line);
+            with_state(h_wnd, |state| state.cancel = None);
+            EnableWindow(controls::item(h_wnd, ID_CONVERT), true);
+            SetWindowTextW(controls::item(h_wnd, ID_CANCEL), w!("Close"));
+            Ok(())
+        }
+        WM_DESTROY => {
+            // the worker stops after the file it is on, its messages go nowhere.
+            with_state(h_wnd, |state| {
+                if let Some(cancel) = &state.cancel {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            });
+            DIALOG.store(0, Ordering::Relaxed);
+            Ok(())
+        }
+        _ => return DefWindowProcW(h_wnd, msg, w_param, l_param),
+    }
+    .map_err(|e| msg_box(h_wnd, e))
+    .ok();
+
+    LRESULT::default()
+}
+
+fn create(h_wnd: HWND) -> Result<()> {
+    let (count, first, font) = with_state(h_wnd, |state| {
+        (state.files.len(), state.files.first().cloned(), state.font)
+    })
+    .context("no state")?;
+    let label = |text: &str, y: i32| {
+        controls::create(
+            h_wnd,
+            WC_STATICW,
+            text,
+            WINDOW_STYLE(0),
+            -1,
+            [12, y + 3, 100, 20],
+            font,
+        );
+    };
+    let edit = WS_TABSTOP | WINDOW_STYLE(ES_AUTOHSCROLL as u32);
+    let button = WS_TABSTOP;
+
+    let use_list = controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        &format!("The files of the navigation list ({count})"),
+        WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32),
+        ID_USE_LIST,
+        [12, 12, 496, 20],
+        font,
+    );
+    label("Or the folder", 40);
+    controls::create(
+        h_wnd,
+        WC_EDITW,
+        "",
+        edit,
+        ID_SOURCE,
+        [116, 40, 300, 24],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Browse...",
+        button,
+        ID_BROWSE_SOURCE,
+        [424, 40, 84, 24],
+        font,
+    );
+    label("Output folder", 72);
+    let output = first
+        .as_deref()
+        .and_then(Path::parent)
+        .map(|dir| long_path::display(dir).display().to_string())
+        .unwrap_or_default();
+    controls::create(
+        h_wnd,
+        WC_EDITW,
+        &output,
+        edit,
+        ID_OUTPUT,
+        [116, 72, 300, 24],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Browse...",
+        button,
+        ID_BROWSE_OUTPUT,
+        [424, 72, 84, 24],
+        font,
+    );
+    label("Format", 104);
+    let format = controls::create(
+        h_wnd,
+        WC_COMBOBOXW,
+        "",
+        WS_TABSTOP | WS_VSCROLL | WINDOW_STYLE(CBS_DROPDOWNLIST as u32),
+        ID_FORMAT,
+        [116, 104, 100, 120],
+        font,
+    );
+    for (name, _) in SAVE_TYPES {
+        unsafe {
+            SendMessageW(
+                format,
+                CB_ADDSTRING,
+                WPARAM(0),
+                LPARAM(l(name).as_ptr() as isize),
+            )
+        };
+    }
+    unsafe { SendMessageW(format, CB_SETCURSEL, WPARAM(0), LPARAM(0)) };
+    controls::create(
+        h_wnd,
+        WC_STATICW,
+        "JPEG quality",
+        WINDOW_STYLE(0),
+        -1,
+        [232, 107, 90, 20],
+        font,
+    );
+    let number = edit | WINDOW_STYLE(ES_NUMBER as u32);
+    controls::create(
+        h_wnd,
+        WC_EDITW,
+        "90",
+        number,
+        ID_QUALITY,
+        [326, 104, 50, 24],
+        font,
+    );
+    label("Max size", 136);
+    controls::create(
+        h_wnd,
+        WC_EDITW,
+        "",
+        number,
+        ID_MAX_SIZE,
+        [116, 136, 100, 24],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_STATICW,
+        "pixels, empty to keep the size",
+        WINDOW_STYLE(0),
+        -1,
+        [224, 139, 284, 20],
+        font,
+    );
+    label("Name suffix", 168);
+    controls::create(
+        h_wnd,
+        WC_EDITW,
+        "_converted",
+        edit,
+        ID_SUFFIX,
+        [116, 168, 100, 24],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_STATICW,
+        "added when a file of the name exists",
+        WINDOW_STYLE(0),
+        -1,
+        [224, 171, 284, 20],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        PROGRESS_CLASSW,
+        "",
+        WS_BORDER,
+        ID_PROGRESS,
+        [12, 204, 496, 18],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_LISTBOXW,
+        "",
+        WS_VSCROLL | WINDOW_STYLE((LBS_NOINTEGRALHEIGHT | LBS_NOSEL) as u32),
+        ID_LOG,
+        [12, 230, 496, 140],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Convert",
+        button,
+        ID_CONVERT,
+        [332, 382, 84, 26],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Close",
+        button,
+        ID_CANCEL,
+        [424, 382, 84, 26],
+        font,
+    );
+
+    if count > 0 {
+        controls::set_checked(h_wnd, ID_USE_LIST, true);
+    } else {
+        unsafe { EnableWindow(use_list, false) };
+    }
+    Ok(())
+}
+
+fn log(h_wnd: HWND, line: &str) {
+    let h_log = controls::item(h_wnd, ID_LOG);
+    let index = unsafe {
+        SendMessageW(
+            h_log,
+            LB_ADDSTRING,
+            WPARAM(0),
+            LPARAM(l(line).as_ptr() as isize),
+        )
+    };
+    unsafe { SendMessageW(h_log, LB_SETTOPINDEX, WPARAM(index.0 as usize), LPARAM(0)) };
+}
+
+fn command(h_wnd: HWND, w_param: WPARAM) -> Result<()> {
+    let msg = (w_param.0 as u32) >> 16;
+    let id = ((w_param.0 as u32) & 0xffff) as i32;
+    if msg != BN_CLICKED {
+        return Ok(());
+    }
+    match id {
+        ID_BROWSE_SOURCE | ID_BROWSE_OUTPUT => {
+            let edit = if id == ID_BROWSE_SOURCE {
+                ID_SOURCE
+            } else {
+                ID_OUTPUT
+            };
+            let current = PathBuf::from(controls::text(h_wnd, edit));
+            let initial = current.is_dir().then_some(current.as_path());
+            if let Some(dir) = folder_dialog(h_wnd, initial)? {
+                let dir = long_path::display(&dir);
+                unsafe {
+                    SetWindowTextW(
+                        controls::item(h_wnd, edit),
+                        PCWSTR::from_raw(long_path::to_wide(&dir).as_ptr()),
+                    )
+                };
+                if id == ID_BROWSE_SOURCE {
+                    controls::set_checked(h_wnd, ID_USE_LIST, false);
+                }
+            }
+            Ok(())
+        }
+        ID_CONVERT => start(h_wnd),
+        ID_CANCEL => {
+            let running = with_state(h_wnd, |state| match &state.cancel {
+                Some(cancel) => {
+                    cancel.store(true, Ordering::Relaxed);
+                    true
+                }
+                None => false,
+            });
+            if running != Some(true) {
+                unsafe { DestroyWindow(h_wnd) };
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the options from the controls.
+fn options(h_wnd: HWND) -> Result<Options> {
+    let output_dir = PathBuf::from(controls::text(h_wnd, ID_OUTPUT).trim());
+    if output_dir.as_os_str().is_empty() {
+        return Err(anyhow!("Choose an output folder."));
+    }
+    fs::create_dir_all(long_path::extended(&output_dir))?;
+    let format = unsafe {
+        SendMessageW(
+            controls::item(h_wnd, ID_FORMAT),
+            CB_GETCURSEL,
+            WPARAM(0),
+            LPARAM(0),
+        )
+    };
+    let (_, extension) = SAVE_TYPES
+        .get(format.0 as usize)
+        .copied()
+        .unwrap_or(SAVE_TYPES[0]);
+    let max_size = controls::text(h_wnd, ID_MAX_SIZE);
+    let max_dimension = match max_size.trim() {
+        "" => None,
+        size => Some(
+            size.parse()
+                .ok()
+                .filter(|&size| size > 0)
+                .context("The max size must be a number of pixels.")?,
+        ),
+    };
+    let quality = controls::text(h_wnd, ID_QUALITY)
+        .trim()
+        .parse()
+        .ok()
+        .filter(|quality| (1..=100).contains(quality))
+        .context("The JPEG quality must be from 1 to 100.")?;
+    Ok(Options {
+        output_dir,
+        extension,
+        max_dimension,
+        quality,
+        suffix: controls::text(h_wnd, ID_SUFFIX),
+    })
+}
+
+/// The files to convert: the navigation list, or the supported files of the chosen folder.
+fn sources(h_wnd: HWND) -> Result<Vec<PathBuf>> {
+    if controls::is_checked(h_wnd, ID_USE_LIST) {
+        return with_state(h_wnd, |state| state.files.clone()).context("no state");
+    }
+    let dir = PathBuf::from(controls::text(h_wnd, ID_SOURCE).trim());
+    if dir.as_os_str().is_empty() {
+        return Err(anyhow!("Choose a folder to convert."));
+    }
+    navigation::supported_files(&long_path::extended(&dir))
+        .with_context(|| format!("Cannot read {}.", dir.display()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Converts the files on a background thread, reporting each to the log.
+fn start(h_wnd: HWND) -> Result<()> {
+    let options = options(h_wnd)?;
+    let files = sources(h_wnd)?;
+    if files.is_empty() {
+        log(h_wnd, "No files to convert.");
+        return Ok(());
+    }
+    let cancel = Arc::new(AtomicBool::new(false));
+    with_state(h_wnd, |state| state.cancel = Some(cancel.clone()));
+    unsafe {
+        SendMessageW(
+            controls::item(h_wnd, ID_PROGRESS),
+            PBM_SETRANGE32,
+            WPARAM(0),
+            LPARAM(files.len() as isize),
+        );
+        SendMessageW(
+            controls::item(h_wnd, ID_PROGRESS),
+            PBM_SETPOS,
+            WPARAM(0),
+            LPARAM(0),
+        );
+        EnableWindow(controls::item(h_wnd, ID_CONVERT), false);
+        SetWindowTextW(controls::item(h_wnd, ID_CANCEL), w!("Cancel"));
+    }
+
+    thread::spawn(move || {
+        let mut failed = 0;
+        let mut done = 0;
+        for file in &files {
+            if cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            // a failure is logged and the batch goes on.
+            let line = match convert(file, &options) {
+                Ok(target) => format!("{} -> {}", file_name(file), file_name(&target)),
+                Err(e) => {
+                    failed += 1;
+                    format!("{}: {e}", file_name(file))
+                }
+            };
+            done += 1;
+            post(h_wnd, WM_BATCH_PROGRESS, done, line);
+        }
+        let mut summary = format!("Converted {} of {} files", done - failed, files.len());
+        if failed > 0 {
+            summary += &format!(", {failed} failed");
+        }
+        if done < files.len() {
+            summary += ", cancelled";
+        }
+        post(h_wnd, WM_BATCH_DONE, done, summary + ".");
+    });
+    Ok(())
+}
+
+fn post(h_wnd: HWND, msg: u32, done: usize, line: String) {
+    let line = Box::into_raw(Box::new(line));
+    let posted = unsafe { PostMessageW(h_wnd, msg, WPARAM(done), LPARAM(line as isize)) };
+    // the dialog may be gone by now.
+    if !posted.as_bool() {
+        drop(unsafe { Box::from_raw(line) });
+    }
+}
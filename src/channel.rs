@@ -0,0 +1,78 @@
+use image::{DynamicImage, GrayImage};
+
+/// One channel of the image, shown alone as gray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl Channel {
+    /// The channel of the keys 1 to 4.
+    pub fn from_number(n: u8) -> Option<Channel> {
+        match n {
+            1 => Some(Channel::Red),
+            2 => Some(Channel::Green),
+            3 => Some(Channel::Blue),
+            4 => Some(Channel::Alpha),
+            _ => None,
+        }
+    }
+
+    /// The channel of `img` as a grayscale image, 8 bits deep. The alpha of an image without
+    /// one is solid white.
+    pub fn isolate(self, img: &DynamicImage) -> DynamicImage {
+        let index = match self {
+            Channel::Red => 0,
+            Channel::Green => 1,
+            Channel::Blue => 2,
+            Channel::Alpha => 3,
+        };
+        let rgba = img.to_rgba8();
+        let values = rgba.pixels().map(|pixel| pixel[index]).collect();
+        // the length matches the size by construction.
+        let gray = GrayImage::from_raw(img.width(), img.height(), values).unwrap_or_default();
+        DynamicImage::ImageLuma8(gray)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Red => "red",
+            Channel::Green => "green",
+            Channel::Blue => "blue",
+            Channel::Alpha => "alpha",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn isolates_each_channel_as_gray() {
+        let img = RgbaImage::from_fn(3, 2, |x, y| {
+            let i = (y * 3 + x) as u8;
+            Rgba([i, 10 + i, 20 + i, 30 + i])
+        });
+        let img = DynamicImage::ImageRgba8(img);
+        for (n, offset) in (1..=4).zip([0, 10, 20, 30]) {
+            let channel = Channel::from_number(n).unwrap();
+            let gray = channel.isolate(&img);
+            assert_eq!(gray.color(), image::ColorType::L8);
+            let expected: Vec<u8> = (0..6).map(|i| offset + i).collect();
+            assert_eq!(gray.as_bytes(), expected, "{}", channel.name());
+        }
+        assert_eq!(Channel::from_number(5), None);
+    }
+
+    #[test]
+    fn alpha_of_an_opaque_image_is_white() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([1, 2, 3])));
+        assert_eq!(Channel::Alpha.isolate(&img).as_bytes(), [255; 4]);
+        assert_eq!(Channel::Blue.isolate(&img).as_bytes(), [3; 4]);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::dib;
+use anyhow::{anyhow, ensure, Result};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use std::io::Cursor;
+use windows::{
+    w,
+    Win32::{
+        Foundation::{HANDLE, HGLOBAL, HWND},
+        System::{
+            DataExchange::{
+                CloseClipboard, EmptyClipboard, GetClipboardData, GetClipboardOwner,
+                IsClipboardFormatAvailable, OpenClipboard, RegisterClipboardFormatW,
+                SetClipboardData,
+            },
+            Memory::GlobalFree,
+            Ole::{CF_DIB, CF_DIBV5, CF_UNICODETEXT},
+            Threading::GetCurrentProcessId,
+        },
+        UI::WindowsAndMessaging::GetWindowThreadProcessId,
+    },
+};
+
+/// Images with more pixels than this are copied as CF_DIB only, encoding them as PNG would
+/// hold up the window.
+const MAX_PNG_PIXELS: u64 = 16_000_000;
+
+/// The "PNG" format browsers and image editors exchange, which keeps alpha.
+fn png_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(w!("PNG")) }
+}
+
+/// Replaces the clipboard contents with `blocks` of data by format. Blocks the clipboard
+/// doesn't take are freed.
+fn set(h_wnd: HWND, blocks: &[(u32, HGLOBAL)]) -> Result<()> {
+    let free = |blocks: &[(u32, HGLOBAL)]| {
+        for (_, h_global) in blocks {
+            unsafe { GlobalFree(*h_global).ok() };
+        }
+    };
+    if unsafe { !OpenClipboard(h_wnd).as_bool() } {
+        free(blocks);
+        return Err(anyhow!("cannot open the clipboard."));
+    }
+    unsafe { EmptyClipboard() };
+    let mut result = Ok(());
+    for (i, (format, h_global)) in blocks.iter().enumerate() {
+        if let Err(e) = unsafe { SetClipboardData(*format, HANDLE(h_global.0)) } {
+            free(&blocks[i..]);
+            result = Err(e.into());
+            break;
+        }
+    }
+    unsafe { CloseClipboard() };
+    result
+}
+
+/// Replaces the clipboard contents with `text`.
+pub fn set_text(h_wnd: HWND, text: &str) -> Result<()> {
+    let wide: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+    let bytes: Vec<u8> = wide.iter().flat_map(|c| c.to_le_bytes()).collect();
+    let h_global = dib::to_hglobal(&bytes)?;
+    set(h_wnd, &[(CF_UNICODETEXT.0 as u32, h_global)])
+}
+
+/// Replaces the clipboard contents with `img`, as CF_DIB and, unless it is very large, as PNG
+/// for applications that want its alpha.
+pub fn set_image(h_wnd: HWND, img: &DynamicImage) -> Result<()> {
+    let mut blocks = vec![(CF_DIB.0 as u32, dib::to_hglobal(&dib::encode(img))?)];
+    if img.width() as u64 * img.height() as u64 <= MAX_PNG_PIXELS {
+        let png = encode_png(img).and_then(|png| dib::to_hglobal(&png));
+        match png {
+            Ok(h_global) => blocks.push((png_format(), h_global)),
+            Err(e) => {
+                unsafe { GlobalFree(blocks[0].1).ok() };
+                return Err(e);
+            }
+        }
+    }
+    set(h_wnd, &blocks)
+}
+
+/// `img` as a PNG file, the contents of the "PNG" format.
+fn encode_png(img: &DynamicImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+/// Decodes clipboard `data`, a PNG file when `png` and a packed DIB otherwise.
+fn decode(data: &[u8], png: bool) -> Result<DynamicImage> {
+    if png {
+        Ok(image::load_from_memory_with_format(data, ImageFormat::Png)?)
+    } else {
+        dib::decode(data)
+    }
+}
+
+/// Whether the clipboard holds an image. Windows converts between CF_BITMAP, CF_DIB and
+/// CF_DIBV5, so any of them counts.
+pub fn has_image() -> bool {
+    [CF_DIB.0 as u32, png_format()]
+        .into_iter()
+        .any(|format| unsafe { IsClipboardFormatAvailable(format) }.as_bool())
+}
+
+/// The image on the clipboard, `None` when it holds none.
+pub fn get_image(h_wnd: HWND) -> Result<Option<DynamicImage>> {
+    // PNG and CF_DIBV5 can carry alpha.
+    let Some(format) = [png_format(), CF_DIBV5.0 as u32, CF_DIB.0 as u32]
+        .into_iter()
+        .find(|&format| unsafe { IsClipboardFormatAvailable(format) }.as_bool())
+    else {
+        return Ok(None);
+    };
+    ensure!(
+        unsafe { OpenClipboard(h_wnd) }.as_bool(),
+        "cannot open the clipboard."
+    );
+    let data = unsafe { GetClipboardData(format) }
+        .map_err(Into::into)
+        .and_then(|handle| dib::from_hglobal(HGLOBAL(handle.0)));
+    unsafe { CloseClipboard() };
+    decode(&data?, format == png_format()).map(Some)
+}
+
+/// Whether a window of this process put the current contents on the clipboard.
+pub fn is_own() -> bool {
+    let owner = unsafe { GetClipboardOwner() };
+    let mut process = 0;
+    owner.0 != 0
+        && unsafe {
+            GetWindowThreadProcessId(owner, Some(&mut process));
+            process == GetCurrentProcessId()
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn png_keeps_the_alpha() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(5, 3, |x, y| {
+            Rgba([x as u8 * 50, y as u8 * 80, 7, (x * 60 + y) as u8])
+        }));
+        let png = encode_png(&img).unwrap();
+        assert_eq!(decode(&png, true).unwrap().to_rgba8(), img.to_rgba8());
+    }
+
+    #[test]
+    fn dib_keeps_the_colors() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(5, 3, |x, y| {
+            Rgb([x as u8 * 50, y as u8 * 80, 7])
+        }));
+        let decoded = decode(&dib::encode(&img), false).unwrap();
+        assert_eq!(decoded.to_rgb8(), img.to_rgb8());
+    }
+}
@@ -0,0 +1,236 @@
+use crate::long_path;
+use anyhow::{ensure, Result};
+use std::ffi::c_void;
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, MAX_PATH},
+        Graphics::Gdi::{GetDC, ReleaseDC},
+        Storage::FileSystem::{FILE_SHARE_READ, OPEN_EXISTING},
+        UI::ColorSystem::{
+            CloseColorProfile, CreateMultiProfileTransform, DeleteColorTransform, GetICMProfileW,
+            GetStandardColorSpaceProfileW, OpenColorProfileW, TranslateBitmapBits, BEST_MODE,
+            BM_RGBTRIPLETS, INDEX_DONT_CARE, INTENT_RELATIVE_COLORIMETRIC, PROFILE,
+            PROFILE_FILENAME, PROFILE_READ,
+        },
+    },
+};
+
+/// `LCS_sRGB`, the signature of the standard sRGB color space.
+const LCS_SRGB: u32 = 0x7352_4742;
+
+/// Points sampled along each channel, 15 apart so that they fall on whole values.
+const GRID: usize = 18;
+const GRID_STEP: usize = 255 / (GRID - 1);
+
+/// Converts display pixels from sRGB to the color profile of a monitor.
+pub struct ColorTransform {
+    /// The transform sampled on a `GRID`³ lattice of sRGB colors, red major. Colors in between
+    /// are interpolated, so the lattice keeps what one channel does to the others: a wide
+    /// gamut monitor desaturates the primaries.
+    lut: Vec<[u8; 3]>,
+    /// File name of the monitor profile.
+    pub profile: String,
+}
+
+fn from_wide(buf: &[u16]) -> PathBuf {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    PathBuf::from(OsString::from_wide(&buf[..len]))
+}
+
+/// The ICC profile of the monitor `h_wnd` is on.
+fn monitor_profile(h_wnd: HWND) -> Option<PathBuf> {
+    let mut buf = [0u16; MAX_PATH as usize];
+    let mut size = buf.len() as u32;
+    let ok = unsafe {
+        let hdc = GetDC(h_wnd);
+        let ok = GetICMProfileW(hdc, &mut size, PWSTR::from_raw(buf.as_mut_ptr()));
+        ReleaseDC(h_wnd, hdc);
+        ok
+    };
+    ok.as_bool().then(|| from_wide(&buf))
+}
+
+fn srgb_profile() -> Option<PathBuf> {
+    let mut buf = [0u16; MAX_PATH as usize];
+    // in bytes.
+    let mut size = (buf.len() * 2) as u32;
+    let ok = unsafe {
+        GetStandardColorSpaceProfileW(
+            PCWSTR::null(),
+            LCS_SRGB,
+            PWSTR::from_raw(buf.as_mut_ptr()),
+            &mut size,
+        )
+    };
+    ok.as_bool().then(|| from_wide(&buf))
+}
+
+fn open_profile(path: &Path) -> Result<isize> {
+    let mut name = long_path::to_wide(path);
+    let profile = PROFILE {
+        dwType: PROFILE_FILENAME,
+        pProfileData: name.as_mut_ptr() as *mut c_void,
+        cbDataSize: (name.len() * 2) as u32,
+    };
+    let handle =
+        unsafe { OpenColorProfileW(&profile, PROFILE_READ, FILE_SHARE_READ.0, OPEN_EXISTING.0) };
+    ensure!(
+        handle != 0,
+        "cannot open the color profile {}.",
+        path.display()
+    );
+    Ok(handle)
+}
+
+impl ColorTransform {
+    /// The transform for the monitor `h_wnd` is on. `None` when the monitor has no profile of
+    /// its own, sRGB is then shown as is.
+    pub fn for_window(h_wnd: HWND) -> Result<Option<ColorTransform>> {
+        let Some(monitor) = monitor_profile(h_wnd) else {
+            return Ok(None);
+        };
+        let srgb = srgb_profile();
+        if srgb
+            .as_ref()
+            .is_some_and(|srgb| srgb.file_name() == monitor.file_name())
+        {
+            return Ok(None);
+        }
+        let Some(srgb) = srgb else {
+            return Ok(None);
+        };
+
+        let source = open_profile(&srgb)?;
+        let target = open_profile(&monitor).inspect_err(|_| unsafe {
+            CloseColorProfile(source);
+        })?;
+        let handle = unsafe {
+            let handle = CreateMultiProfileTransform(
+                &[source, target],
+                &[INTENT_RELATIVE_COLORIMETRIC],
+                BEST_MODE,
+                INDEX_DONT_CARE,
+            );
+            CloseColorProfile(source);
+            CloseColorProfile(target);
+            handle
+        };
+        ensure!(handle != 0, "cannot create the color transform.");
+        let lut = translate(handle, &lattice().collect::<Vec<_>>());
+        unsafe { DeleteColorTransform(handle) };
+        Ok(Some(ColorTransform {
+            lut: lut?,
+            profile: monitor
+                .file_name()
+                .unwrap_or(monitor.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        }))
+    }
+
+    /// The transform `convert` sampled like a monitor's, named `profile`.
+    #[cfg(test)]
+    pub fn from_fn(profile: &str, convert: impl Fn([u8; 3]) -> [u8; 3]) -> ColorTransform {
+        ColorTransform {
+            lut: lattice().map(convert).collect(),
+            profile: profile.to_string(),
+        }
+    }
+
+    /// Converts the sRGB color `rgb`, interpolating trilinearly between the samples around it.
+    pub fn map(&self, rgb: [u8; 3]) -> [u8; 3] {
+        // the lower lattice point of each channel and how far past it `rgb` is.
+        let cell = rgb.map(|v| (v as usize / GRID_STEP).min(GRID - 2));
+        let frac: [f32; 3] =
+            [0, 1, 2].map(|c| (rgb[c] as usize - cell[c] * GRID_STEP) as f32 / GRID_STEP as f32);
+        let mut out = [0f32; 3];
+        for corner in 0..8 {
+            let step = [corner >> 2 & 1, corner >> 1 & 1, corner & 1];
+            let weight: f32 = (0..3)
+                .map(|c| if step[c] == 1 { frac[c] } else { 1.0 - frac[c] })
+                .product();
+            if weight == 0.0 {
+                continue;
+            }
+            let [r, g, b] = [0, 1, 2].map(|c| cell[c] + step[c]);
+            let sample = self.lut[(r * GRID + g) * GRID + b];
+            for (out, sample) in out.iter_mut().zip(sample) {
+                *out += weight * sample as f32;
+            }
+        }
+        out.map(|v| v.round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+/// The sRGB colors the transform is sampled at, in the order of `ColorTransform::lut`.
+fn lattice() -> impl Iterator<Item = [u8; 3]> {
+    let level = |i: usize| (i * GRID_STEP) as u8;
+    (0..GRID).flat_map(move |r| {
+        (0..GRID).flat_map(move |g| (0..GRID).map(move |b| [level(r), level(g), level(b)]))
+    })
+}
+
+/// Runs `colors` through the transform `handle`, as one row of 24-bit BGR pixels like those
+/// of a DIB.
+fn translate(handle: isize, colors: &[[u8; 3]]) -> Result<Vec<[u8; 3]>> {
+    let row: Vec<u8> = colors.iter().flat_map(|&[r, g, b]| [b, g, r]).collect();
+    let mut converted = vec![0u8; row.len()];
+    let ok = unsafe {
+        TranslateBitmapBits(
+            handle,
+            row.as_ptr() as *const c_void,
+            BM_RGBTRIPLETS,
+            colors.len() as u32,
+            1,
+            0,
+            converted.as_mut_ptr() as *mut c_void,
+            BM_RGBTRIPLETS,
+            0,
+            None,
+            LPARAM(0),
+        )
+    };
+    ensure!(ok.as_bool(), "TranslateBitmapBits failed.");
+    Ok(converted
+        .chunks_exact(3)
+        .map(|bgr| [bgr[2], bgr[1], bgr[0]])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Roughly sRGB shown on a Display P3 monitor, ignoring the tone curves: the primaries
+    /// move inwards.
+    fn wide_gamut([r, g, b]: [u8; 3]) -> [u8; 3] {
+        let matrix = [[0.82, 0.18, 0.0], [0.03, 0.97, 0.0], [0.02, 0.08, 0.9]];
+        matrix.map(|row: [f32; 3]| {
+            (row[0] * r as f32 + row[1] * g as f32 + row[2] * b as f32).round() as u8
+        })
+    }
+
+    #[test]
+    fn saturated_primaries_are_pulled_in() {
+        let color = ColorTransform::from_fn("p3.icm", wide_gamut);
+        assert_eq!(color.map([255, 0, 0]), [209, 8, 5]);
+        assert_eq!(color.map([0, 255, 0]), [46, 247, 20]);
+        // between the samples too.
+        let between = [200, 37, 91];
+        let [r, g, b] = color.map(between);
+        let [er, eg, eb] = wide_gamut(between);
+        assert!(r.abs_diff(er) <= 1 && g.abs_diff(eg) <= 1 && b.abs_diff(eb) <= 1);
+    }
+
+    #[test]
+    fn the_identity_keeps_every_value() {
+        let color = ColorTransform::from_fn("srgb.icm", |rgb| rgb);
+        for v in 0..=255 {
+            assert_eq!(color.map([v, 255 - v, v / 3]), [v, 255 - v, v / 3]);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{controls, l, scaled};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use windows::{
+    core::PCWSTR,
+    w,
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Graphics::Gdi::{COLOR_BTNFACE, HBRUSH, HFONT},
+        UI::{
+            Controls::{WC_BUTTONW, WC_STATICW},
+            Input::KeyboardAndMouse::EnableWindow,
+            WindowsAndMessaging::{
+                AdjustWindowRectEx, CreateWindowExW, DefWindowProcW, DestroyWindow,
+                DispatchMessageW, GetMessageW, GetWindowLongPtrW, GetWindowRect, IsDialogMessageW,
+                IsWindow, LoadCursorW, PostQuitMessage, RegisterClassW, SetWindowLongPtrW,
+                TranslateMessage, BN_CLICKED, BS_AUTOCHECKBOX, BS_DEFPUSHBUTTON, CREATESTRUCTW,
+                GWLP_USERDATA, IDCANCEL, IDC_ARROW, IDOK, MSG, WINDOW_STYLE, WM_CLOSE, WM_COMMAND,
+                WM_CREATE, WM_NCCREATE, WNDCLASSW, WS_CAPTION, WS_EX_DLGMODALFRAME, WS_SYSMENU,
+                WS_TABSTOP, WS_VISIBLE,
+            },
+        },
+    },
+};
+
+const CLASS_NAME: PCWSTR = w!("pinion_confirm_class");
+
+const ID_CHECK: i32 = 5000;
+
+/// Client size of the window at 96 DPI.
+const WIDTH: i32 = 400;
+const HEIGHT: i32 = 136;
+
+/// Set while a question is open, so a timer firing behind it cannot ask another.
+static ASKING: AtomicBool = AtomicBool::new(false);
+
+struct Confirm {
+    owner: HWND,
+    text: String,
+    ok: String,
+    check: String,
+    font: HFONT,
+    /// Whether the user chose `ok` and whether the box was checked then.
+    answer: (bool, bool),
+}
+
+/// Asks `text` in a window modal to `owner`, with an `ok` button, Cancel and a check box
+/// labeled `check`. Returns whether the user chose `ok` and whether the box was checked. The
+/// answer is no while another question is open.
+pub fn ask(
+    owner: HWND,
+    title: &str,
+    text: &str,
+    ok: &str,
+    check: &str,
+    font: HFONT,
+) -> (bool, bool) {
+    if ASKING.swap(true, Ordering::Relaxed) {
+        return (false, false);
+    }
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let wnd_class = WNDCLASSW {
+            lpszClassName: CLASS_NAME,
+            lpfnWndProc: Some(window_proc),
+            hCursor: unsafe { LoadCursorW(None, IDC_ARROW) }.unwrap_or_default(),
+            hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize),
+            ..Default::default()
+        };
+        unsafe { RegisterClassW(&wnd_class) };
+    });
+
+    let style = WS_CAPTION | WS_SYSMENU;
+    let mut rc = RECT {
+        left: 0,
+        top: 0,
+        right: scaled(WIDTH),
+        bottom: scaled(HEIGHT),
+    };
+    let mut owner_rc = RECT::default();
+    unsafe {
+        AdjustWindowRectEx(&mut rc, style, false, WS_EX_DLGMODALFRAME);
+        GetWindowRect(owner, &mut owner_rc);
+    }
+    let (width, height) = (rc.right - rc.left, rc.bottom - rc.top);
+    // owned here rather than by the window, the answer is read after it is gone.
+    let state = RefCell::new(Confirm {
+        owner,
+        text: text.to_string(),
+        ok: ok.to_string(),
+        check: check.to_string(),
+        font,
+        answer: (false, false),
+    });
+    let title = l(title);
+    unsafe {
+        EnableWindow(owner, false);
+        let h_wnd = CreateWindowExW(
+            WS_EX_DLGMODALFRAME,
+            CLASS_NAME,
+            PCWSTR::from_raw(title.as_ptr()),
+            style | WS_VISIBLE,
+            // centered on the owner.
+            (owner_rc.left + owner_rc.right - width) / 2,
+            (owner_rc.top + owner_rc.bottom - height) / 2,
+            width,
+            height,
+            owner,
+            None,
+            None,
+            Some(&state as *const _ as *const _),
+        );
+
+        let mut msg = MSG::default();
+        while IsWindow(h_wnd).as_bool() {
+            if !GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                // pass the quit on to the main loop.
+                PostQuitMessage(msg.wParam.0 as i32);
+                DestroyWindow(h_wnd);
+                break;
+            }
+            if !IsDialogMessageW(h_wnd, &msg).as_bool() {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+        EnableWindow(owner, true);
+    }
+    ASKING.store(false, Ordering::Relaxed);
+    let answer = state.borrow().answer;
+    answer
+}
+
+fn with_state<R>(h_wnd: HWND, f: impl FnOnce(&mut Confirm) -> R) -> Option<R> {
+    let state = unsafe { GetWindowLongPtrW(h_wnd, GWLP_USERDATA) } as *const RefCell<Confirm>;
+    if state.is_null() {
+        return None;
+    }
+    Some(f(&mut unsafe { &*state }.borrow_mut()))
+}
+
+unsafe extern "system" fn window_proc(
+    h_wnd: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_NCCREATE => {
+            let create = &*(l_param.0 as *const CREATESTRUCTW);
+            SetWindowLongPtrW(h_wnd, GWLP_USERDATA, create.lpCreateParams as isize);
+            DefWindowProcW(h_wnd, msg, w_param, l_param)
+        }
+        WM_CREATE => {
+            create(h_wnd);
+            LRESULT::default()
+        }
+        WM_COMMAND => {
+            command(h_wnd, w_param);
+            LRESULT::default()
+        }
+        // like Cancel, so the owner is enabled before the window goes.
+        WM_CLOSE => {
+            command(h_wnd, WPARAM(IDCANCEL.0 as usize));
+            LRESULT::default()
+        }
+        _ => DefWindowProcW(h_wnd, msg, w_param, l_param),
+    }
+}
+
+fn create(h_wnd: HWND) {
+    with_state(h_wnd, |state| {
+        let font = state.font;
+        controls::create(
+            h_wnd,
+            WC_STATICW,
+            &state.text,
+            WINDOW_STYLE(0),
+            -1,
+            [16, 16, 368, 52],
+            font,
+        );
+        controls::create(
+            h_wnd,
+            WC_BUTTONW,
+            &state.check,
+            WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32),
+            ID_CHECK,
+            [16, 72, 368, 20],
+            font,
+        );
+        controls::create(
+            h_wnd,
+            WC_BUTTONW,
+            &state.ok,
+            WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32),
+            IDOK.0,
+            [208, 100, 84, 26],
+            font,
+        );
+        controls::create(
+            h_wnd,
+            WC_BUTTONW,
+            "Cancel",
+            WS_TABSTOP,
+            IDCANCEL.0,
+            [300, 100, 84, 26],
+            font,
+        );
+    });
+}
+
+fn command(h_wnd: HWND, w_param: WPARAM) {
+    let msg = (w_param.0 as u32) >> 16;
+    let id = ((w_param.0 as u32) & 0xffff) as i32;
+    // Enter and Esc come as IDOK and IDCANCEL from any control.
+    if msg != BN_CLICKED || (id != IDOK.0 && id != IDCANCEL.0) {
+        return;
+    }
+    let checked = controls::is_checked(h_wnd, ID_CHECK);
+    let owner = with_state(h_wnd, |state| {
+        state.answer = (id == IDOK.0, checked);
+        state.owner
+    });
+    unsafe {
+        // before destroying, so the owner gets the activation back.
+        if let Some(owner) = owner {
+            EnableWindow(owner, true);
+        }
+        DestroyWindow(h_wnd);
+    }
+}
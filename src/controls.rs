@@ -0,0 +1,83 @@
+use crate::{l, scaled};
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{HWND, LPARAM, WPARAM},
+        Graphics::Gdi::HFONT,
+        UI::{
+            Controls::{BST_CHECKED, WC_EDITW, WC_LISTBOXW},
+            WindowsAndMessaging::{
+                CreateWindowExW, GetDlgItem, GetWindowTextLengthW, GetWindowTextW, SendMessageW,
+                BM_GETCHECK, BM_SETCHECK, HMENU, WINDOW_EX_STYLE, WINDOW_STYLE, WM_SETFONT,
+                WS_CHILD, WS_EX_CLIENTEDGE, WS_VISIBLE,
+            },
+        },
+    },
+};
+
+/// The control `id` of the window `h_wnd`.
+pub fn item(h_wnd: HWND, id: i32) -> HWND {
+    unsafe { GetDlgItem(h_wnd, id) }
+}
+
+/// Creates a child control at `[x, y, width, height]`, given at 96 DPI.
+pub fn create(
+    parent: HWND,
+    class: PCWSTR,
+    text: &str,
+    style: WINDOW_STYLE,
+    id: i32,
+    [x, y, width, height]: [i32; 4],
+    font: HFONT,
+) -> HWND {
+    let ex_style = if class == WC_EDITW || class == WC_LISTBOXW {
+        WS_EX_CLIENTEDGE
+    } else {
+        WINDOW_EX_STYLE::default()
+    };
+    let h_control = unsafe {
+        CreateWindowExW(
+            ex_style,
+            class,
+            PCWSTR::from_raw(l(text).as_ptr()),
+            WS_CHILD | WS_VISIBLE | style,
+            scaled(x),
+            scaled(y),
+            scaled(width),
+            scaled(height),
+            parent,
+            HMENU(id as isize),
+            None,
+            None,
+        )
+    };
+    unsafe { SendMessageW(h_control, WM_SETFONT, WPARAM(font.0 as usize), LPARAM(1)) };
+    h_control
+}
+
+/// The text of the control `id`.
+pub fn text(h_wnd: HWND, id: i32) -> String {
+    let h_control = item(h_wnd, id);
+    let len = unsafe { GetWindowTextLengthW(h_control) } as usize;
+    let mut buf = vec![0u16; len + 1];
+    let len = unsafe { GetWindowTextW(h_control, &mut buf) } as usize;
+    String::from_utf16_lossy(&buf[..len])
+}
+
+/// Whether the check box `id` is checked.
+pub fn is_checked(h_wnd: HWND, id: i32) -> bool {
+    let state = unsafe { SendMessageW(item(h_wnd, id), BM_GETCHECK, WPARAM(0), LPARAM(0)) };
+    state.0 as u32 == BST_CHECKED.0
+}
+
+pub fn set_checked(h_wnd: HWND, id: i32, checked: bool) {
+    let state = if checked { BST_CHECKED.0 } else { 0 };
+    unsafe {
+        SendMessageW(
+            item(h_wnd, id),
+            BM_SETCHECK,
+            WPARAM(state as usize),
+            LPARAM(0),
+        )
+    };
+}
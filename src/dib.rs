@@ -0,0 +1,329 @@
+use crate::color::ColorTransform;
+use anyhow::{ensure, Context, Result};
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use windows::Win32::{
+    Foundation::{HGLOBAL, RECT},
+    Graphics::Gdi::{
+        CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GdiFlush, SelectObject,
+        SetBrushOrgEx, SetStretchBltMode, StretchDIBits, BITMAPINFO, BITMAPINFOHEADER,
+        BI_BITFIELDS, BI_RGB, COLORONCOLOR, DIB_RGB_COLORS, HALFTONE, HDC, SRCCOPY,
+    },
+    System::Memory::{
+        GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
+    },
+};
+
+/// How an image is scaled for display.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Scaling {
+    /// Smoothed both ways, for photos.
+    #[default]
+    Smooth,
+    /// Every pixel a sharp block, for pixel art.
+    Nearest,
+}
+
+impl Scaling {
+    pub fn parse(value: &str) -> Option<Scaling> {
+        match value {
+            "smooth" => Some(Scaling::Smooth),
+            "nearest" => Some(Scaling::Nearest),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scaling::Smooth => "smooth",
+            Scaling::Nearest => "nearest",
+        }
+    }
+
+    pub fn toggled(self) -> Scaling {
+        match self {
+            Scaling::Smooth => Scaling::Nearest,
+            Scaling::Nearest => Scaling::Smooth,
+        }
+    }
+}
+
+/// Converts `img` to 24-bit BGR rows padded to four bytes, mapping the colors through `color`.
+/// With a `backdrop` color, transparent pixels are blended onto it; otherwise alpha is dropped.
+fn bgr_rows(
+    img: &DynamicImage,
+    bottom_up: bool,
+    backdrop: Option<[u8; 3]>,
+    color: Option<&ColorTransform>,
+) -> Vec<u8> {
+    let convert = |rgb: [u8; 3]| color.map_or(rgb, |color| color.map(rgb));
+    let width = img.width() as usize;
+    let height = img.height() as usize;
+    let stride = (3 * width + 3) & !3;
+    let mut bits = vec![0u8; stride * height];
+    let offset = |y: usize| if bottom_up { height - 1 - y } else { y } * stride;
+    match backdrop {
+        Some(backdrop) if img.color().has_alpha() => {
+            let rgba = img.to_rgba8();
+            for (y, row) in rgba.rows().enumerate() {
+                let offset = offset(y);
+                for (x, px) in row.enumerate() {
+                    let p = offset + 3 * x;
+                    let alpha = px[3] as u32;
+                    let blended = [0, 1, 2].map(|c| {
+                        ((px[c] as u32 * alpha + backdrop[c] as u32 * (255 - alpha) + 127) / 255)
+                            as u8
+                    });
+                    let [r, g, b] = convert(blended);
+                    bits[p..p + 3].copy_from_slice(&[b, g, r]);
+                }
+            }
+        }
+        _ => {
+            let rgb = img.to_rgb8();
+            for (y, row) in rgb.rows().enumerate() {
+                let offset = offset(y);
+                for (x, px) in row.enumerate() {
+                    let p = offset + 3 * x;
+                    let [r, g, b] = convert(px.0);
+                    bits[p..p + 3].copy_from_slice(&[b, g, r]);
+                }
+            }
+        }
+    }
+    bits
+}
+
+fn header(width: u32, height: i32, size: usize) -> BITMAPINFOHEADER {
+    BITMAPINFOHEADER {
+        biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+        biWidth: width as i32,
+        biHeight: height,
+        biPlanes: 1,
+        biBitCount: 24,
+        biCompression: BI_RGB.0 as u32,
+        biSizeImage: size as u32,
+        ..Default::default()
+    }
+}
+
+/// Encodes `img` as a packed DIB, the layout of CF_DIB: a `BITMAPINFOHEADER` followed by
+/// 24-bit BGR rows, bottom-up and padded to four bytes.
+pub fn encode(img: &DynamicImage) -> Vec<u8> {
+    let bits = bgr_rows(img, true, None, None);
+    let header = header(img.width(), img.height() as i32, bits.len());
+    let header_size = mem::size_of::<BITMAPINFOHEADER>();
+    let mut dib = vec![0u8; header_size];
+    unsafe {
+        ptr::copy_nonoverlapping(
+            &header as *const _ as *const u8,
+            dib.as_mut_ptr(),
+            header_size,
+        )
+    };
+    dib.extend_from_slice(&bits);
+    dib
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Decodes a packed DIB, as found in CF_DIB and CF_DIBV5, by giving it the file header of a
+/// BMP file.
+pub fn decode(packed: &[u8]) -> Result<DynamicImage> {
+    ensure!(
+        packed.len() >= mem::size_of::<BITMAPINFOHEADER>(),
+        "The bitmap is truncated."
+    );
+    let header_size = u32_at(packed, 0);
+    let bit_count = u16::from_le_bytes([packed[14], packed[15]]) as u32;
+    let compression = u32_at(packed, 16);
+    let colors_used = u32_at(packed, 32);
+    // BI_BITFIELDS with the original header puts the three masks after it.
+    let masks = if header_size == 40 && compression == BI_BITFIELDS.0 as u32 {
+        12
+    } else {
+        0
+    };
+    let colors = match colors_used {
+        0 if bit_count <= 8 => 1 << bit_count,
+        n => n,
+    };
+    let offset = colors
+        .checked_mul(4)
+        .and_then(|palette| palette.checked_add(masks))
+        .and_then(|size| size.checked_add(header_size))
+        .and_then(|size| size.checked_add(14))
+        .context("The bitmap header is corrupt.")?;
+    let file_size = u32::try_from(14 + packed.len()).context("The bitmap is too large.")?;
+    let mut bmp = Vec::with_capacity(14 + packed.len());
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&file_size.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&offset.to_le_bytes());
+    bmp.extend_from_slice(packed);
+    let img = image::load_from_memory_with_format(&bmp, ImageFormat::Bmp)?;
+    // many applications leave the alpha of 32-bit bitmaps at zero, meaning opaque.
+    if img.color().has_alpha() && img.to_rgba8().pixels().all(|px| px[3] == 0) {
+        return Ok(DynamicImage::ImageRgb8(img.to_rgb8()));
+    }
+    Ok(img)
+}
+
+/// An image in the layout GDI draws from: 24-bit BGR rows, top-down and padded to four bytes.
+pub struct Dib {
+    width: u32,
+    height: u32,
+    bits: Vec<u8>,
+}
+
+impl Dib {
+    /// Converts `img`, blending transparent pixels onto `backdrop`.
+    pub fn new(img: &DynamicImage, backdrop: [u8; 3]) -> Dib {
+        Dib::with_color(img, backdrop, None)
+    }
+
+    /// Like `new`, then converts the pixels from sRGB through `color`.
+    pub fn with_color(
+        img: &DynamicImage,
+        backdrop: [u8; 3],
+        color: Option<&ColorTransform>,
+    ) -> Dib {
+        let bits = bgr_rows(img, false, Some(backdrop), color);
+        Dib {
+            width: img.width(),
+            height: img.height(),
+            bits,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Bytes of pixel data.
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Draws the whole image stretched to `dest`. Shrinking is smoothed, enlarging keeps the
+    /// pixels sharp.
+    pub fn draw(&self, hdc: HDC, dest: &RECT) {
+        self.draw_scaled(hdc, dest, Scaling::Smooth);
+    }
+
+    /// Like `draw`, but with `Scaling::Nearest` shrinking drops pixels instead of averaging
+    /// them.
+    pub fn draw_scaled(&self, hdc: HDC, dest: &RECT, scaling: Scaling) {
+        let info = BITMAPINFO {
+            bmiHeader: header(self.width, -(self.height as i32), self.bits.len()),
+            ..Default::default()
+        };
+        let dest_width = dest.right - dest.left;
+        let dest_height = dest.bottom - dest.top;
+        unsafe {
+            if dest_width < self.width as i32 && scaling == Scaling::Smooth {
+                SetStretchBltMode(hdc, HALFTONE);
+                // required after switching to HALFTONE.
+                SetBrushOrgEx(hdc, 0, 0, None);
+            } else {
+                SetStretchBltMode(hdc, COLORONCOLOR);
+            }
+            StretchDIBits(
+                hdc,
+                dest.left,
+                dest.top,
+                dest_width,
+                dest_height,
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                Some(self.bits.as_ptr() as *const c_void),
+                &info,
+                DIB_RGB_COLORS,
+                SRCCOPY,
+            );
+        }
+    }
+}
+
+/// Runs `draw` on a memory device context over a `width` x `height` bitmap and returns what it
+/// drew, so whatever paints a window can paint an image as well.
+pub fn capture(width: u32, height: u32, draw: impl FnOnce(HDC)) -> Result<RgbaImage> {
+    let info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            // top-down.
+            biHeight: -(height as i32),
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0 as u32,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut bits = ptr::null_mut();
+    unsafe {
+        let h_mdc = CreateCompatibleDC(None);
+        ensure!(h_mdc.0 != 0, "CreateCompatibleDC failed.");
+        let h_bmp = match CreateDIBSection(h_mdc, &info, DIB_RGB_COLORS, &mut bits, None, 0) {
+            Ok(h_bmp) if !bits.is_null() => h_bmp,
+            _ => {
+                DeleteDC(h_mdc);
+                anyhow::bail!("cannot create a {width} x {height} bitmap.");
+            }
+        };
+        let old = SelectObject(h_mdc, h_bmp);
+        draw(HDC(h_mdc.0));
+        GdiFlush();
+        // BGR with an unused fourth byte.
+        let source =
+            std::slice::from_raw_parts(bits as *const u8, 4 * width as usize * height as usize);
+        let mut img = RgbaImage::new(width, height);
+        for (px, bgr) in img.chunks_exact_mut(4).zip(source.chunks_exact(4)) {
+            px.copy_from_slice(&[bgr[2], bgr[1], bgr[0], 255]);
+        }
+        SelectObject(h_mdc, old);
+        DeleteObject(h_bmp);
+        DeleteDC(h_mdc);
+        Ok(img)
+    }
+}
+
+/// Copies `bytes` into a movable global memory block, as clipboard and OLE transfers expect.
+/// The receiver owns the block.
+pub fn to_hglobal(bytes: &[u8]) -> Result<HGLOBAL> {
+    let h_global = unsafe { GlobalAlloc(GMEM_MOVEABLE, bytes.len())? };
+    let p = unsafe { GlobalLock(h_global) };
+    if p.is_null() {
+        unsafe { GlobalFree(h_global).ok() };
+    }
+    ensure!(!p.is_null(), "GlobalLock failed.");
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), p as *mut u8, bytes.len());
+        GlobalUnlock(h_global);
+    }
+    Ok(h_global)
+}
+
+/// Copies the contents of a global memory block, such as clipboard data.
+pub fn from_hglobal(h_global: HGLOBAL) -> Result<Vec<u8>> {
+    let p = unsafe { GlobalLock(h_global) };
+    ensure!(!p.is_null(), "GlobalLock failed.");
+    let bytes = unsafe {
+        let bytes = std::slice::from_raw_parts(p as *const u8, GlobalSize(h_global)).to_vec();
+        GlobalUnlock(h_global);
+        bytes
+    };
+    Ok(bytes)
+}
@@ -0,0 +1,228 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::borrow::Cow;
+
+/// How the difference view shows the differences.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    /// The absolute difference of each channel, multiplied by `gain`.
+    Absolute { gain: u8 },
+    /// Identical pixels black, differing ones glowing brighter the more they differ.
+    Heatmap,
+}
+
+/// The gains A steps through.
+const GAINS: [u8; 4] = [1, 2, 4, 8];
+
+/// Where only the image compared has pixels, striped so it stands out from any difference.
+const EXCESS: [Rgba<u8>; 2] = [Rgba([255, 0, 255, 255]), Rgba([64, 0, 64, 255])];
+/// Width of the stripes, in pixels.
+const EXCESS_STRIPE: u32 = 8;
+
+impl Default for Style {
+    fn default() -> Self {
+        Style::Absolute { gain: 1 }
+    }
+}
+
+impl Style {
+    /// Between the absolute difference and the heatmap.
+    pub fn toggled(self) -> Style {
+        match self {
+            Style::Absolute { .. } => Style::Heatmap,
+            Style::Heatmap => Style::default(),
+        }
+    }
+
+    /// The next larger gain, back to 1 after the largest. The heatmap has none.
+    pub fn amplified(self) -> Style {
+        match self {
+            Style::Absolute { gain } => Style::Absolute {
+                gain: GAINS.into_iter().find(|&g| g > gain).unwrap_or(GAINS[0]),
+            },
+            Style::Heatmap => Style::Heatmap,
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Style::Absolute { gain: 1 } => "difference".to_string(),
+            Style::Absolute { gain } => format!("difference x{gain}"),
+            Style::Heatmap => "difference heatmap".to_string(),
+        }
+    }
+}
+
+/// The per-channel differences of two images over the region both cover, from the top left.
+pub struct Diff {
+    /// Absolute differences of red, green, blue and alpha.
+    pixels: RgbaImage,
+    /// The sizes of the image compared and the one it was compared with.
+    size: (u32, u32),
+    other_size: (u32, u32),
+    /// How many pixels of `pixels` differ in any channel.
+    differing: u64,
+}
+
+fn rgba8(img: &DynamicImage) -> Cow<'_, RgbaImage> {
+    match img.as_rgba8() {
+        Some(rgba) => Cow::Borrowed(rgba),
+        None => Cow::Owned(img.to_rgba8()),
+    }
+}
+
+/// Compares `img` with `other`, pixel by pixel at full resolution.
+pub fn compute(img: &DynamicImage, other: &DynamicImage) -> Diff {
+    let (a, b) = (rgba8(img), rgba8(other));
+    let width = a.width().min(b.width());
+    let height = a.height().min(b.height());
+    let mut differing = 0;
+    let pixels = RgbaImage::from_fn(width, height, |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let d = Rgba([0, 1, 2, 3].map(|i| pa[i].abs_diff(pb[i])));
+        if d != Rgba([0; 4]) {
+            differing += 1;
+        }
+        d
+    });
+    Diff {
+        pixels,
+        size: a.dimensions(),
+        other_size: b.dimensions(),
+        differing,
+    }
+}
+
+/// Black to red to yellow to white as `t` goes from 0 to 1.
+fn heat(t: f32) -> [u8; 3] {
+    let ramp = |from: f32| ((3.0 * t - from).clamp(0.0, 1.0) * 255.0) as u8;
+    [ramp(0.0), ramp(1.0), ramp(2.0)]
+}
+
+impl Diff {
+    /// How many pixels were compared.
+    pub fn compared(&self) -> u64 {
+        self.pixels.width() as u64 * self.pixels.height() as u64
+    }
+
+    pub fn same_size(&self) -> bool {
+        self.size == self.other_size
+    }
+
+    /// For the title, such as `1234 pixels differ (0.52%)`.
+    pub fn summary(&self) -> String {
+        let percent = 100.0 * self.differing as f64 / self.compared().max(1) as f64;
+        let sizes = if self.same_size() {
+            ""
+        } else {
+            ", sizes differ"
+        };
+        format!("{} pixels differ ({percent:.2}%){sizes}", self.differing)
+    }
+
+    /// The differences as an image the size of the image compared, its part beyond the other
+    /// image striped.
+    pub fn render(&self, style: Style) -> DynamicImage {
+        let (width, height) = (self.pixels.width(), self.pixels.height());
+        let img = RgbaImage::from_fn(self.size.0, self.size.1, |x, y| {
+            if x >= width || y >= height {
+                return EXCESS[((x + y) / EXCESS_STRIPE % 2) as usize];
+            }
+            let d = self.pixels.get_pixel(x, y);
+            // a difference in alpha alone shows as gray.
+            let [r, g, b] = [0, 1, 2].map(|i| d[i].max(d[3]));
+            match style {
+                Style::Absolute { gain } => {
+                    let amplify = |v: u8| v.saturating_mul(gain);
+                    Rgba([amplify(r), amplify(g), amplify(b), 255])
+                }
+                Style::Heatmap => {
+                    let largest = r.max(g).max(b);
+                    if largest == 0 {
+                        Rgba([0, 0, 0, 255])
+                    } else {
+                        // the smallest difference still glows dark red.
+                        let t = (largest as f32 / 255.0).sqrt().max(0.15);
+                        let [r, g, b] = heat(t);
+                        Rgba([r, g, b, 255])
+                    }
+                }
+            }
+        });
+        DynamicImage::ImageRgba8(img)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([x as u8 * 10, y as u8 * 10, 5, 255])
+        }))
+    }
+
+    #[test]
+    fn identical_images_do_not_differ() {
+        let img = gradient(4, 3);
+        let diff = compute(&img, &img);
+        assert_eq!(diff.compared(), 12);
+        assert_eq!(diff.summary(), "0 pixels differ (0.00%)");
+        let rendered = diff.render(Style::Heatmap).to_rgba8();
+        assert!(rendered.pixels().all(|px| *px == Rgba([0, 0, 0, 255])));
+    }
+
+    #[test]
+    fn differences_are_amplified_and_heated() {
+        // a pixel of another red and one of another alpha.
+        let img = gradient(3, 3);
+        let mut other = img.to_rgba8();
+        other.put_pixel(1, 1, Rgba([40, 10, 5, 255]));
+        other.put_pixel(2, 0, Rgba([20, 0, 5, 235]));
+        let diff = compute(&img, &DynamicImage::ImageRgba8(other));
+        assert_eq!(diff.summary(), "2 pixels differ (22.22%)");
+        let absolute = diff.render(Style::Absolute { gain: 4 }).to_rgba8();
+        assert_eq!(*absolute.get_pixel(1, 1), Rgba([120, 0, 0, 255]));
+        assert_eq!(*absolute.get_pixel(2, 0), Rgba([80, 80, 80, 255]));
+        assert_eq!(*absolute.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        let heatmap = diff.render(Style::Heatmap).to_rgba8();
+        assert_eq!(*heatmap.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_ne!(*heatmap.get_pixel(2, 0), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn mismatched_sizes_compare_the_common_part() {
+        let img = gradient(4, 3);
+        let other = DynamicImage::ImageRgb8(RgbImage::from_fn(3, 3, |x, y| {
+            if (x, y) == (1, 1) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([x as u8 * 10, y as u8 * 10, 5])
+            }
+        }));
+        let diff = compute(&img, &other);
+        assert!(!diff.same_size());
+        assert_eq!(diff.compared(), 9);
+        assert_eq!(diff.summary(), "1 pixels differ (11.11%), sizes differ");
+        let rendered = diff.render(Style::default()).to_rgba8();
+        assert_eq!(rendered.dimensions(), (4, 3));
+        assert_eq!(*rendered.get_pixel(1, 1), Rgba([10, 10, 5, 255]));
+        assert_eq!(*rendered.get_pixel(3, 0), EXCESS[0]);
+        // and the other way round, nothing beyond the image compared.
+        let back = compute(&other, &img).render(Style::default());
+        assert_eq!(back.to_rgba8().dimensions(), (3, 3));
+    }
+
+    #[test]
+    fn styles_cycle() {
+        let style = Style::default();
+        assert_eq!(style.name(), "difference");
+        assert_eq!(style.amplified().name(), "difference x2");
+        let cycled = (0..4).fold(style, |style, _| style.amplified());
+        assert_eq!(cycled, style);
+        assert_eq!(style.toggled(), Style::Heatmap);
+        assert_eq!(Style::Heatmap.amplified(), Style::Heatmap);
+        assert_eq!(Style::Heatmap.toggled(), style);
+    }
+}
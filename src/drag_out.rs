@@ -0,0 +1,73 @@
+use crate::{dib, long_path};
+use anyhow::Result;
+use image::DynamicImage;
+use std::mem::ManuallyDrop;
+use std::path::Path;
+use std::ptr;
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::HWND,
+        System::{
+            Com::{
+                IDataObject, DVASPECT_CONTENT, FORMATETC, STGMEDIUM, STGMEDIUM_0, TYMED_HGLOBAL,
+            },
+            Memory::GlobalFree,
+            Ole::{CF_DIB, DROPEFFECT_COPY},
+        },
+        UI::Shell::{
+            BHID_DataObject, IShellItem, SHCreateDataObject, SHCreateItemFromParsingName,
+            SHDoDragDrop,
+        },
+    },
+};
+
+/// Images with more pixels than this are dragged as a file only, without the CF_DIB copy.
+const MAX_DIB_PIXELS: u64 = 50_000_000;
+
+/// Builds the data object for dragging the current image: the file itself, as Explorer would
+/// offer it, plus its pixels as CF_DIB. Without a backing file only CF_DIB is offered.
+pub fn data_object(file_path: Option<&Path>, img: &DynamicImage) -> Result<IDataObject> {
+    let data: IDataObject = match file_path {
+        Some(file_path) => unsafe {
+            let path = long_path::to_wide(&long_path::display(file_path));
+            let item: IShellItem =
+                SHCreateItemFromParsingName(PCWSTR::from_raw(path.as_ptr()), None)?;
+            item.BindToHandler(None, &BHID_DataObject)?
+        },
+        None => unsafe { SHCreateDataObject(None, None, None)? },
+    };
+
+    let pixels = img.width() as u64 * img.height() as u64;
+    if file_path.is_none() || pixels <= MAX_DIB_PIXELS {
+        set_dib(&data, img)?;
+    }
+    Ok(data)
+}
+
+fn set_dib(data: &IDataObject, img: &DynamicImage) -> Result<()> {
+    let h_global = dib::to_hglobal(&dib::encode(img))?;
+    let format = FORMATETC {
+        cfFormat: CF_DIB.0,
+        ptd: ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL.0 as u32,
+    };
+    let medium = STGMEDIUM {
+        tymed: TYMED_HGLOBAL,
+        Anonymous: STGMEDIUM_0 { hGlobal: h_global },
+        pUnkForRelease: ManuallyDrop::new(None),
+    };
+    // the data object owns the memory once SetData succeeds.
+    unsafe { data.SetData(&format, &medium, true) }.inspect_err(|_| unsafe {
+        GlobalFree(h_global).ok();
+    })?;
+    Ok(())
+}
+
+/// Runs the drag loop with the shell's standard drop source. Esc cancels it.
+pub fn drag(h_wnd: HWND, data: &IDataObject) -> Result<()> {
+    unsafe { SHDoDragDrop(h_wnd, data, None, DROPEFFECT_COPY)? };
+    Ok(())
+}
@@ -0,0 +1,391 @@
+use crate::{l, long_path, EXTENSIONS};
+use anyhow::{ensure, Result};
+use std::ffi::OsString;
+use std::mem;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use windows::{
+    core::{ComInterface, PCWSTR, PWSTR},
+    w,
+    Win32::{
+        Foundation::{ERROR_CANCELLED, HWND},
+        System::Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_INPROC_SERVER},
+        UI::{
+            Controls::Dialogs::{
+                CommDlgExtendedError, GetOpenFileNameW, GetSaveFileNameW, OFN_FILEMUSTEXIST,
+                OFN_OVERWRITEPROMPT, OPENFILENAMEW,
+            },
+            Shell::{
+                Common::COMDLG_FILTERSPEC, FOLDERID_Pictures, FileOpenDialog, FileSaveDialog,
+                IFileDialog, IFileDialogCustomize, IFileOpenDialog, IFileSaveDialog, IShellItem,
+                SHCreateItemFromParsingName, SHGetKnownFolderItem, FOS_FILEMUSTEXIST,
+                FOS_FORCEFILESYSTEM, FOS_OVERWRITEPROMPT, FOS_PICKFOLDERS, KF_FLAG_DEFAULT,
+                SIGDN_FILESYSPATH,
+            },
+        },
+    },
+};
+
+const TITLE: &str = "Choose a image file";
+
+/// The formats an image can be saved as, by name and extension.
+pub const SAVE_TYPES: [(&str, &str); 3] = [("PNG", "png"), ("JPEG", "jpg"), ("BMP", "bmp")];
+
+// large enough for any extended-length path.
+const MAX_LONG_PATH: u32 = 32768;
+
+/// Shows the open dialog and returns the chosen path, or `None` when the user cancelled.
+///
+/// The dialog starts in `initial_dir` when given. Uses the `IFileOpenDialog` COM dialog and
+/// falls back to `GetOpenFileNameW` when it cannot be created.
+pub fn open_dialog(h_wnd: HWND, initial_dir: Option<&Path>) -> Result<Option<PathBuf>> {
+    match unsafe {
+        CoCreateInstance::<_, IFileOpenDialog>(&FileOpenDialog, None, CLSCTX_INPROC_SERVER)
+    } {
+        Ok(dialog) => com_open_dialog(&dialog, h_wnd, initial_dir),
+        Err(_) => legacy_open_dialog(h_wnd, initial_dir),
+    }
+}
+
+fn filter_name() -> String {
+    format!("Image file ({})", EXTENSIONS.join(", "))
+}
+
+fn filter_spec() -> String {
+    EXTENSIONS
+        .iter()
+        .map(|ext| format!("*.{ext}"))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn com_open_dialog(
+    dialog: &IFileOpenDialog,
+    h_wnd: HWND,
+    initial_dir: Option<&Path>,
+) -> Result<Option<PathBuf>> {
+    let name = l(&filter_name());
+    let spec = l(&filter_spec());
+    let filters = [COMDLG_FILTERSPEC {
+        pszName: PCWSTR::from_raw(name.as_ptr()),
+        pszSpec: PCWSTR::from_raw(spec.as_ptr()),
+    }];
+
+    unsafe {
+        dialog.SetFileTypes(&filters)?;
+        dialog.SetTitle(PCWSTR::from_raw(l(TITLE).as_ptr()))?;
+        dialog.SetOptions(dialog.GetOptions()? | FOS_FILEMUSTEXIST | FOS_FORCEFILESYSTEM)?;
+        if let Ok(pictures) =
+            SHGetKnownFolderItem::<_, IShellItem>(&FOLDERID_Pictures, KF_FLAG_DEFAULT, None)
+        {
+            dialog.SetDefaultFolder(&pictures)?;
+        }
+        // SetFolder overrides the folder the dialog itself remembers for this application.
+        if let Some(dir) = initial_dir {
+            let dir = long_path::to_wide(dir);
+            if let Ok(folder) = SHCreateItemFromParsingName::<_, _, IShellItem>(
+                PCWSTR::from_raw(dir.as_ptr()),
+                None,
+            ) {
+                dialog.SetFolder(&folder)?;
+            }
+        }
+    }
+
+    match unsafe { dialog.Show(h_wnd) } {
+        Err(e) if e.code() == ERROR_CANCELLED.to_hresult() => return Ok(None),
+        r => r?,
+    }
+
+    let item = unsafe { dialog.GetResult()? };
+    item_path(&item).map(Some)
+}
+
+fn item_path(item: &IShellItem) -> Result<PathBuf> {
+    let path = unsafe { item.GetDisplayName(SIGDN_FILESYSPATH)? };
+    let result = PathBuf::from(OsString::from_wide(unsafe { path.as_wide() }));
+    unsafe { CoTaskMemFree(Some(path.as_ptr() as *const _)) };
+    Ok(result)
+}
+
+fn set_folder(dialog: &impl ComInterface, dir: &Path) {
+    let dir = long_path::to_wide(dir);
+    let Ok(dialog) = dialog.cast::<IFileDialog>() else {
+        return;
+    };
+    if let Ok(folder) = unsafe {
+        SHCreateItemFromParsingName::<_, _, IShellItem>(PCWSTR::from_raw(dir.as_ptr()), None)
+    } {
+        unsafe { dialog.SetFolder(&folder).ok() };
+    }
+}
+
+fn legacy_open_dialog(h_wnd: HWND, initial_dir: Option<&Path>) -> Result<Option<PathBuf>> {
+    let mut buf = vec![0u16; MAX_LONG_PATH as usize];
+
+    let filter = l(&format!("{}\0{}\0", filter_name(), filter_spec()));
+    let title = l(TITLE);
+    let initial_dir = initial_dir.map(long_path::to_wide);
+
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: mem::size_of::<OPENFILENAMEW>() as u32,
+        lpstrFilter: PCWSTR::from_raw(filter.as_ptr()),
+        lpstrTitle: PCWSTR::from_raw(title.as_ptr()),
+        lpstrInitialDir: initial_dir
+            .as_ref()
+            .map_or(PCWSTR::null(), |dir| PCWSTR::from_raw(dir.as_ptr())),
+        lpstrFile: PWSTR::from_raw(buf.as_mut_ptr()),
+        nMaxFile: MAX_LONG_PATH,
+        Flags: OFN_FILEMUSTEXIST,
+        hwndOwner: h_wnd,
+        ..Default::default()
+    };
+
+    if unsafe { !GetOpenFileNameW(&mut ofn).as_bool() } {
+        // zero means the user closed the dialog without choosing a file.
+        let err = unsafe { CommDlgExtendedError() };
+        ensure!(err.0 == 0, "Cannot get file path.");
+        return Ok(None);
+    }
+
+    let result = PathBuf::from(OsString::from_wide(unsafe { ofn.lpstrFile.as_wide() }));
+    Ok(Some(result))
+}
+
+/// Lets the user pick a folder, starting in `initial_dir` when given. `None` if cancelled.
+pub fn folder_dialog(h_wnd: HWND, initial_dir: Option<&Path>) -> Result<Option<PathBuf>> {
+    let dialog: IFileOpenDialog =
+        unsafe { CoCreateInstance(&FileOpenDialog, None, CLSCTX_INPROC_SERVER)? };
+    unsafe {
+        dialog.SetOptions(dialog.GetOptions()? | FOS_PICKFOLDERS | FOS_FORCEFILESYSTEM)?;
+    }
+    if let Some(dir) = initial_dir {
+        set_folder(&dialog, dir);
+    }
+    match unsafe { dialog.Show(h_wnd) } {
+        Err(e) if e.code() == ERROR_CANCELLED.to_hresult() => return Ok(None),
+        r => r?,
+    }
+    let item = unsafe { dialog.GetResult()? };
+    item_path(&item).map(Some)
+}
+
+/// Lets the user pick where to save a playlist, proposing `file_path`. `None` if cancelled.
+pub fn save_list_dialog(h_wnd: HWND, file_path: &Path) -> Result<Option<PathBuf>> {
+    let dialog: IFileSaveDialog =
+        unsafe { CoCreateInstance(&FileSaveDialog, None, CLSCTX_INPROC_SERVER)? };
+    let filters = [COMDLG_FILTERSPEC {
+        pszName: w!("Playlist (*.txt)"),
+        pszSpec: w!("*.txt"),
+    }];
+    unsafe {
+        dialog.SetFileTypes(&filters)?;
+        dialog.SetDefaultExtension(w!("txt"))?;
+        let name = file_path.file_name().unwrap_or_default();
+        dialog.SetFileName(PCWSTR::from_raw(
+            long_path::to_wide(Path::new(name)).as_ptr(),
+        ))?;
+        dialog.SetOptions(dialog.GetOptions()? | FOS_OVERWRITEPROMPT | FOS_FORCEFILESYSTEM)?;
+    }
+    if let Some(dir) = file_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        set_folder(&dialog, dir);
+    }
+    match unsafe { dialog.Show(h_wnd) } {
+        Err(e) if e.code() == ERROR_CANCELLED.to_hresult() => return Ok(None),
+        r => r?,
+    }
+    let item = unsafe { dialog.GetResult()? };
+    item_path(&item).map(Some)
+}
+
+/// Shows the save dialog for a copy of `file_path` and returns the chosen path, or `None` when
+/// the user cancelled. `note` is shown in the dialog when given.
+///
+/// The dialog proposes the same name and format, in the same directory. Like [`open_dialog`] it
+/// falls back to `GetSaveFileNameW`, which cannot show the note.
+pub fn save_dialog(h_wnd: HWND, file_path: &Path, note: Option<&str>) -> Result<Option<PathBuf>> {
+    match unsafe {
+        CoCreateInstance::<_, IFileSaveDialog>(&FileSaveDialog, None, CLSCTX_INPROC_SERVER)
+    } {
+        Ok(dialog) => com_save_dialog(&dialog, h_wnd, file_path, note, None, None),
+        Err(_) => legacy_save_dialog(h_wnd, file_path),
+    }
+}
+
+/// The scales the export dialog offers, as multiples of the window size.
+pub const EXPORT_SCALES: [u32; 4] = [1, 2, 3, 4];
+
+// ids of the controls added to the save dialog.
+const ID_NOTE: u32 = 1;
+const ID_SCALE_GROUP: u32 = 2;
+const ID_SCALE: u32 = 3;
+const ID_GRID: u32 = 4;
+
+/// Like [`save_dialog`], with a choice of `EXPORT_SCALES` starting at `scale`, which is set to
+/// the chosen one, and with a `grid` a check box for including the pixel grid.
+/// `GetSaveFileNameW` cannot offer the choices and leaves both as they are.
+pub fn export_dialog(
+    h_wnd: HWND,
+    file_path: &Path,
+    scale: &mut u32,
+    grid: Option<&mut bool>,
+) -> Result<Option<PathBuf>> {
+    match unsafe {
+        CoCreateInstance::<_, IFileSaveDialog>(&FileSaveDialog, None, CLSCTX_INPROC_SERVER)
+    } {
+        Ok(dialog) => com_save_dialog(&dialog, h_wnd, file_path, None, Some(scale), grid),
+        Err(_) => legacy_save_dialog(h_wnd, file_path),
+    }
+}
+
+/// Index into `SAVE_TYPES` of the format of `file_path`, PNG when it cannot be saved as such.
+fn save_type(file_path: &Path) -> usize {
+    let ext = file_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("jpg" | "jpeg") => 1,
+        Some("bmp") => 2,
+        _ => 0,
+    }
+}
+
+fn save_file_name(file_path: &Path) -> String {
+    let stem = file_path.file_stem().unwrap_or_default().to_string_lossy();
+    format!("{}.{}", stem, SAVE_TYPES[save_type(file_path)].1)
+}
+
+fn com_save_dialog(
+    dialog: &IFileSaveDialog,
+    h_wnd: HWND,
+    file_path: &Path,
+    note: Option<&str>,
+    scale: Option<&mut u32>,
+    grid: Option<&mut bool>,
+) -> Result<Option<PathBuf>> {
+    let names: Vec<_> = SAVE_TYPES
+        .iter()
+        .map(|(name, ext)| l(&format!("{name} (*.{ext})")))
+        .collect();
+    let specs: Vec<_> = SAVE_TYPES
+        .iter()
+        .map(|(_, ext)| l(&format!("*.{ext}")))
+        .collect();
+    let filters: Vec<_> = names
+        .iter()
+        .zip(&specs)
+        .map(|(name, spec)| COMDLG_FILTERSPEC {
+            pszName: PCWSTR::from_raw(name.as_ptr()),
+            pszSpec: PCWSTR::from_raw(spec.as_ptr()),
+        })
+        .collect();
+    let index = save_type(file_path);
+
+    unsafe {
+        dialog.SetFileTypes(&filters)?;
+        // one-based.
+        dialog.SetFileTypeIndex(index as u32 + 1)?;
+        // appended to names typed without one, following the chosen type.
+        dialog.SetDefaultExtension(PCWSTR::from_raw(l(SAVE_TYPES[index].1).as_ptr()))?;
+        dialog.SetFileName(PCWSTR::from_raw(l(&save_file_name(file_path)).as_ptr()))?;
+        dialog.SetOptions(dialog.GetOptions()? | FOS_OVERWRITEPROMPT | FOS_FORCEFILESYSTEM)?;
+        if let Some(dir) = file_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            set_folder(dialog, dir);
+        }
+        if let Some(note) = note {
+            dialog
+                .cast::<IFileDialogCustomize>()?
+                .AddText(ID_NOTE, PCWSTR::from_raw(l(note).as_ptr()))?;
+        }
+        if let Some(scale) = &scale {
+            let customize = dialog.cast::<IFileDialogCustomize>()?;
+            customize.StartVisualGroup(ID_SCALE_GROUP, w!("Size:"))?;
+            customize.AddComboBox(ID_SCALE)?;
+            for (i, n) in EXPORT_SCALES.iter().enumerate() {
+                let label = if *n == 1 {
+                    "The window size".to_string()
+                } else {
+                    format!("{n} times the window size")
+                };
+                customize.AddControlItem(
+                    ID_SCALE,
+                    i as u32,
+                    PCWSTR::from_raw(l(&label).as_ptr()),
+                )?;
+            }
+            let index = EXPORT_SCALES.iter().position(|n| n == *scale).unwrap_or(0);
+            customize.SetSelectedControlItem(ID_SCALE, index as u32)?;
+            customize.EndVisualGroup()?;
+        }
+        if let Some(grid) = &grid {
+            dialog.cast::<IFileDialogCustomize>()?.AddCheckButton(
+                ID_GRID,
+                w!("Include the pixel grid"),
+                **grid,
+            )?;
+        }
+    }
+
+    match unsafe { dialog.Show(h_wnd) } {
+        Err(e) if e.code() == ERROR_CANCELLED.to_hresult() => return Ok(None),
+        r => r?,
+    }
+    if let Some(scale) = scale {
+        let index = unsafe {
+            dialog
+                .cast::<IFileDialogCustomize>()?
+                .GetSelectedControlItem(ID_SCALE)?
+        };
+        *scale = EXPORT_SCALES.get(index as usize).copied().unwrap_or(1);
+    }
+    if let Some(grid) = grid {
+        *grid = unsafe {
+            dialog
+                .cast::<IFileDialogCustomize>()?
+                .GetCheckButtonState(ID_GRID)?
+        }
+        .as_bool();
+    }
+    let item = unsafe { dialog.GetResult()? };
+    item_path(&item).map(Some)
+}
+
+fn legacy_save_dialog(h_wnd: HWND, file_path: &Path) -> Result<Option<PathBuf>> {
+    let mut buf = vec![0u16; MAX_LONG_PATH as usize];
+    for (dst, src) in buf.iter_mut().zip(save_file_name(file_path).encode_utf16()) {
+        *dst = src;
+    }
+
+    let filter: String = SAVE_TYPES
+        .iter()
+        .map(|(name, ext)| format!("{name} (*.{ext})\0*.{ext}\0"))
+        .collect();
+    let filter = l(&filter);
+    let index = save_type(file_path);
+    let ext = l(SAVE_TYPES[index].1);
+    let initial_dir = file_path.parent().map(long_path::to_wide);
+
+    let mut ofn = OPENFILENAMEW {
+        lStructSize: mem::size_of::<OPENFILENAMEW>() as u32,
+        lpstrFilter: PCWSTR::from_raw(filter.as_ptr()),
+        nFilterIndex: index as u32 + 1,
+        lpstrDefExt: PCWSTR::from_raw(ext.as_ptr()),
+        lpstrInitialDir: initial_dir
+            .as_ref()
+            .map_or(PCWSTR::null(), |dir| PCWSTR::from_raw(dir.as_ptr())),
+        lpstrFile: PWSTR::from_raw(buf.as_mut_ptr()),
+        nMaxFile: MAX_LONG_PATH,
+        Flags: OFN_OVERWRITEPROMPT,
+        hwndOwner: h_wnd,
+        ..Default::default()
+    };
+
+    if unsafe { !GetSaveFileNameW(&mut ofn).as_bool() } {
+        let err = unsafe { CommDlgExtendedError() };
+        ensure!(err.0 == 0, "Cannot get file path.");
+        return Ok(None);
+    }
+
+    let result = PathBuf::from(OsString::from_wide(unsafe { ofn.lpstrFile.as_wide() }));
+    Ok(Some(result))
+}
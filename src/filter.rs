@@ -0,0 +1,36 @@
+use image::DynamicImage;
+
+/// A processing step previewed over the image, or applied to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Blur,
+    Sharpen,
+}
+
+/// Standard deviation of the Gaussian blur, in pixels.
+const BLUR_SIGMA: f32 = 1.5;
+/// Radius of the unsharp mask, and the difference below which pixels are left alone.
+const SHARPEN_SIGMA: f32 = 1.0;
+const SHARPEN_THRESHOLD: i32 = 2;
+
+impl Filter {
+    pub fn apply(self, img: &DynamicImage) -> DynamicImage {
+        self.apply_scaled(img, 1.0)
+    }
+
+    /// Like `apply` on a copy of the image resized by `scale`, with the radius scaled alike so
+    /// that it looks as it will at full size.
+    pub fn apply_scaled(self, img: &DynamicImage, scale: f32) -> DynamicImage {
+        match self {
+            Filter::Blur => img.blur(BLUR_SIGMA * scale),
+            Filter::Sharpen => img.unsharpen(SHARPEN_SIGMA * scale, SHARPEN_THRESHOLD),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Blur => "blur",
+            Filter::Sharpen => "sharpen",
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::scaled;
+use windows::Win32::{
+    Foundation::{HWND, POINT},
+    System::SystemServices::{
+        GC_PAN, GC_PAN_WITH_SINGLE_FINGER_HORIZONTALLY, GC_PAN_WITH_SINGLE_FINGER_VERTICALLY,
+        GC_TWOFINGERTAP, GC_ZOOM,
+    },
+    UI::{
+        Input::Touch::{
+            SetGestureConfig, GESTURECONFIG, GESTUREINFO, GID_PAN, GID_TWOFINGERTAP, GID_ZOOM,
+        },
+        WindowsAndMessaging::{GF_BEGIN, GF_END},
+    },
+};
+
+/// How far, in pixels at 96 DPI, a finger must travel sideways in fit mode to change the file.
+const SWIPE_DISTANCE: i32 = 80;
+
+/// What a touch gesture asks the viewer to do.
+pub enum Action {
+    /// Multiply the scale by `factor`, keeping `center` in place.
+    Zoom {
+        factor: f32,
+        center: POINT,
+    },
+    Pan {
+        dx: i32,
+        dy: i32,
+    },
+    /// Show the next file, or the previous one when false.
+    Swipe {
+        next: bool,
+    },
+    /// Toggle between fit and 100%, about `center`.
+    ToggleActualSize {
+        center: POINT,
+    },
+    None,
+}
+
+/// Asks for pinch, single-finger pan in both directions and two-finger tap, instead of the
+/// default set which has no single-finger horizontal pan.
+pub fn configure(h_wnd: HWND) {
+    let config = [
+        GESTURECONFIG {
+            dwID: GID_ZOOM,
+            dwWant: GC_ZOOM.0,
+            dwBlock: 0,
+        },
+        GESTURECONFIG {
+            dwID: GID_PAN,
+            dwWant: GC_PAN.0
+                | GC_PAN_WITH_SINGLE_FINGER_VERTICALLY.0
+                | GC_PAN_WITH_SINGLE_FINGER_HORIZONTALLY.0,
+            dwBlock: 0,
+        },
+        GESTURECONFIG {
+            dwID: GID_TWOFINGERTAP,
+            dwWant: GC_TWOFINGERTAP.0,
+            dwBlock: 0,
+        },
+    ];
+    unsafe {
+        SetGestureConfig(
+            h_wnd,
+            0,
+            &config,
+            std::mem::size_of::<GESTURECONFIG>() as u32,
+        )
+    };
+}
+
+/// Turns the stream of `WM_GESTURE` messages into [`Action`]s.
+#[derive(Default)]
+pub struct Gestures {
+    distance: u32,
+    last: POINT,
+    start: POINT,
+    /// Set when a pan started while the whole image was visible.
+    swiping: bool,
+}
+
+impl Gestures {
+    /// `pt` is the gesture location in client coordinates. `pannable` tells whether the image
+    /// is larger than the viewport, in which case a pan scrolls instead of swiping.
+    pub fn interpret(&mut self, info: &GESTUREINFO, pt: POINT, pannable: bool) -> Action {
+        let begin = info.dwFlags & GF_BEGIN != 0;
+        let end = info.dwFlags & GF_END != 0;
+        match info.dwID {
+            id if id == GID_ZOOM.0 => {
+                // the distance between the two fingers.
+                let distance = info.ullArguments as u32;
+                let previous = std::mem::replace(&mut self.distance, distance);
+                if begin || previous == 0 || distance == 0 {
+                    return Action::None;
+                }
+                Action::Zoom {
+                    factor: distance as f32 / previous as f32,
+                    center: pt,
+                }
+            }
+            id if id == GID_PAN.0 => {
+                let last = std::mem::replace(&mut self.last, pt);
+                if begin {
+                    self.start = pt;
+                    self.swiping = !pannable;
+                    return Action::None;
+                }
+                if !self.swiping {
+                    return Action::Pan {
+                        dx: pt.x - last.x,
+                        dy: pt.y - last.y,
+                    };
+                }
+                let dx = pt.x - self.start.x;
+                let dy = pt.y - self.start.y;
+                if end && dx.abs() >= scaled(SWIPE_DISTANCE) && dx.abs() > dy.abs() {
+                    // the content follows the finger, so swiping left brings in the next file.
+                    Action::Swipe { next: dx < 0 }
+                } else {
+                    Action::None
+                }
+            }
+            id if id == GID_TWOFINGERTAP.0 => Action::ToggleActualSize { center: pt },
+            _ => Action::None,
+        }
+    }
+}
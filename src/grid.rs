@@ -0,0 +1,104 @@
+use windows::Win32::{
+    Foundation::{COLORREF, RECT},
+    Graphics::Gdi::{
+        CreateCompatibleBitmap, CreateCompatibleDC, CreatedHDC, DeleteDC, DeleteObject,
+        GdiAlphaBlend, SelectObject, SetPixel, AC_SRC_OVER, BLENDFUNCTION, HBITMAP, HDC, HGDIOBJ,
+    },
+};
+
+/// The grid shows once an image pixel covers at least this many screen pixels each way.
+pub const MIN_CELL: f64 = 8.0;
+
+/// Lines on every pixel boundary: black at 40%.
+const GRID_COLOR: u32 = 0x0000_0000;
+const GRID_ALPHA: u8 = 102;
+/// Lines every `guide` pixels: blue at 80%, as 0x00bbggrr.
+const GUIDE_COLOR: u32 = 0x00d7_7800;
+const GUIDE_ALPHA: u8 = 204;
+
+/// Draws lines along the pixel boundaries of an image of `size` drawn at `rect`, where they
+/// cross `viewport`, when its pixels are large enough. With a `guide`, every `guide`th line
+/// from the top left stands out.
+pub fn draw(hdc: HDC, viewport: &RECT, rect: &RECT, size: (u32, u32), guide: Option<u32>) {
+    let scale_x = (rect.right - rect.left) as f64 / size.0.max(1) as f64;
+    let scale_y = (rect.bottom - rect.top) as f64 / size.1.max(1) as f64;
+    if scale_x < MIN_CELL || scale_y < MIN_CELL {
+        return;
+    }
+    let clip = RECT {
+        left: rect.left.max(viewport.left),
+        top: rect.top.max(viewport.top),
+        right: rect.right.min(viewport.right),
+        bottom: rect.bottom.min(viewport.bottom),
+    };
+    if clip.right <= clip.left || clip.bottom <= clip.top {
+        return;
+    }
+    // the indices and client positions of the pixel boundaries from `from` to `to`.
+    let boundaries = |from: i32, to: i32, origin: i32, scale: f64, len: u32| {
+        let first = ((from - origin) as f64 / scale).floor().max(0.0) as u32;
+        let last = (((to - origin) as f64 / scale).ceil().max(0.0) as u32).min(len);
+        (first..=last).map(move |i| (i, origin + (i as f64 * scale).round() as i32))
+    };
+    let is_guide = |i: u32| guide.is_some_and(|n| i.is_multiple_of(n));
+
+    let grid = Pen::new(hdc, GRID_COLOR, GRID_ALPHA);
+    let guides = Pen::new(hdc, GUIDE_COLOR, GUIDE_ALPHA);
+    for (i, x) in boundaries(clip.left, clip.right, rect.left, scale_x, size.0) {
+        if (clip.left..clip.right).contains(&x) {
+            let pen = if is_guide(i) { &guides } else { &grid };
+            pen.fill(hdc, x, clip.top, 1, clip.bottom - clip.top);
+        }
+    }
+    for (i, y) in boundaries(clip.top, clip.bottom, rect.top, scale_y, size.1) {
+        if (clip.top..clip.bottom).contains(&y) {
+            let pen = if is_guide(i) { &guides } else { &grid };
+            pen.fill(hdc, clip.left, y, clip.right - clip.left, 1);
+        }
+    }
+}
+
+/// A translucent color, as one pixel stretched over each rectangle it fills.
+struct Pen {
+    h_mdc: CreatedHDC,
+    h_bmp: HBITMAP,
+    old: HGDIOBJ,
+    alpha: u8,
+}
+
+impl Pen {
+    fn new(hdc: HDC, color: u32, alpha: u8) -> Pen {
+        unsafe {
+            let h_mdc = CreateCompatibleDC(hdc);
+            let h_bmp = CreateCompatibleBitmap(hdc, 1, 1);
+            let old = SelectObject(h_mdc, h_bmp);
+            SetPixel(h_mdc, 0, 0, COLORREF(color));
+            Pen {
+                h_mdc,
+                h_bmp,
+                old,
+                alpha,
+            }
+        }
+    }
+
+    fn fill(&self, hdc: HDC, x: i32, y: i32, width: i32, height: i32) {
+        let blend = BLENDFUNCTION {
+            BlendOp: AC_SRC_OVER as u8,
+            BlendFlags: 0,
+            SourceConstantAlpha: self.alpha,
+            AlphaFormat: 0,
+        };
+        unsafe { GdiAlphaBlend(hdc, x, y, width, height, self.h_mdc, 0, 0, 1, 1, blend) };
+    }
+}
+
+impl Drop for Pen {
+    fn drop(&mut self) {
+        unsafe {
+            SelectObject(self.h_mdc, self.old);
+            DeleteObject(self.h_bmp);
+            DeleteDC(self.h_mdc);
+        }
+    }
+}
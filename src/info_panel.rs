@@ -0,0 +1,51 @@
+use crate::theme;
+use windows::Win32::{
+    Foundation::{COLORREF, POINT, RECT},
+    Graphics::Gdi::{
+        DrawTextW, FillRect, OffsetRect, SelectObject, SetBkMode, SetTextColor, DT_CALCRECT,
+        DT_NOPREFIX, HDC, HFONT, TRANSPARENT,
+    },
+};
+
+/// Draws `lines` in a box at the top left of `viewport`, `padding` pixels from the edges.
+pub fn draw(hdc: HDC, viewport: &RECT, lines: &[String], font: HFONT, padding: i32) {
+    let at = POINT {
+        x: viewport.left + 2 * padding,
+        y: viewport.top + 2 * padding,
+    };
+    draw_at(hdc, at, viewport, lines, font, padding);
+}
+
+/// Draws `lines` in a box with the text starting at `at`, moved as needed to stay inside
+/// `bounds`.
+pub fn draw_at(hdc: HDC, at: POINT, bounds: &RECT, lines: &[String], font: HFONT, padding: i32) {
+    let mut text: Vec<u16> = lines.join("\n").encode_utf16().collect();
+    let [r, g, b] = theme::background_rgb();
+    // black on light backgrounds, white on dark ones.
+    let luma = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+    let color = if luma > 128 { 0 } else { 0x00ff_ffff };
+    unsafe {
+        let old = SelectObject(hdc, font);
+        let mut rc = RECT {
+            left: at.x,
+            top: at.y,
+            right: bounds.right - 2 * padding,
+            bottom: bounds.bottom - 2 * padding,
+        };
+        DrawTextW(hdc, &mut text, &mut rc, DT_CALCRECT | DT_NOPREFIX);
+        let dx = (bounds.right - 2 * padding - rc.right).min(0);
+        let dy = (bounds.bottom - 2 * padding - rc.bottom).min(0);
+        OffsetRect(&mut rc, dx, dy);
+        let panel = RECT {
+            left: rc.left - padding,
+            top: rc.top - padding,
+            right: rc.right + padding,
+            bottom: rc.bottom + padding,
+        };
+        FillRect(hdc, &panel, theme::background());
+        SetBkMode(hdc, TRANSPARENT);
+        SetTextColor(hdc, COLORREF(color));
+        DrawTextW(hdc, &mut text, &mut rc, DT_NOPREFIX);
+        SelectObject(hdc, old);
+    }
+}
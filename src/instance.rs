@@ -0,0 +1,63 @@
+use crate::{long_path, CLASS_NAME};
+use std::ffi::{c_void, OsString};
+use std::os::windows::ffi::OsStringExt;
+use std::path::{self, PathBuf};
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{LPARAM, WPARAM},
+        System::DataExchange::COPYDATASTRUCT,
+        UI::WindowsAndMessaging::{FindWindowW, SendMessageW, SetForegroundWindow, WM_COPYDATA},
+    },
+};
+
+/// `dwData` of a WM_COPYDATA message carrying a file list from another pinion process.
+pub const COPYDATA_OPEN_FILES: usize = 0x7069_6e6f;
+
+/// Hands `files` to an already running pinion window and brings it to the front.
+///
+/// Returns false when no other window exists or it did not take the files, in which case this
+/// process should open them itself.
+pub fn forward(files: &[PathBuf]) -> bool {
+    let h_wnd = unsafe { FindWindowW(CLASS_NAME, PCWSTR::null()) };
+    if h_wnd.0 == 0 {
+        return false;
+    }
+
+    // the other process has its own working directory, so relative paths must not leak out.
+    let data: Vec<u16> = files
+        .iter()
+        .flat_map(|file| long_path::to_wide(&path::absolute(file).unwrap_or(file.clone())))
+        .collect();
+    let cds = COPYDATASTRUCT {
+        dwData: COPYDATA_OPEN_FILES,
+        cbData: (data.len() * 2) as u32,
+        lpData: data.as_ptr() as *mut c_void,
+    };
+    let accepted = unsafe {
+        // we were started by the user, so we may hand the foreground over.
+        SetForegroundWindow(h_wnd);
+        SendMessageW(
+            h_wnd,
+            WM_COPYDATA,
+            WPARAM::default(),
+            LPARAM(&cds as *const _ as isize),
+        )
+    };
+    accepted.0 != 0
+}
+
+/// Decodes the file list sent by [`forward`].
+pub fn received_files(cds: &COPYDATASTRUCT) -> Option<Vec<PathBuf>> {
+    if cds.dwData != COPYDATA_OPEN_FILES || cds.lpData.is_null() {
+        return None;
+    }
+    let data =
+        unsafe { std::slice::from_raw_parts(cds.lpData as *const u16, cds.cbData as usize / 2) };
+    let files = data
+        .split(|&c| c == 0)
+        .filter(|file| !file.is_empty())
+        .map(|file| PathBuf::from(OsString::from_wide(file)))
+        .collect();
+    Some(files)
+}
@@ -0,0 +1,975 @@
+use anyhow::{bail, ensure, Context, Result};
+
+/// The row-major index of each coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const SOF0: u8 = 0xc0;
+const SOF1: u8 = 0xc1;
+const DHT: u8 = 0xc4;
+const RST0: u8 = 0xd0;
+const RST7: u8 = 0xd7;
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+const DQT: u8 = 0xdb;
+const DRI: u8 = 0xdd;
+const APP0: u8 = 0xe0;
+const APP1: u8 = 0xe1;
+const APP15: u8 = 0xef;
+const COM: u8 = 0xfe;
+
+/// Bits of the fast Huffman lookup, longer codes are decoded one length at a time.
+const LOOKUP_BITS: u32 = 9;
+
+/// The EXIF orientation tag.
+const ORIENTATION: u16 = 0x0112;
+
+struct Component {
+    id: u8,
+    h: u8,
+    v: u8,
+    /// Quantization table.
+    tq: u8,
+    /// Huffman tables, from the scan that coded the component.
+    td: u8,
+    ta: u8,
+    blocks_w: usize,
+    blocks_h: usize,
+    /// Quantized DCT coefficients of each block, row-major.
+    blocks: Vec<[i16; 64]>,
+}
+
+/// A baseline JPEG decoded as far as its DCT coefficients.
+struct Jpeg {
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    /// APPn, COM and DQT segments in file order, without their length.
+    segments: Vec<(u8, Vec<u8>)>,
+    /// How many of `segments` come before the frame header.
+    frame_at: usize,
+}
+
+struct Huffman {
+    /// Length and symbol for each `LOOKUP_BITS` prefix, length 0 for longer codes.
+    lookup: Vec<(u8, u8)>,
+    maxcode: [i32; 17],
+    mincode: [i32; 17],
+    valptr: [usize; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8], values: &[u8]) -> Result<Huffman> {
+        let mut table = Huffman {
+            lookup: vec![(0, 0); 1 << LOOKUP_BITS],
+            maxcode: [-1; 17],
+            mincode: [0; 17],
+            valptr: [0; 17],
+            values: values.to_vec(),
+        };
+        let mut code = 0i32;
+        let mut k = 0;
+        for len in 1..=16 {
+            let count = counts[len - 1] as usize;
+            table.valptr[len] = k;
+            table.mincode[len] = code;
+            for i in 0..count {
+                let symbol = *values.get(k + i).context("bad Huffman table")?;
+                let c = code + i as i32;
+                ensure!(c < 1 << len, "bad Huffman table");
+                if len as u32 <= LOOKUP_BITS {
+                    let shift = LOOKUP_BITS - len as u32;
+                    for j in 0..1 << shift {
+                        table.lookup[((c as usize) << shift) | j] = (len as u8, symbol);
+                    }
+                }
+            }
+            code += count as i32;
+            k += count;
+            if count > 0 {
+                table.maxcode[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        Ok(table)
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8> {
+        let (len, symbol) = self.lookup[reader.peek(LOOKUP_BITS) as usize];
+        if len > 0 {
+            reader.consume(len as u32);
+            return Ok(symbol);
+        }
+        for len in LOOKUP_BITS as usize + 1..=16 {
+            let code = reader.peek(len as u32) as i32;
+            if code <= self.maxcode[len] {
+                reader.consume(len as u32);
+                return Ok(self.values[self.valptr[len] + (code - self.mincode[len]) as usize]);
+            }
+        }
+        bail!("bad Huffman code")
+    }
+}
+
+/// Reads entropy-coded data, removing the stuffed zero after 0xFF and stopping at markers.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitReader<'_> {
+    fn fill(&mut self) {
+        while self.bits <= 32 {
+            let byte = match self.data.get(self.pos) {
+                Some(0xff) if self.data.get(self.pos + 1) == Some(&0) => {
+                    self.pos += 2;
+                    0xff
+                }
+                // a marker, or the end of the data: pad with zeros.
+                Some(0xff) | None => 0,
+                Some(&byte) => {
+                    self.pos += 1;
+                    byte
+                }
+            };
+            self.acc = self.acc << 8 | byte as u64;
+            self.bits += 8;
+        }
+    }
+
+    fn peek(&mut self, n: u32) -> u32 {
+        self.fill();
+        ((self.acc >> (self.bits - n)) & ((1 << n) - 1)) as u32
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.bits -= n;
+    }
+
+    /// Reads `n` bits as a signed value, as coded after a magnitude category.
+    fn receive_extend(&mut self, n: u32) -> i32 {
+        if n == 0 {
+            return 0;
+        }
+        let v = self.peek(n) as i32;
+        self.consume(n);
+        if v < 1 << (n - 1) {
+            v - (1 << n) + 1
+        } else {
+            v
+        }
+    }
+
+    /// Skips to after the next restart marker.
+    fn restart(&mut self) -> Result<()> {
+        self.acc = 0;
+        self.bits = 0;
+        let marker = self.data[self.pos..]
+            .windows(2)
+            .position(|w| w[0] == 0xff && (RST0..=RST7).contains(&w[1]))
+            .context("missing restart marker")?;
+        self.pos += marker + 2;
+        Ok(())
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<usize> {
+    let bytes = data.get(pos..pos + 2).context("unexpected end of data")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+}
+
+impl Jpeg {
+    /// Reads the segments and, unless `headers_only`, the coefficients of every scan.
+    fn parse(data: &[u8], headers_only: bool) -> Result<Jpeg> {
+        ensure!(data.starts_with(&[0xff, SOI]), "not a JPEG file");
+        let mut jpeg = Jpeg {
+            width: 0,
+            height: 0,
+            components: Vec::new(),
+            segments: Vec::new(),
+            frame_at: 0,
+        };
+        let mut tables: [Option<Huffman>; 8] = Default::default();
+        let mut restart_interval = 0;
+        let mut pos = 2;
+        loop {
+            ensure!(data.get(pos) == Some(&0xff), "bad marker");
+            while data.get(pos) == Some(&0xff) {
+                pos += 1;
+            }
+            let marker = *data.get(pos).context("unexpected end of data")?;
+            pos += 1;
+            if marker == EOI {
+                break;
+            }
+            let len = read_u16(data, pos)?;
+            let payload = data.get(pos + 2..pos + len).context("truncated segment")?;
+            pos += len;
+            match marker {
+                SOF0 | SOF1 => {
+                    jpeg.frame_at = jpeg.segments.len();
+                    jpeg.read_frame(payload)?;
+                }
+                DHT => read_huffman(payload, &mut tables)?,
+                DRI => restart_interval = read_u16(payload, 0)?,
+                SOS => {
+                    ensure!(!jpeg.components.is_empty(), "scan before the frame header");
+                    if headers_only {
+                        break;
+                    }
+                    pos = jpeg.read_scan(payload, data, pos, &tables, restart_interval)?;
+                }
+                DQT | APP0..=APP15 | COM => jpeg.segments.push((marker, payload.to_vec())),
+                0xc2..=0xcf => bail!("only baseline JPEG files can be transformed losslessly"),
+                _ => {}
+            }
+        }
+        ensure!(!jpeg.components.is_empty(), "no frame header");
+        Ok(jpeg)
+    }
+
+    fn max_sampling(&self) -> (usize, usize) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (h as usize, v as usize)
+    }
+
+    /// The number of MCUs across and down an interleaved scan.
+    fn mcus(&self) -> (usize, usize) {
+        let (h, v) = self.max_sampling();
+        (self.width / (8 * h), self.height / (8 * v))
+    }
+
+    fn read_frame(&mut self, payload: &[u8]) -> Result<()> {
+        ensure!(
+            payload.first() == Some(&8),
+            "only 8-bit JPEG files are supported"
+        );
+        self.height = read_u16(payload, 1)?;
+        self.width = read_u16(payload, 3)?;
+        ensure!(self.width > 0 && self.height > 0, "bad image size");
+        let count = *payload.get(5).context("truncated frame header")? as usize;
+        for i in 0..count {
+            let c = payload
+                .get(6 + 3 * i..9 + 3 * i)
+                .context("truncated frame header")?;
+            let (h, v) = (c[1] >> 4, c[1] & 15);
+            ensure!((1..=4).contains(&h) && (1..=4).contains(&v), "bad sampling");
+            self.components.push(Component {
+                id: c[0],
+                h,
+                v,
+                tq: c[2],
+                td: 0,
+                ta: 0,
+                blocks_w: 0,
+                blocks_h: 0,
+                blocks: Vec::new(),
+            });
+        }
+        // trimming partial MCUs at the right and bottom edges, as jpegtran can, would crop.
+        let (h, v) = self.max_sampling();
+        ensure!(
+            self.width.is_multiple_of(8 * h) && self.height.is_multiple_of(8 * v),
+            "the image size is not a multiple of the {}x{} MCU size",
+            8 * h,
+            8 * v
+        );
+        for c in &mut self.components {
+            c.blocks_w = self.width * c.h as usize / h / 8;
+            c.blocks_h = self.height * c.v as usize / v / 8;
+            c.blocks = vec![[0; 64]; c.blocks_w * c.blocks_h];
+        }
+        Ok(())
+    }
+
+    /// Decodes the scan whose entropy-coded data starts at `pos` and returns where it ends.
+    fn read_scan(
+        &mut self,
+        payload: &[u8],
+        data: &[u8],
+        pos: usize,
+        tables: &[Option<Huffman>; 8],
+        restart_interval: usize,
+    ) -> Result<usize> {
+        let count = *payload.first().context("truncated scan header")? as usize;
+        let mut scan = Vec::new();
+        for i in 0..count {
+            let s = payload
+                .get(1 + 2 * i..3 + 2 * i)
+                .context("truncated scan header")?;
+            let index = self
+                .components
+                .iter()
+                .position(|c| c.id == s[0])
+                .context("unknown component in scan")?;
+            let c = &mut self.components[index];
+            (c.td, c.ta) = (s[1] >> 4, s[1] & 15);
+            ensure!(c.td < 4 && c.ta < 4, "bad Huffman table selector");
+            scan.push(index);
+        }
+        let spectral = payload
+            .get(1 + 2 * count..4 + 2 * count)
+            .context("truncated scan header")?;
+        ensure!(spectral == [0, 63, 0], "not a sequential scan");
+
+        let mut reader = BitReader {
+            data,
+            pos,
+            acc: 0,
+            bits: 0,
+        };
+        let mut preds = vec![0i32; scan.len()];
+        let mut mcu = 0;
+        let mut decode = |reader: &mut BitReader,
+                          comps: &mut [Component],
+                          blocks: &[(usize, usize)]|
+         -> Result<()> {
+            if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+                reader.restart()?;
+                preds.iter_mut().for_each(|p| *p = 0);
+            }
+            mcu += 1;
+            for &(i, block) in blocks {
+                let c = &mut comps[scan[i]];
+                let dc = tables[c.td as usize]
+                    .as_ref()
+                    .context("missing Huffman table")?;
+                let ac = tables[4 + c.ta as usize]
+                    .as_ref()
+                    .context("missing Huffman table")?;
+                let coefficients = &mut c.blocks[block];
+                let size = dc.decode(reader)? as u32;
+                preds[i] += reader.receive_extend(size);
+                coefficients[0] = preds[i] as i16;
+                let mut k = 1;
+                while k < 64 {
+                    let rs = ac.decode(reader)?;
+                    let (run, size) = ((rs >> 4) as usize, (rs & 15) as u32);
+                    if size == 0 {
+                        if run != 15 {
+                            break;
+                        }
+                        k += 16;
+                        continue;
+                    }
+                    k += run;
+                    ensure!(k < 64, "bad coefficient run");
+                    coefficients[ZIGZAG[k]] = reader.receive_extend(size) as i16;
+                    k += 1;
+                }
+            }
+            Ok(())
+        };
+
+        if scan.len() == 1 {
+            let c = &self.components[scan[0]];
+            for block in 0..c.blocks_w * c.blocks_h {
+                decode(&mut reader, &mut self.components, &[(0, block)])?;
+            }
+        } else {
+            let (mcus_x, mcus_y) = self.mcus();
+            let mut blocks = Vec::new();
+            for my in 0..mcus_y {
+                for mx in 0..mcus_x {
+                    blocks.clear();
+                    for (i, &index) in scan.iter().enumerate() {
+                        let c = &self.components[index];
+                        for v in 0..c.v as usize {
+                            for h in 0..c.h as usize {
+                                let x = mx * c.h as usize + h;
+                                let y = my * c.v as usize + v;
+                                blocks.push((i, y * c.blocks_w + x));
+                            }
+                        }
+                    }
+                    decode(&mut reader, &mut self.components, &blocks)?;
+                }
+            }
+        }
+
+        // the next marker, skipping the padding and any bytes the reader didn't need.
+        let mut pos = reader.pos;
+        while pos + 1 < data.len()
+            && !(data[pos] == 0xff && data[pos + 1] != 0 && !(RST0..=RST7).contains(&data[pos + 1]))
+        {
+            pos += 1;
+        }
+        Ok(pos)
+    }
+
+    /// Rotates clockwise by `quarter_turns` in the DCT domain: blocks move like pixels, and the
+    /// coefficients inside each block are transposed and have their signs flipped.
+    fn rotate(&mut self, quarter_turns: u32) {
+        let quarter_turns = quarter_turns % 4;
+        if quarter_turns == 0 {
+            return;
+        }
+        let odd = quarter_turns % 2 == 1;
+        for c in &mut self.components {
+            let (w, h) = (c.blocks_w, c.blocks_h);
+            let (out_w, out_h) = if odd { (h, w) } else { (w, h) };
+            let mut blocks = vec![[0i16; 64]; c.blocks.len()];
+            for y in 0..out_h {
+                for x in 0..out_w {
+                    let (sx, sy) = match quarter_turns {
+                        1 => (y, h - 1 - x),
+                        2 => (w - 1 - x, h - 1 - y),
+                        _ => (w - 1 - y, x),
+                    };
+                    let src = &c.blocks[sy * w + sx];
+                    let dst = &mut blocks[y * out_w + x];
+                    for r in 0..8 {
+                        for col in 0..8 {
+                            // odd frequencies change sign when mirrored along their axis.
+                            let (value, negate) = match quarter_turns {
+                                1 => (src[col * 8 + r], col % 2 == 1),
+                                2 => (src[r * 8 + col], (r + col) % 2 == 1),
+                                _ => (src[col * 8 + r], r % 2 == 1),
+                            };
+                            dst[r * 8 + col] = if negate { value.wrapping_neg() } else { value };
+                        }
+                    }
+                }
+            }
+            c.blocks = blocks;
+            (c.blocks_w, c.blocks_h) = (out_w, out_h);
+            if odd {
+                (c.h, c.v) = (c.v, c.h);
+            }
+        }
+        if odd {
+            (self.width, self.height) = (self.height, self.width);
+            for (marker, payload) in &mut self.segments {
+                if *marker == DQT {
+                    transpose_quantization(payload);
+                }
+            }
+        }
+    }
+
+    /// Checks that `encode` can write the components as one scan.
+    fn check_encodable(&self) -> Result<()> {
+        ensure!(
+            self.components.len() == 1
+                || self
+                    .components
+                    .iter()
+                    .map(|c| c.h as usize * c.v as usize)
+                    .sum::<usize>()
+                    <= 10,
+            "too many blocks per MCU"
+        );
+        Ok(())
+    }
+
+    /// Writes the file with a single scan and Huffman tables optimized for it.
+    fn encode(&self) -> Result<Vec<u8>> {
+        self.check_encodable()?;
+        let interleaved = self.components.len() > 1;
+
+        let mut frequencies = [[0u32; 257]; 8];
+        self.encode_scan(interleaved, &mut |table, symbol, _, _| {
+            frequencies[table][symbol as usize] += 1;
+        });
+        let mut codes: [Option<([u16; 256], [u8; 256])>; 8] = [None; 8];
+        let mut dht = Vec::new();
+        for (table, frequencies) in frequencies.iter().enumerate() {
+            if frequencies.iter().all(|&f| f == 0) {
+                continue;
+            }
+            let (counts, values) = optimal_table(frequencies);
+            dht.push((((table / 4) << 4) | (table % 4)) as u8);
+            dht.extend_from_slice(&counts);
+            dht.extend_from_slice(&values);
+            codes[table] = Some(canonical_codes(&counts, &values));
+        }
+
+        let mut out = vec![0xff, SOI];
+        let write_segments = |out: &mut Vec<u8>, segments: &[(u8, Vec<u8>)]| {
+            for (marker, payload) in segments {
+                if *marker == APP1 && payload.starts_with(b"Exif\0\0") {
+                    let mut payload = payload.clone();
+                    reset_orientation(&mut payload[6..]);
+                    write_segment(out, *marker, &payload);
+                } else {
+                    write_segment(out, *marker, payload);
+                }
+            }
+        };
+        // the frame header stays where it was among the other segments.
+        let (before, after) = self.segments.split_at(self.frame_at);
+        write_segments(&mut out, before);
+        let mut sof = vec![8];
+        sof.extend_from_slice(&(self.height as u16).to_be_bytes());
+        sof.extend_from_slice(&(self.width as u16).to_be_bytes());
+        sof.push(self.components.len() as u8);
+        for c in &self.components {
+            sof.extend_from_slice(&[c.id, c.h << 4 | c.v, c.tq]);
+        }
+        write_segment(&mut out, SOF0, &sof);
+        write_segments(&mut out, after);
+        write_segment(&mut out, DHT, &dht);
+        let mut sos = vec![self.components.len() as u8];
+        for c in &self.components {
+            sos.extend_from_slice(&[c.id, c.td << 4 | c.ta]);
+        }
+        sos.extend_from_slice(&[0, 63, 0]);
+        write_segment(&mut out, SOS, &sos);
+
+        let mut writer = BitWriter {
+            out: &mut out,
+            acc: 0,
+            bits: 0,
+        };
+        self.encode_scan(interleaved, &mut |table, symbol, extra, size| {
+            if let Some((code, code_size)) = &codes[table] {
+                writer.put(
+                    code[symbol as usize] as u32,
+                    code_size[symbol as usize] as u32,
+                );
+                writer.put(extra, size);
+            }
+        });
+        writer.flush();
+        out.extend_from_slice(&[0xff, EOI]);
+        Ok(out)
+    }
+
+    /// Walks the blocks in scan order, calling `emit(table, symbol, extra_bits, extra_size)`
+    /// for each Huffman symbol. Tables are numbered DC 0 to 3, then AC 4 to 7.
+    fn encode_scan(&self, interleaved: bool, emit: &mut dyn FnMut(usize, u8, u32, u32)) {
+        let mut preds = vec![0i32; self.components.len()];
+        let mut block = |i: usize, index: usize| {
+            let c = &self.components[i];
+            encode_block(
+                &c.blocks[index],
+                &mut preds[i],
+                c.td as usize,
+                4 + c.ta as usize,
+                emit,
+            );
+        };
+        if !interleaved {
+            let c = &self.components[0];
+            for index in 0..c.blocks_w * c.blocks_h {
+                block(0, index);
+            }
+            return;
+        }
+        let (mcus_x, mcus_y) = self.mcus();
+        for my in 0..mcus_y {
+            for mx in 0..mcus_x {
+                for (i, c) in self.components.iter().enumerate() {
+                    for v in 0..c.v as usize {
+                        for h in 0..c.h as usize {
+                            let x = mx * c.h as usize + h;
+                            let y = my * c.v as usize + v;
+                            block(i, y * c.blocks_w + x);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn read_huffman(payload: &[u8], tables: &mut [Option<Huffman>; 8]) -> Result<()> {
+    let mut pos = 0;
+    while pos < payload.len() {
+        let class_id = payload[pos];
+        let (class, id) = ((class_id >> 4) as usize, (class_id & 15) as usize);
+        ensure!(class < 2 && id < 4, "bad Huffman table id");
+        let counts = payload
+            .get(pos + 1..pos + 17)
+            .context("truncated Huffman table")?;
+        let total = counts.iter().map(|&c| c as usize).sum::<usize>();
+        let values = payload
+            .get(pos + 17..pos + 17 + total)
+            .context("truncated Huffman table")?;
+        tables[class * 4 + id] = Some(Huffman::new(counts, values)?);
+        pos += 17 + total;
+    }
+    Ok(())
+}
+
+/// The magnitude category of `v` and the bits coded after it.
+fn magnitude(v: i32) -> (u8, u32) {
+    if v == 0 {
+        return (0, 0);
+    }
+    let size = 32 - v.unsigned_abs().leading_zeros();
+    let bits = (if v < 0 { v - 1 } else { v }) as u32 & ((1 << size) - 1);
+    (size as u8, bits)
+}
+
+fn encode_block(
+    block: &[i16; 64],
+    pred: &mut i32,
+    dc: usize,
+    ac: usize,
+    emit: &mut dyn FnMut(usize, u8, u32, u32),
+) {
+    let (size, bits) = magnitude(block[0] as i32 - *pred);
+    *pred = block[0] as i32;
+    emit(dc, size, bits, size as u32);
+    let mut run = 0;
+    for &index in &ZIGZAG[1..] {
+        let v = block[index];
+        if v == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            emit(ac, 0xf0, 0, 0);
+            run -= 16;
+        }
+        let (size, bits) = magnitude(v as i32);
+        emit(ac, run << 4 | size, bits, size as u32);
+        run = 0;
+    }
+    if run > 0 {
+        emit(ac, 0x00, 0, 0);
+    }
+}
+
+/// Code lengths limited to 16 bits for the symbol `frequencies`, as in JPEG Annex K.2. Returns
+/// the count of codes of each length and the symbols ordered by length.
+fn optimal_table(frequencies: &[u32; 257]) -> ([u8; 16], Vec<u8>) {
+    let mut freq: Vec<u64> = frequencies.iter().map(|&f| f as u64).collect();
+    // a reserved symbol keeps any real code from being all ones.
+    freq[256] = 1;
+    let mut code_size = [0usize; 257];
+    let mut others = [usize::MAX; 257];
+    loop {
+        let least = |skip: Option<usize>, freq: &[u64]| {
+            (0..257)
+                .filter(|&i| freq[i] > 0 && Some(i) != skip)
+                .min_by_key(|&i| (freq[i], usize::MAX - i))
+        };
+        let Some(mut v1) = least(None, &freq) else {
+            break;
+        };
+        let Some(mut v2) = least(Some(v1), &freq) else {
+            break;
+        };
+        freq[v1] += freq[v2];
+        freq[v2] = 0;
+        code_size[v1] += 1;
+        while others[v1] != usize::MAX {
+            v1 = others[v1];
+            code_size[v1] += 1;
+        }
+        others[v1] = v2;
+        code_size[v2] += 1;
+        while others[v2] != usize::MAX {
+            v2 = others[v2];
+            code_size[v2] += 1;
+        }
+    }
+
+    let mut bits = [0usize; 33];
+    for &size in &code_size {
+        if size > 0 {
+            bits[size.min(32)] += 1;
+        }
+    }
+    let mut i = 32;
+    while i > 16 {
+        while bits[i] > 0 {
+            let mut j = i - 2;
+            while bits[j] == 0 {
+                j -= 1;
+            }
+            bits[i] -= 2;
+            bits[i - 1] += 1;
+            bits[j + 1] += 2;
+            bits[j] -= 1;
+        }
+        i -= 1;
+    }
+    while bits[i] == 0 {
+        i -= 1;
+    }
+    // drop the reserved symbol, it has the longest code.
+    bits[i] -= 1;
+
+    let mut counts = [0u8; 16];
+    for (count, &b) in counts.iter_mut().zip(&bits[1..=16]) {
+        *count = b as u8;
+    }
+    let mut values = Vec::new();
+    for size in 1..=32 {
+        for (symbol, _) in code_size[..256]
+            .iter()
+            .enumerate()
+            .filter(|(_, &s)| s == size)
+        {
+            values.push(symbol as u8);
+        }
+    }
+    values.truncate(counts.iter().map(|&c| c as usize).sum());
+    (counts, values)
+}
+
+/// The code and its length for each symbol of a table given as in a DHT segment.
+fn canonical_codes(counts: &[u8; 16], values: &[u8]) -> ([u16; 256], [u8; 256]) {
+    let mut code = [0u16; 256];
+    let mut size = [0u8; 256];
+    let mut next = 0u16;
+    let mut k = 0;
+    for len in 1..=16 {
+        for _ in 0..counts[len - 1] {
+            code[values[k] as usize] = next;
+            size[values[k] as usize] = len as u8;
+            next += 1;
+            k += 1;
+        }
+        next <<= 1;
+    }
+    (code, size)
+}
+
+struct BitWriter<'a> {
+    out: &'a mut Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter<'_> {
+    fn put(&mut self, value: u32, size: u32) {
+        self.acc = self.acc << size | value as u64;
+        self.bits += size;
+        while self.bits >= 8 {
+            let byte = (self.acc >> (self.bits - 8)) as u8;
+            self.out.push(byte);
+            if byte == 0xff {
+                self.out.push(0);
+            }
+            self.bits -= 8;
+        }
+    }
+
+    /// Pads the last byte with ones.
+    fn flush(&mut self) {
+        if self.bits > 0 {
+            let pad = 8 - self.bits;
+            self.put((1 << pad) - 1, pad);
+        }
+    }
+}
+
+fn write_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    out.extend_from_slice(&[0xff, marker]);
+    out.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// Transposes the tables of a DQT segment to match transposed coefficients.
+fn transpose_quantization(payload: &mut [u8]) {
+    let mut pos = 0;
+    while pos < payload.len() {
+        let width = if payload[pos] >> 4 == 0 { 1 } else { 2 };
+        let Some(table) = payload.get_mut(pos + 1..pos + 1 + 64 * width) else {
+            return;
+        };
+        let mut natural = [[0u8; 2]; 64];
+        for (k, &index) in ZIGZAG.iter().enumerate() {
+            natural[index][..width].copy_from_slice(&table[k * width..(k + 1) * width]);
+        }
+        for (k, &index) in ZIGZAG.iter().enumerate() {
+            let transposed = (index % 8) * 8 + index / 8;
+            table[k * width..(k + 1) * width].copy_from_slice(&natural[transposed][..width]);
+        }
+        pos += 1 + 64 * width;
+    }
+}
+
+/// Sets the orientation tag of the EXIF data `tiff` to 1, the pixels are now upright.
+fn reset_orientation(tiff: &mut [u8]) {
+    let little = tiff.starts_with(b"II");
+    let u16_at = |tiff: &[u8], pos: usize| {
+        let b = tiff.get(pos..pos + 2)?;
+        Some(if little {
+            u16::from_le_bytes([b[0], b[1]])
+        } else {
+            u16::from_be_bytes([b[0], b[1]])
+        })
+    };
+    let Some(ifd) = tiff.get(4..8).map(|b| {
+        if little {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        }
+    }) else {
+        return;
+    };
+    let ifd = ifd as usize;
+    let Some(count) = u16_at(tiff, ifd) else {
+        return;
+    };
+    for i in 0..count as usize {
+        let entry = ifd + 2 + 12 * i;
+        if u16_at(tiff, entry) == Some(ORIENTATION) && u16_at(tiff, entry + 2) == Some(3) {
+            let one = if little {
+                1u16.to_le_bytes()
+            } else {
+                1u16.to_be_bytes()
+            };
+            if let Some(value) = tiff.get_mut(entry + 8..entry + 10) {
+                value.copy_from_slice(&one);
+            }
+            return;
+        }
+    }
+}
+
+/// Checks that [`rotate`] can handle `data`: an intact 8-bit baseline JPEG whose size is a
+/// multiple of the MCU. The error tells why not.
+pub fn check_rotatable(data: &[u8]) -> Result<()> {
+    Jpeg::parse(data, false)?.check_encodable()
+}
+
+/// Rotates the JPEG file `data` clockwise by `quarter_turns` without decoding the pixels, so
+/// there is no generation loss. Metadata and ICC profiles are kept, with the EXIF orientation
+/// reset to upright.
+pub fn rotate(data: &[u8], quarter_turns: u32) -> Result<Vec<u8>> {
+    let mut jpeg = Jpeg::parse(data, false)?;
+    jpeg.rotate(quarter_turns);
+    jpeg.encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{DynamicImage, GenericImageView, RgbImage};
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 7) as u8, (y * 3) as u8, (x * y) as u8])
+        });
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, 85)
+            .encode_image(&DynamicImage::ImageRgb8(img))
+            .unwrap();
+        data
+    }
+
+    const APP2: u8 = 0xe2;
+
+    /// An APP1 EXIF payload in the byte order `little` or not, holding `orientation` and a
+    /// camera make.
+    fn exif(little: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |v: u16| {
+            if little {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let u32_bytes = |v: u32| {
+            if little {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend_from_slice(if little { b"II" } else { b"MM" });
+        payload.extend_from_slice(&u16_bytes(42));
+        payload.extend_from_slice(&u32_bytes(8));
+        payload.extend_from_slice(&u16_bytes(2));
+        // Make, ASCII, inline.
+        payload.extend_from_slice(&u16_bytes(0x010f));
+        payload.extend_from_slice(&u16_bytes(2));
+        payload.extend_from_slice(&u32_bytes(4));
+        payload.extend_from_slice(b"pin\0");
+        // Orientation, SHORT, padded to four bytes.
+        payload.extend_from_slice(&u16_bytes(ORIENTATION));
+        payload.extend_from_slice(&u16_bytes(3));
+        payload.extend_from_slice(&u32_bytes(1));
+        payload.extend_from_slice(&u16_bytes(orientation));
+        payload.extend_from_slice(&[0, 0]);
+        payload.extend_from_slice(&u32_bytes(0));
+        payload
+    }
+
+    fn icc() -> Vec<u8> {
+        let mut payload = b"ICC_PROFILE\0\x01\x01".to_vec();
+        payload.extend((0..200).map(|i| (i * 13) as u8));
+        payload
+    }
+
+    /// `data` with `segments` inserted after its APP0 segment.
+    fn with_segments(data: &[u8], segments: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        assert_eq!(data[3], APP0);
+        let end = 4 + u16::from_be_bytes([data[4], data[5]]) as usize;
+        let mut out = data[..end].to_vec();
+        for (marker, payload) in segments {
+            write_segment(&mut out, *marker, payload);
+        }
+        out.extend_from_slice(&data[end..]);
+        out
+    }
+
+    /// The markers of `data` in file order, with runs of DHT segments counted as one since
+    /// `encode` writes all tables in a single segment.
+    fn markers(data: &[u8]) -> Vec<u8> {
+        let mut markers = vec![data[1]];
+        let mut pos = 2;
+        loop {
+            let marker = data[pos + 1];
+            markers.push(marker);
+            if marker == SOS {
+                break;
+            }
+            pos += 2 + u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        }
+        markers.push(data[data.len() - 1]);
+        markers.dedup_by(|a, b| *a == DHT && *b == DHT);
+        markers
+    }
+
+    #[test]
+    fn four_turns_are_the_identity() {
+        for little in [false, true] {
+            let data = with_segments(&jpeg(64, 32), &[(APP1, exif(little, 6)), (APP2, icc())]);
+            check_rotatable(&data).unwrap();
+            let turned = rotate(&data, 1).unwrap();
+            let img = image::load_from_memory(&turned).unwrap();
+            assert_eq!(img.dimensions(), (32, 64));
+            let segments = Jpeg::parse(&turned, true).unwrap().segments;
+            let payload = |marker| {
+                segments
+                    .iter()
+                    .find(|(m, _)| *m == marker)
+                    .map(|(_, payload)| payload.clone())
+            };
+            assert_eq!(payload(APP2), Some(icc()));
+            assert_eq!(payload(APP1), Some(exif(little, 1)));
+
+            let turned = (1..4).fold(turned, |data, _| rotate(&data, 1).unwrap());
+            assert_eq!(markers(&turned), markers(&data));
+            let original = image::load_from_memory(&data).unwrap();
+            let turned = image::load_from_memory(&turned).unwrap();
+            assert_eq!(original.to_rgb8(), turned.to_rgb8());
+        }
+    }
+
+    #[test]
+    fn partial_mcus_are_refused() {
+        let data = jpeg(60, 32);
+        let reason = check_rotatable(&data).unwrap_err().to_string();
+        assert!(reason.contains("MCU size"), "{reason}");
+        assert!(rotate(&data, 1).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::long_path;
+use anyhow::Result;
+use std::env;
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use windows::{
+    core::{ComInterface, PCWSTR},
+    w,
+    Win32::{
+        Storage::EnhancedStorage::PKEY_Title,
+        System::Com::{
+            CoCreateInstance, StructuredStorage::PropVariantClear, StructuredStorage::PROPVARIANT,
+            CLSCTX_INPROC_SERVER, VT_LPWSTR,
+        },
+        UI::Shell::{
+            Common::{IObjectArray, IObjectCollection},
+            DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW,
+            PropertiesSystem::IPropertyStore,
+            SHStrDupW, SetCurrentProcessExplicitAppUserModelID, ShellLink,
+        },
+    },
+};
+
+/// Keeps the taskbar button, and with it the jump list, the same across builds and paths.
+const APP_ID: PCWSTR = w!("benki.pinion");
+
+/// Must be called before the first window is created.
+pub fn set_app_id() -> Result<()> {
+    unsafe { SetCurrentProcessExplicitAppUserModelID(APP_ID)? };
+    Ok(())
+}
+
+/// Rebuilds the "Recent" category of the taskbar jump list from `recent`, most recent first.
+pub fn refresh(recent: &[PathBuf]) -> Result<()> {
+    let list: ICustomDestinationList =
+        unsafe { CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)? };
+    unsafe { list.SetAppID(APP_ID)? };
+
+    let mut min_slots = 0;
+    let removed: IObjectArray = unsafe { list.BeginList(&mut min_slots)? };
+    let result = build(&list, recent, &removed, min_slots as usize);
+    if result.is_err() {
+        unsafe { list.AbortList().ok() };
+    }
+    result
+}
+
+fn build(
+    list: &ICustomDestinationList,
+    recent: &[PathBuf],
+    removed: &IObjectArray,
+    slots: usize,
+) -> Result<()> {
+    // re-adding an item the user removed from the list makes AppendCategory fail.
+    let removed = removed_arguments(removed)?;
+    let exe = env::current_exe()?;
+
+    let items: IObjectCollection =
+        unsafe { CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)? };
+    for (file, args) in recent
+        .iter()
+        .filter(|file| file.is_file())
+        .map(|file| (file, arguments(file)))
+        .filter(|(_, args)| !removed.contains(args))
+        .take(slots)
+    {
+        unsafe { items.AddObject(&shell_link(&exe, file, &args)?)? };
+    }
+
+    unsafe {
+        list.AppendCategory(w!("Recent"), &items.cast::<IObjectArray>()?)?;
+        list.CommitList()?;
+    }
+    Ok(())
+}
+
+fn arguments(file: &Path) -> OsString {
+    let mut args = OsString::from("\"");
+    args.push(long_path::display(file));
+    args.push("\"");
+    args
+}
+
+fn removed_arguments(removed: &IObjectArray) -> Result<Vec<OsString>> {
+    let mut result = Vec::new();
+    for i in 0..unsafe { removed.GetCount()? } {
+        let Ok(link) = (unsafe { removed.GetAt::<IShellLinkW>(i) }) else {
+            continue;
+        };
+        let mut buf = vec![0u16; 32768];
+        unsafe { link.GetArguments(&mut buf)? };
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        result.push(OsString::from_wide(&buf[..len]));
+    }
+    Ok(result)
+}
+
+fn shell_link(exe: &Path, file: &Path, args: &OsString) -> Result<IShellLinkW> {
+    let link: IShellLinkW = unsafe { CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)? };
+    let exe = long_path::to_wide(exe);
+    let args = long_path::to_wide(Path::new(args));
+    unsafe {
+        link.SetPath(PCWSTR::from_raw(exe.as_ptr()))?;
+        link.SetArguments(PCWSTR::from_raw(args.as_ptr()))?;
+        link.SetIconLocation(PCWSTR::from_raw(exe.as_ptr()), 0)?;
+    }
+
+    // custom categories show the title property, not the link's file name.
+    let title = file.file_name().unwrap_or(file.as_os_str());
+    let title = long_path::to_wide(Path::new(title));
+    let store: IPropertyStore = link.cast()?;
+    let mut value = PROPVARIANT::default();
+    unsafe {
+        let inner = &mut *value.Anonymous.Anonymous;
+        inner.vt = VT_LPWSTR;
+        inner.Anonymous.pwszVal = SHStrDupW(PCWSTR::from_raw(title.as_ptr()))?;
+        let result = store
+            .SetValue(&PKEY_Title, &value)
+            .and_then(|_| store.Commit());
+        PropVariantClear(&mut value)?;
+        result?;
+    }
+    Ok(link)
+}
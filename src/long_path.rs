@@ -0,0 +1,219 @@
+use std::ffi::OsString;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::{self, Path, PathBuf};
+
+/// The classic path limit, including the terminating NUL.
+const MAX_PATH: usize = 260;
+
+const VERBATIM: &[u16] = &[b'\\' as u16, b'\\' as u16, b'?' as u16, b'\\' as u16];
+const UNC: &[u16] = &[b'\\' as u16, b'\\' as u16];
+
+/// Encodes `path` as a NUL-terminated UTF-16 string for Win32 calls, without the lossy
+/// round trip through `str`.
+pub fn to_wide(path: &Path) -> Vec<u16> {
+    path.as_os_str().encode_wide().chain(Some(0)).collect()
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+/// Returns the extended-length (`\\?\`) form of `path` when it exceeds the classic limit
+/// once made absolute, otherwise the path unchanged.
+pub fn extended(path: &Path) -> PathBuf {
+    let source: Vec<u16> = path.as_os_str().encode_wide().collect();
+    if source.starts_with(VERBATIM) {
+        return path.to_path_buf();
+    }
+    // verbatim paths are passed to the file system as is, so they must be absolute already.
+    // the limit applies to the absolute path Windows resolves a relative one to.
+    let Ok(absolute) = path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let absolute: Vec<u16> = absolute.as_os_str().encode_wide().collect();
+    if absolute.len() < MAX_PATH {
+        return path.to_path_buf();
+    }
+    let prefixed: Vec<u16> = if absolute.starts_with(UNC) {
+        wide(r"\\?\UNC\")
+            .into_iter()
+            .chain(absolute[UNC.len()..].iter().copied())
+            .collect()
+    } else {
+        VERBATIM.iter().chain(absolute.iter()).copied().collect()
+    };
+    PathBuf::from(OsString::from_wide(&prefixed))
+}
+
+/// `path` as text for the settings files, without losing anything: `%` is written as `%25`
+/// and an unpaired surrogate, which NTFS allows in names, as `%uD800`.
+pub fn encode(path: &Path) -> String {
+    let mut text = String::new();
+    for c in char::decode_utf16(path.as_os_str().encode_wide()) {
+        match c {
+            Ok('%') => text.push_str("%25"),
+            Ok(c) => text.push(c),
+            Err(e) => text.push_str(&format!("%u{:04X}", e.unpaired_surrogate())),
+        }
+    }
+    text
+}
+
+/// The path [`encode`] wrote as `text`. A `%` starting neither escape is kept as is.
+pub fn decode(text: &str) -> PathBuf {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('%') {
+        units.extend(rest[..i].encode_utf16());
+        let escape = &rest[i..];
+        let surrogate = escape
+            .strip_prefix("%u")
+            .and_then(|hex| hex.get(..4))
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u16::from_str_radix(hex, 16).ok());
+        if let Some(after) = escape.strip_prefix("%25") {
+            units.push(b'%' as u16);
+            rest = after;
+        } else if let Some(unit) = surrogate {
+            units.push(unit);
+            rest = &escape[6..];
+        } else {
+            units.push(b'%' as u16);
+            rest = &escape[1..];
+        }
+    }
+    units.extend(rest.encode_utf16());
+    PathBuf::from(OsString::from_wide(&units))
+}
+
+/// Strips an extended-length prefix so the path reads the way the user typed it.
+pub fn display(path: &Path) -> PathBuf {
+    let source: Vec<u16> = path.as_os_str().encode_wide().collect();
+    let unc = wide(r"\\?\UNC\");
+    let stripped: Vec<u16> = if source.starts_with(&unc) {
+        UNC.iter()
+            .chain(source[unc.len()..].iter())
+            .copied()
+            .collect()
+    } else if source.starts_with(VERBATIM) {
+        source[VERBATIM.len()..].to_vec()
+    } else {
+        return path.to_path_buf();
+    };
+    PathBuf::from(OsString::from_wide(&stripped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navigation::Navigation;
+    use crate::EXTENSIONS;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::fs;
+
+    #[test]
+    fn long_paths_get_the_prefix() {
+        let long = format!(r"C:\{}\image.png", "a".repeat(300));
+        let extended = extended(Path::new(&long));
+        assert_eq!(extended, PathBuf::from(format!(r"\\?\{long}")));
+        assert_eq!(display(&extended), PathBuf::from(&long));
+    }
+
+    #[test]
+    fn long_unc_paths_get_the_unc_prefix() {
+        let long = format!(r"\\server\share\{}.png", "b".repeat(300));
+        let extended = extended(Path::new(&long));
+        assert_eq!(extended, PathBuf::from(format!(r"\\?\UNC\{}", &long[2..])));
+        assert_eq!(display(&extended), PathBuf::from(&long));
+    }
+
+    #[test]
+    fn short_and_verbatim_paths_are_unchanged() {
+        assert_eq!(extended(Path::new(r"C:\a.png")), PathBuf::from(r"C:\a.png"));
+        let verbatim = format!(r"\\?\C:\{}.png", "c".repeat(300));
+        assert_eq!(extended(Path::new(&verbatim)), PathBuf::from(&verbatim));
+    }
+
+    #[test]
+    fn relative_paths_are_measured_absolute() {
+        // short as typed, beyond the limit once joined to the current directory.
+        let relative = "d".repeat(MAX_PATH - 3);
+        let extended = extended(Path::new(&relative));
+        assert!(extended
+            .as_os_str()
+            .encode_wide()
+            .collect::<Vec<_>>()
+            .starts_with(VERBATIM));
+        assert!(extended.ends_with(&relative));
+    }
+
+    #[test]
+    fn names_outside_the_bmp_round_trip() {
+        let path = PathBuf::from("C:\\photos\\\u{1f600} 100% \u{10437}.png");
+        let encoded = encode(&path);
+        assert_eq!(encoded, "C:\\photos\\\u{1f600} 100%25 \u{10437}.png");
+        assert_eq!(decode(&encoded), path);
+        let wide = to_wide(&path);
+        assert_eq!(wide.len(), path.as_os_str().encode_wide().count() + 1);
+        assert_eq!(
+            PathBuf::from(OsString::from_wide(&wide[..wide.len() - 1])),
+            path
+        );
+    }
+
+    #[test]
+    fn unpaired_surrogates_round_trip() {
+        let mut units: Vec<u16> = "C:\\a".encode_utf16().collect();
+        units.extend([0xd800, b'.' as u16, 0xdc00]);
+        let path = PathBuf::from(OsString::from_wide(&units));
+        let encoded = encode(&path);
+        assert_eq!(encoded, "C:\\a%uD800.%uDC00");
+        assert_eq!(decode(&encoded), path);
+    }
+
+    #[test]
+    fn files_deep_in_a_long_temp_dir_open_navigate_and_delete() {
+        let mut dir = std::env::temp_dir().join(format!("pinion-{}-long", std::process::id()));
+        let root = dir.clone();
+        for level in 0..4 {
+            dir.push(format!("{level}{}", "e".repeat(80)));
+        }
+        let units = |path: &Path| path.as_os_str().encode_wide().count();
+        assert!(units(&dir) > 300);
+        fs::create_dir_all(extended(&dir)).unwrap();
+        for (name, value) in [("a.png", 10), ("b.png", 20), ("c.png", 30)] {
+            RgbImage::from_pixel(2, 1, Rgb([value, 0, 0]))
+                .save_with_format(extended(&dir.join(name)), ImageFormat::Png)
+                .unwrap();
+        }
+
+        let file_path = dir.join("b.png");
+        let img = crate::open_image(&file_path, &mut |_, _| {}).unwrap();
+        assert_eq!(img.to_rgb8().get_pixel(0, 0), &Rgb([20, 0, 0]));
+        // the title shows the path as typed, not the extended form.
+        assert_eq!(display(&extended(&file_path)), file_path);
+
+        let mut navigation = Navigation::folder(&file_path, &EXTENSIONS).unwrap();
+        assert_eq!(navigation.position(), Some((1, 3)));
+        let next = navigation.next().unwrap().to_path_buf();
+        assert_eq!(next, dir.join("c.png"));
+        let img = crate::open_image(&next, &mut |_, _| {}).unwrap();
+        assert_eq!(img.to_rgb8().get_pixel(0, 0), &Rgb([30, 0, 0]));
+        navigation.prev();
+        assert_eq!(navigation.prev(), Some(dir.join("a.png").as_path()));
+
+        fs::remove_file(extended(&file_path)).unwrap();
+        assert!(!extended(&file_path).exists());
+        let navigation = Navigation::folder(&dir.join("a.png"), &EXTENSIONS).unwrap();
+        assert_eq!(navigation.position(), Some((0, 2)));
+        fs::remove_dir_all(extended(&root)).unwrap();
+    }
+
+    #[test]
+    fn stray_percent_signs_are_kept() {
+        assert_eq!(
+            decode("C:\\50%\\%u12.png"),
+            PathBuf::from("C:\\50%\\%u12.png")
+        );
+    }
+}
@@ -1,6 +1,5 @@
 use anyhow::{anyhow, ensure, Context, Result};
 use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
-use std::fs;
 use std::mem;
 
 extern "C" {
@@ -12,7 +11,7 @@ extern "C" {
     ) -> i32;
 }
 
-#[repr(packed)]
+#[repr(C, packed)]
 struct Lz4iHeader {
     sig: [u8; 4],
     width: u32,
@@ -41,16 +40,16 @@ fn lz4_decomp(header: &Lz4iHeader, src: &[u8]) -> Result<Vec<u8>> {
     Ok(dst)
 }
 
-pub fn read_lz4i(file_path: &str) -> Result<DynamicImage> {
-    let raw_lz4i = fs::read(file_path)?;
+/// Decodes an LZ4I file that has already been read into memory.
+pub fn decode_lz4i(raw_lz4i: &[u8]) -> Result<DynamicImage> {
+    let header_size = mem::size_of::<Lz4iHeader>();
+    ensure!(raw_lz4i.len() >= header_size, "Invalid LZ4I format.");
     let header = unsafe { &*(raw_lz4i.as_ptr() as *const Lz4iHeader) };
     ensure!(header.sig.eq(b"lz4i"), "Invalid LZ4I format.");
 
     let width = header.width.to_be();
     let height = header.height.to_be();
 
-    let header_size = mem::size_of::<Lz4iHeader>();
-
     let decomped = lz4_decomp(header, &raw_lz4i[header_size..])?;
 
     let img = if header.channels == 3 {
@@ -1,86 +1,399 @@
 #![windows_subsystem = "windows"]
 
-use anyhow::{ensure, Context, Error, Result};
-use image::{self, imageops, DynamicImage};
+use anyhow::{anyhow, ensure, Context, Error, Result};
+use image::{self, codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat, RgbaImage};
+use std::cell::RefCell;
 use std::env;
 use std::ffi::c_void;
-use std::mem;
-use std::path::Path;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read};
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
 use windows::{
-    core::{PCWSTR, PWSTR},
+    core::PCWSTR,
     w,
     Win32::{
-        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Foundation::{BOOL, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
         Graphics::Gdi::{
-            BeginPaint, BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, CreateFontW, DeleteDC,
-            DeleteObject, EndPaint, GetSysColorBrush, InvalidateRect, SelectObject, SetDIBits,
-            UpdateWindow, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CLIP_DEFAULT_PRECIS, COLOR_MENUBAR,
-            DEFAULT_CHARSET, DEFAULT_PITCH, DEFAULT_QUALITY, DIB_RGB_COLORS, FF_DONTCARE, HFONT,
-            OUT_DEFAULT_PRECIS, PAINTSTRUCT, SRCCOPY,
+            BeginPaint, BitBlt, ClientToScreen, CreateCompatibleBitmap, CreateCompatibleDC,
+            CreateFontW, DeleteDC, DeleteObject, EndPaint, FillRect, GetDC, GetDeviceCaps,
+            InvalidateRect, MonitorFromWindow, PtInRect, ReleaseDC, ScreenToClient, SelectObject,
+            SetViewportOrgEx, UpdateWindow, CLIP_DEFAULT_PRECIS, DEFAULT_CHARSET, DEFAULT_PITCH,
+            DEFAULT_QUALITY, FF_DONTCARE, HDC, HFONT, HMONITOR, LOGPIXELSY,
+            MONITOR_DEFAULTTONEAREST, OUT_DEFAULT_PRECIS, PAINTSTRUCT, SRCCOPY,
         },
+        System::DataExchange::{
+            AddClipboardFormatListener, RemoveClipboardFormatListener, COPYDATASTRUCT,
+        },
+        System::Diagnostics::Debug::OutputDebugStringW,
+        System::Ole::{OleInitialize, OleUninitialize},
+        System::SystemInformation::GetLocalTime,
+        System::SystemServices::{MK_CONTROL, MK_SHIFT},
+        System::Threading::GetCurrentThreadId,
         UI::{
-            Controls::Dialogs::{GetOpenFileNameW, OFN_FILEMUSTEXIST, OPENFILENAMEW},
+            Controls::{NMHDR, TCN_SELCHANGE},
+            Input::KeyboardAndMouse::{
+                DragDetect, GetFocus, GetKeyState, ReleaseCapture, SetCapture, SetFocus, VK_ADD,
+                VK_BACK, VK_CONTROL, VK_ESCAPE, VK_F5, VK_LEFT, VK_OEM_MINUS, VK_OEM_PLUS,
+                VK_RIGHT, VK_SHIFT, VK_SPACE, VK_SUBTRACT, VK_TAB,
+            },
+            Input::Touch::{
+                CloseGestureInfoHandle, GetGestureInfo, GESTUREINFO, GID_BEGIN, GID_END,
+                HGESTUREINFO,
+            },
+            Shell::{DragAcceptFiles, DragFinish, DragQueryFileW, HDROP},
             WindowsAndMessaging::{
-                CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, LoadCursorW,
-                MessageBoxW, PostQuitMessage, RegisterClassW, SendMessageW, SetWindowTextW,
-                ShowWindow, TranslateMessage, BN_CLICKED, BS_PUSHBUTTON, CW_USEDEFAULT, HMENU,
-                IDI_APPLICATION, MB_OK, MSG, SW_SHOW, WINDOW_EX_STYLE, WINDOW_STYLE, WM_COMMAND,
-                WM_CREATE, WM_DESTROY, WM_PAINT, WM_SETFONT, WNDCLASSW, WS_CAPTION, WS_CHILD,
-                WS_OVERLAPPED, WS_SYSMENU, WS_VISIBLE,
+                CreateWindowExW, DefWindowProcW, DispatchMessageW, EnumThreadWindows,
+                GetClassNameW, GetClientRect, GetDlgItem, GetMessageW, GetWindowLongPtrW,
+                GetWindowRect, IsIconic, IsWindowVisible, KillTimer, LoadCursorW, MessageBoxW,
+                PostMessageW, PostQuitMessage, RegisterClassW, SendMessageW, SetForegroundWindow,
+                SetProcessDPIAware, SetTimer, SetWindowLongPtrW, SetWindowTextW, ShowWindow,
+                TranslateMessage, BN_CLICKED, BS_PUSHBUTTON, CREATESTRUCTW, EN_SETFOCUS,
+                GWLP_USERDATA, HMENU, IDI_APPLICATION, MB_OK, MSG, SIZE_MINIMIZED, SW_HIDE,
+                SW_RESTORE, SW_SHOW, SW_SHOWNORMAL, WHEEL_DELTA, WINDOW_EX_STYLE, WINDOW_STYLE,
+                WM_APP, WM_CAPTURECHANGED, WM_CLIPBOARDUPDATE, WM_CLOSE, WM_COMMAND, WM_COPYDATA,
+                WM_CREATE, WM_CTLCOLORBTN, WM_CTLCOLOREDIT, WM_DESTROY, WM_DISPLAYCHANGE,
+                WM_DROPFILES, WM_ENTERSIZEMOVE, WM_ERASEBKGND, WM_EXITSIZEMOVE, WM_GESTURE,
+                WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE, WM_MOUSEWHEEL,
+                WM_MOVE, WM_NCCREATE, WM_NCDESTROY, WM_NOTIFY, WM_PAINT, WM_RBUTTONUP, WM_SETFONT,
+                WM_SETTINGCHANGE, WM_SIZE, WM_TIMER, WNDCLASSW, WS_CHILD, WS_OVERLAPPEDWINDOW,
+                WS_VISIBLE,
             },
         },
     },
 };
 
+mod batch;
+mod channel;
+mod clipboard;
+mod color;
+mod confirm;
+mod controls;
+mod dib;
+mod diff;
+mod drag_out;
+mod file_dialog;
+mod filter;
+mod gesture;
+mod grid;
+mod info_panel;
+mod instance;
+mod jpeg_transform;
+mod jump_list;
+mod long_path;
 mod lz4i_decoder;
-use lz4i_decoder::read_lz4i;
+mod measure;
+mod navigation;
+mod path_box;
+mod placement;
+mod playlist;
+mod print;
+mod probe;
+mod selection;
+mod session;
+mod settings;
+mod slideshow;
+mod stats;
+mod tab;
+mod tab_strip;
+mod taskbar;
+mod theme;
+mod thumbnail;
+mod tray;
+mod view;
+use channel::Channel;
+use color::ColorTransform;
+use dib::Scaling;
+use diff::Diff;
+use file_dialog::{export_dialog, open_dialog, save_dialog, save_list_dialog};
+use filter::Filter;
+use gesture::{Action, Gestures};
+use lz4i_decoder::decode_lz4i;
+use measure::Measure;
+use navigation::Navigation;
+use selection::Selection;
+use session::{Session, SessionTab};
+use settings::settings;
+use slideshow::Slideshow;
+use stats::Stats;
+use tab::Tab;
+use taskbar::Taskbar;
+use view::View;
 
 const CLASS_NAME: PCWSTR = w!("pinion_window_class");
 
-static mut H_WINDOW: Option<HWND> = None;
+/// File extensions pinion can open.
+const EXTENSIONS: [&str; 5] = ["jpg", "png", "gif", "bmp", "lz4i"];
+
+/// Shared by all windows, deleted with the last one.
 static mut H_FONT: Option<HFONT> = None;
-static mut BUF: Vec<u8> = Vec::new();
-static mut DATA_LEN: usize = 0;
-static mut WIDTH: i32 = 0;
-static mut HEIGHT: i32 = 0;
+/// Pixels per logical inch of the screen, 96 at 100% scaling.
+static mut DPI: i32 = 96;
+/// Set when the user asked not to confirm large files again until pinion closes.
+static OPEN_LARGE_FILES: AtomicBool = AtomicBool::new(false);
 
 const ID_OPEN_BUTTON: i32 = 2100;
+const ID_TAB_STRIP: i32 = 2101;
+const ID_PATH_BOX: i32 = 2102;
+
+/// Height of the strip holding the Open button, at 96 DPI.
+const TOOLBAR_HEIGHT: i32 = 32;
+
+/// Selections smaller than this, at 96 DPI, are taken for a click.
+const MIN_SELECTION: i32 = 8;
+
+/// Zoom per wheel notch.
+const WHEEL_ZOOM: f32 = 1.25;
+
+const ID_SLIDESHOW_TIMER: usize = 1;
+/// Ends the `title_note`.
+const ID_TITLE_NOTE_TIMER: usize = 2;
+const TITLE_NOTE_MS: u32 = 2000;
+
+/// Posted by the statistics thread, `LPARAM` owns a boxed `StatsResult`.
+const WM_STATS: u32 = WM_APP + 1;
+/// Posted by the difference thread, `LPARAM` owns a boxed `DiffResult`.
+const WM_DIFF: u32 = WM_APP + 3;
+
+/// Quality of the JPEG files Save As writes, the encoder's default of 75 is visibly lossy.
+const JPEG_QUALITY: u8 = 90;
+
+/// Files at least this large show their loading progress on the taskbar button.
+const LARGE_FILE: u64 = 16 * 1024 * 1024;
+
+/// Decoded pixels kept for all tabs together. Background tabs beyond it are decoded again
+/// when activated.
+const CACHE_BUDGET: usize = 1024 * 1024 * 1024;
+
+/// Viewer state of one window, owned by its window procedure.
+///
+/// Borrow it only briefly and never across anything that can re-enter `window_proc`.
+#[derive(Default)]
+struct AppState {
+    tabs: Vec<Tab>,
+    active: usize,
+    /// Counts tab activations, see `Tab::last_active`.
+    activations: u64,
+    gestures: Gestures,
+    /// The last mouse position while dragging to pan.
+    pan_from: Option<POINT>,
+    /// The rectangle being dragged while Z is held, to zoom into, or with Shift, to mark.
+    selection: Option<Selection>,
+    /// Whether `selection` marks a region for the statistics rather than zooming.
+    marking: bool,
+    /// Whether a drag with M held is measuring.
+    measuring: bool,
+    /// Whether a rectangle was dragged since Z went down, so releasing Z doesn't go back.
+    zoom_selected: bool,
+    /// Set during a live resize, which skips the slow fitted copy.
+    resizing: bool,
+    taskbar: Taskbar,
+    slideshow: Slideshow,
+    /// Shown after the title for a moment, such as a new slideshow interval.
+    title_note: Option<String>,
+    /// Whether folder navigation keeps to the extension of the file shown.
+    same_extension: bool,
+    /// From sRGB to the profile of the monitor the window is on, `None` to show pixels as is.
+    color: Option<ColorTransform>,
+    /// The monitor `color` was made for.
+    monitor: HMONITOR,
+    /// Whether the info panel is shown.
+    info: bool,
+    /// Whether the statistics are shown, in the info panel.
+    stats: bool,
+    /// The file and region whose statistics are being computed.
+    pending_stats: Option<(Weak<DynamicImage>, Option<[u32; 4]>)>,
+    /// How the difference view shows, `None` when it is off.
+    diff_style: Option<diff::Style>,
+    /// The difference last computed, and the pair of images being compared.
+    diff: Option<DiffCache>,
+    pending_diff: Option<DiffPair>,
+    /// Whether images copied to the clipboard are shown as they arrive.
+    watching_clipboard: bool,
+    /// Multiple of the window size the view is exported at.
+    export_scale: u32,
+    /// Whether the window is minimized to an icon in the notification area.
+    in_tray: bool,
+    /// Whether pixel boundaries are drawn at high zoom.
+    grid: bool,
+}
+
+impl AppState {
+    fn tab(&self) -> Option<&Tab> {
+        self.tabs.get(self.active)
+    }
+
+    fn tab_mut(&mut self) -> Option<&mut Tab> {
+        self.tabs.get_mut(self.active)
+    }
+
+    /// The active tab's view and image size, when it shows an image.
+    fn view_mut(&mut self) -> Option<(&mut View, (u32, u32))> {
+        let tab = self.tabs.get_mut(self.active)?;
+        let size = tab.image_size()?;
+        Some((&mut tab.view, size))
+    }
+
+    fn can_pan(&self, viewport: &RECT) -> bool {
+        self.tab().is_some_and(|tab| tab.can_pan(viewport))
+    }
+
+    /// The tab active before the active one, which the difference view compares with.
+    fn diff_other(&self) -> Option<usize> {
+        (0..self.tabs.len())
+            .filter(|&i| i != self.active)
+            .max_by_key(|&i| self.tabs[i].last_active)
+    }
+
+    fn activate(&mut self, index: usize) {
+        self.active = index;
+        self.activations += 1;
+        if let Some(tab) = self.tabs.get_mut(index) {
+            tab.last_active = self.activations;
+        }
+    }
+
+    /// Drops the decoded images of background tabs, least recently used first, until all tabs
+    /// together fit in `CACHE_BUDGET`.
+    fn trim_cache(&mut self) {
+        let mut used: usize = self.tabs.iter().map(Tab::memory).sum();
+        // pasted images cannot be decoded again.
+        let mut background: Vec<usize> = (0..self.tabs.len())
+            .filter(|&i| i != self.active && self.tabs[i].is_loaded() && self.tabs[i].is_file())
+            .collect();
+        background.sort_by_key(|&i| self.tabs[i].last_active);
+        for i in background {
+            if used <= CACHE_BUDGET {
+                break;
+            }
+            used -= self.tabs[i].memory();
+            self.tabs[i].unload();
+        }
+    }
+
+    /// Shows the slideshow position on the taskbar button, or nothing when it isn't running.
+    fn update_taskbar(&mut self) {
+        let position = self.tab().and_then(|tab| tab.navigation.position());
+        match position {
+            Some((index, len)) if self.slideshow.is_active() => {
+                self.taskbar.set_position(index as u64 + 1, len as u64)
+            }
+            _ => self.taskbar.clear(),
+        }
+    }
+}
+
+/// Open windows. The process quits when the last one is destroyed.
+static WINDOW_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs `f` on the state of `h_wnd`, kept in its `GWLP_USERDATA` from `WM_NCCREATE` to
+/// `WM_NCDESTROY`. `None` outside that time.
+fn with_state<R>(h_wnd: HWND, f: impl FnOnce(&mut AppState) -> R) -> Option<R> {
+    let state = unsafe { GetWindowLongPtrW(h_wnd, GWLP_USERDATA) } as *const RefCell<AppState>;
+    if state.is_null() {
+        return None;
+    }
+    Some(f(&mut unsafe { &*state }.borrow_mut()))
+}
+
+/// Files passed on the command line. `/` and `-` style flags, such as those added by shell
+/// verbs, are skipped unless a file of that name exists, as is the playlist after `--list`.
+/// Names with `*` or `?` stand for the files they match.
+fn file_args() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--list" {
+            args.next();
+            continue;
+        }
+        let is_flag = arg
+            .to_str()
+            .is_some_and(|arg| arg.starts_with('/') || arg.starts_with('-'));
+        if !is_flag || long_path::extended(Path::new(&arg)).exists() {
+            files.extend(navigation::expand_wildcards(PathBuf::from(arg)));
+        }
+    }
+    files
+}
+
+/// The playlist given as `--list show.txt`.
+fn list_arg() -> Option<PathBuf> {
+    let mut args = env::args_os().skip(1);
+    args.find(|arg| arg == "--list")?;
+    args.next().map(PathBuf::from)
+}
+
+/// `--no-restore` starts with an empty window instead of the last session.
+fn no_restore() -> bool {
+    env::args_os().skip(1).any(|arg| arg == "--no-restore")
+}
 
 fn main() -> Result<()> {
+    if let Some(job) = thumbnail::arg() {
+        std::process::exit(thumbnail::run(job));
+    }
+    let list = list_arg();
+    let (files, list_error) = match &list {
+        // the list's files go where files on the command line go, to a running window too.
+        Some(list) => match playlist::read(&long_path::extended(list)) {
+            Ok(files) => (files, None),
+            Err(e) => (Vec::new(), Some(e)),
+        },
+        None => (file_args(), None),
+    };
+    let single_instance = settings().single_instance;
+    if !files.is_empty() && single_instance && instance::forward(&files) {
+        return Ok(());
+    }
+
+    // the file dialog and drag and drop need COM and OLE, so the UI thread joins an STA once
+    // for the whole run.
+    let ole = unsafe { OleInitialize(ptr::null()) };
+    jump_list::set_app_id().map_err(log_error).ok();
+
+    // without this, Windows stretches the window on scaled displays and touch positions no
+    // longer match client coordinates.
+    unsafe {
+        SetProcessDPIAware();
+        let hdc = GetDC(None);
+        DPI = GetDeviceCaps(hdc, LOGPIXELSY);
+        ReleaseDC(None, hdc);
+    }
+
     let wnd_class = WNDCLASSW {
         lpszClassName: CLASS_NAME,
         lpfnWndProc: Some(window_proc),
         hCursor: unsafe { LoadCursorW(None, IDI_APPLICATION)? },
-        hbrBackground: unsafe { GetSysColorBrush(COLOR_MENUBAR) },
+        // WM_ERASEBKGND fills with the theme's color instead.
         ..Default::default()
     };
     unsafe { RegisterClassW(&wnd_class) };
+    theme::refresh();
 
-    let title = format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-    let hwnd = unsafe {
-        CreateWindowExW(
-            WINDOW_EX_STYLE::default(),
-            CLASS_NAME,
-            PCWSTR::from_raw(l(&title).as_ptr()),
-            WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
-            CW_USEDEFAULT,
-            CW_USEDEFAULT,
-            656,
-            551,
-            None,
-            None,
-            None,
-            None,
-        )
-    };
-    ensure!(hwnd.0 != 0, "failed to create window.");
+    let rc = placement::initial_rect(scaled(656), scaled(551));
+    let hwnd = create_window(&rc)?;
+    let saved = settings().placement(placement::layout_key());
+    placement::show(hwnd, saved);
+    unsafe { UpdateWindow(hwnd) };
 
-    unsafe {
-        BUF.reserve(640 * 480 * 3);
-        ShowWindow(hwnd, SW_SHOW);
-        UpdateWindow(hwnd);
-        H_WINDOW = Some(hwnd);
+    refresh_jump_list();
+    if let Some(e) = list_error {
+        msg_box(hwnd, e);
+    } else if list.is_some() {
+        open_playlist(hwnd, files)
+            .map_err(|e| msg_box(hwnd, e))
+            .ok();
+    } else if !files.is_empty() {
+        open_files(hwnd, files).map_err(|e| msg_box(hwnd, e)).ok();
+    } else if !no_restore() {
+        restore_session(hwnd).map_err(log_error).ok();
     }
 
     let mut msg = MSG::default();
@@ -88,11 +401,66 @@ fn main() -> Result<()> {
         if unsafe { !GetMessageW(&mut msg, None, 0, 0).as_bool() } {
             break;
         }
+        if batch::is_dialog_message(&msg)
+            || print::is_dialog_message(&msg)
+            || path_box::key(&msg, ID_PATH_BOX)
+        {
+            continue;
+        }
         unsafe {
             TranslateMessage(&msg);
             DispatchMessageW(&msg);
         }
     }
+
+    if ole.is_ok() {
+        unsafe { OleUninitialize() };
+    }
+    Ok(())
+}
+
+/// Creates another viewer window at `rc`, still hidden.
+fn create_window(rc: &RECT) -> Result<HWND> {
+    // WM_NCCREATE takes the state, it stays here to be dropped when creation fails earlier.
+    let mut pending = Some(Box::new(RefCell::new(AppState {
+        export_scale: 1,
+        slideshow: Slideshow::new(settings().slideshow_interval_ms),
+        ..Default::default()
+    })));
+    let h_wnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            CLASS_NAME,
+            PCWSTR::from_raw(l(&app_title()).as_ptr()),
+            WS_OVERLAPPEDWINDOW,
+            rc.left,
+            rc.top,
+            rc.right - rc.left,
+            rc.bottom - rc.top,
+            None,
+            None,
+            None,
+            Some(&mut pending as *mut _ as *const c_void),
+        )
+    };
+    // once WM_NCCREATE took the state, WM_NCDESTROY frees it, also when creation fails.
+    ensure!(h_wnd.0 != 0, "failed to create window.");
+    Ok(h_wnd)
+}
+
+/// Ctrl+N: an independent window, offset from `h_wnd` so both stay visible.
+fn new_window(h_wnd: HWND) -> Result<()> {
+    let mut rc = RECT::default();
+    unsafe { GetWindowRect(h_wnd, &mut rc) };
+    let offset = scaled(TOOLBAR_HEIGHT);
+    let rc = RECT {
+        left: rc.left + offset,
+        top: rc.top + offset,
+        right: rc.right + offset,
+        bottom: rc.bottom + offset,
+    };
+    let new = create_window(&rc)?;
+    unsafe { ShowWindow(new, SW_SHOWNORMAL) };
     Ok(())
 }
 
@@ -103,40 +471,268 @@ unsafe extern "system" fn window_proc(
     l_param: LPARAM,
 ) -> LRESULT {
     match msg {
+        WM_NCCREATE => {
+            let create = &*(l_param.0 as *const CREATESTRUCTW);
+            let pending = &mut *(create.lpCreateParams as *mut Option<Box<RefCell<AppState>>>);
+            if let Some(state) = pending.take() {
+                SetWindowLongPtrW(h_wnd, GWLP_USERDATA, Box::into_raw(state) as isize);
+            }
+            WINDOW_COUNT.fetch_add(1, Ordering::Relaxed);
+            return DefWindowProcW(h_wnd, msg, w_param, l_param);
+        }
+        WM_NCDESTROY => {
+            let state = SetWindowLongPtrW(h_wnd, GWLP_USERDATA, 0) as *mut RefCell<AppState>;
+            if !state.is_null() {
+                drop(Box::from_raw(state));
+            }
+            if WINDOW_COUNT.fetch_sub(1, Ordering::Relaxed) == 1 {
+                if let Some(font) = H_FONT {
+                    DeleteObject(font);
+                    H_FONT = None;
+                }
+                PostQuitMessage(0);
+            }
+            return DefWindowProcW(h_wnd, msg, w_param, l_param);
+        }
         WM_CREATE => create(h_wnd),
         WM_COMMAND => command(h_wnd, w_param),
-        WM_PAINT => {
-            if DATA_LEN > 0 {
-                paint(h_wnd)
-            } else {
-                return DefWindowProcW(h_wnd, msg, w_param, l_param);
+        WM_NOTIFY => notify(h_wnd, l_param),
+        WM_DROPFILES => drop_files(h_wnd, HDROP(w_param.0 as isize)),
+        WM_KEYDOWN => key_down(h_wnd, w_param),
+        WM_KEYUP => key_up(h_wnd, w_param),
+        WM_LBUTTONDOWN => l_button_down(h_wnd, w_param, l_param),
+        WM_MOUSEMOVE => mouse_move(h_wnd, l_param),
+        WM_LBUTTONUP => l_button_up(h_wnd),
+        WM_CAPTURECHANGED => {
+            let selecting = with_state(h_wnd, |state| {
+                state.pan_from = None;
+                state.measuring = false;
+                state.selection.take().is_some()
+            })
+            .unwrap_or_default();
+            if selecting {
+                redraw(h_wnd);
             }
+            Ok(())
+        }
+        WM_MOUSEWHEEL => mouse_wheel(h_wnd, w_param, l_param),
+        WM_STATS => stats_done(h_wnd, l_param),
+        WM_DIFF => diff_done(h_wnd, l_param),
+        WM_CLIPBOARDUPDATE => {
+            clipboard_update(h_wnd);
+            Ok(())
+        }
+        WM_GESTURE => match gesture(h_wnd, l_param) {
+            Ok(false) => return DefWindowProcW(h_wnd, msg, w_param, l_param),
+            result => result.map(|_| ()),
+        },
+        WM_TIMER if w_param.0 == ID_SLIDESHOW_TIMER => slideshow_tick(h_wnd),
+        WM_TIMER if w_param.0 == ID_TITLE_NOTE_TIMER => {
+            KillTimer(h_wnd, ID_TITLE_NOTE_TIMER);
+            with_state(h_wnd, |state| state.title_note = None);
+            update_title(h_wnd);
+            Ok(())
+        }
+        WM_COPYDATA => return LRESULT(copy_data(h_wnd, l_param) as isize),
+        WM_PAINT => paint(h_wnd),
+        WM_ERASEBKGND => {
+            erase_background(h_wnd, HDC(w_param.0 as isize));
+            return LRESULT(1);
+        }
+        // the corners around the Open button.
+        WM_CTLCOLORBTN => return LRESULT(theme::background().0),
+        // the path box, which has no dark look of its own.
+        WM_CTLCOLOREDIT if theme::is_dark() => {
+            return LRESULT(theme::dark_edit(HDC(w_param.0 as isize)).0)
+        }
+        WM_SETTINGCHANGE if is_color_set_change(l_param) => {
+            theme::refresh();
+            apply_theme(h_wnd);
+            background_changed(h_wnd);
+            Ok(())
+        }
+        WM_SIZE if w_param.0 as u32 == SIZE_MINIMIZED && settings().minimize_to_tray => {
+            to_tray(h_wnd);
+            Ok(())
+        }
+        WM_SIZE => {
+            layout(h_wnd);
+            Ok(())
+        }
+        tray::WM_TRAY => tray_message(h_wnd, l_param),
+        path_box::WM_PATH_ENTERED => open_typed_path(h_wnd),
+        WM_MOVE => {
+            update_color(h_wnd, false);
+            Ok(())
+        }
+        WM_DISPLAYCHANGE => {
+            update_color(h_wnd, true);
+            Ok(())
+        }
+        WM_ENTERSIZEMOVE => {
+            with_state(h_wnd, |state| state.resizing = true);
+            Ok(())
+        }
+        WM_EXITSIZEMOVE => {
+            with_state(h_wnd, |state| state.resizing = false);
+            redraw(h_wnd);
+            Ok(())
         }
         WM_DESTROY => {
-            if let Some(font) = H_FONT {
-                DeleteObject(font);
+            // the last window closed is the one the next start comes back to.
+            if WINDOW_COUNT.load(Ordering::Relaxed) == 1 {
+                save_session(h_wnd).map_err(log_error).ok();
+            }
+            if with_state(h_wnd, |state| state.watching_clipboard).unwrap_or_default() {
+                RemoveClipboardFormatListener(h_wnd);
+            }
+            if with_state(h_wnd, |state| state.in_tray).unwrap_or_default() {
+                tray::remove(h_wnd);
+            }
+            placement::current(h_wnd)
+                .and_then(|placement| settings().remember_placement(placement))
+                .map_err(log_error)
+                .ok();
+            Ok(())
+        }
+        m if m == taskbar::button_created_message() => {
+            with_state(h_wnd, |state| {
+                state.taskbar.button_created(h_wnd);
+                state.update_taskbar();
+            });
+            Ok(())
+        }
+        // Explorer restarted and lost the icon.
+        m if m == tray::taskbar_created_message() => {
+            if with_state(h_wnd, |state| state.in_tray).unwrap_or_default() {
+                tray::add(h_wnd);
             }
-            PostQuitMessage(0);
             Ok(())
         }
         _ => return DefWindowProcW(h_wnd, msg, w_param, l_param),
     }
-    .map_err(msg_box)
+    .map_err(|e| msg_box(h_wnd, e))
     .ok();
 
     LRESULT::default()
 }
 
 fn create(h_wnd: HWND) -> Result<()> {
-    create_font()?;
+    if unsafe { H_FONT }.is_none() {
+        create_font()?;
+    }
     create_button(h_wnd)?;
+    path_box::create(h_wnd, ID_PATH_BOX, unsafe { H_FONT.context("no font")? })?;
+    tab_strip::create(h_wnd, ID_TAB_STRIP, unsafe { H_FONT.context("no font")? })?;
+    apply_theme(h_wnd);
+    unsafe { DragAcceptFiles(h_wnd, true) };
+    gesture::configure(h_wnd);
+    update_color(h_wnd, true);
+    Ok(())
+}
+
+/// Makes the color transform for the monitor the window is on, unless it is still the one
+/// `color` was made for and not `force`.
+fn update_color(h_wnd: HWND, force: bool) {
+    let monitor = unsafe { MonitorFromWindow(h_wnd, MONITOR_DEFAULTTONEAREST) };
+    if !force && with_state(h_wnd, |state| state.monitor == monitor).unwrap_or_default() {
+        return;
+    }
+    let color = if settings().color_management {
+        ColorTransform::for_window(h_wnd)
+            .map_err(log_error)
+            .ok()
+            .flatten()
+    } else {
+        None
+    };
+    with_state(h_wnd, |state| {
+        state.monitor = monitor;
+        state.color = color;
+        for tab in &mut state.tabs {
+            tab.invalidate_display();
+        }
+    });
+    redraw(h_wnd);
+}
+
+/// Turns color management on or off in every window, to compare with the unmanaged pixels.
+fn toggle_color_management() -> Result<()> {
+    {
+        let mut settings = settings();
+        settings.color_management = !settings.color_management;
+        settings.save()?;
+    }
+    for_each_window(|h_wnd| update_color(h_wnd, true));
+    Ok(())
+}
+
+/// Sent as the `WM_SETTINGCHANGE` area when the light/dark app mode changes.
+fn is_color_set_change(l_param: LPARAM) -> bool {
+    l_param.0 != 0
+        && unsafe { PCWSTR::from_raw(l_param.0 as *const u16).to_string() }
+            .is_ok_and(|area| area == "ImmersiveColorSet")
+}
+
+fn apply_theme(h_wnd: HWND) {
+    let controls = [ID_OPEN_BUTTON, ID_TAB_STRIP, ID_PATH_BOX].map(|id| controls::item(h_wnd, id));
+    theme::apply(h_wnd, &controls);
+    unsafe { InvalidateRect(h_wnd, None, true) };
+}
+
+/// Stores the `background` setting and shows it in every window.
+fn set_background(background: Option<[u8; 3]>) -> Result<()> {
+    {
+        let mut settings = settings();
+        settings.background = background;
+        settings.save()?;
+    }
+    for_each_window(background_changed);
     Ok(())
 }
 
+fn background_changed(h_wnd: HWND) {
+    with_state(h_wnd, |state| {
+        for tab in &mut state.tabs {
+            tab.invalidate_display();
+        }
+    });
+    unsafe { InvalidateRect(h_wnd, None, true) };
+}
+
+/// Calls `f` for each pinion window of this process.
+fn for_each_window(mut f: impl FnMut(HWND)) {
+    unsafe extern "system" fn visit(h_wnd: HWND, data: LPARAM) -> BOOL {
+        let f = &mut *(data.0 as *mut &mut dyn FnMut(HWND));
+        let mut buf = [0u16; 32];
+        let len = GetClassNameW(h_wnd, &mut buf) as usize;
+        if CLASS_NAME.as_wide() == &buf[..len] {
+            f(h_wnd);
+        }
+        true.into()
+    }
+    let mut f: &mut dyn FnMut(HWND) = &mut f;
+    unsafe {
+        EnumThreadWindows(
+            GetCurrentThreadId(),
+            Some(visit),
+            LPARAM(&mut f as *mut _ as isize),
+        )
+    };
+}
+
+/// Fills the toolbar and tab strip area, `paint` covers the viewport itself.
+fn erase_background(h_wnd: HWND, hdc: HDC) {
+    let mut rc = RECT::default();
+    unsafe { GetClientRect(h_wnd, &mut rc) };
+    rc.bottom = viewport(h_wnd).top;
+    unsafe { FillRect(hdc, &rc, theme::background()) };
+}
+
 fn create_font() -> Result<()> {
     let font = unsafe {
         CreateFontW(
-            18,
+            scaled(18),
             0,
             0,
             0,
@@ -164,10 +760,10 @@ fn create_button(h_wnd: HWND) -> Result<()> {
             w!("BUTTON"),
             w!("Open"),
             WS_CHILD | WS_VISIBLE | WINDOW_STYLE(BS_PUSHBUTTON as u32),
-            4,
-            4,
-            80,
-            24,
+            scaled(4),
+            scaled(4),
+            scaled(80),
+            scaled(24),
             h_wnd,
             HMENU(ID_OPEN_BUTTON as isize),
             None,
@@ -189,175 +785,2142 @@ fn command(h_wnd: HWND, w_param: WPARAM) -> Result<()> {
     let msg = (w_param.0 as u32) >> 16;
     let id = ((w_param.0 as u32) & 0xffff) as i32;
     if msg == BN_CLICKED && id == ID_OPEN_BUTTON {
-        let file_path = open_dialog(h_wnd)?;
-        read_image(&file_path)?;
+        if let Some(file_path) = choose_file(h_wnd)? {
+            open_file(h_wnd, &file_path)?;
+        }
+    }
+    if msg == EN_SETFOCUS && id == ID_PATH_BOX {
+        path_box::select_all(controls::item(h_wnd, ID_PATH_BOX));
     }
     Ok(())
 }
 
-fn open_image(file_path: &str) -> Result<DynamicImage> {
-    let path = Path::new(file_path);
-    if path.extension().context("no extension")?.eq("lz4i") {
-        read_lz4i(file_path)
-    } else {
-        Ok(image::open(file_path)?)
+/// Opens the file typed or pasted into the path box, or the first image of a folder, as if
+/// passed on the command line. The text stays when that fails, to be corrected.
+fn open_typed_path(h_wnd: HWND) -> Result<()> {
+    let text = controls::text(h_wnd, ID_PATH_BOX);
+    let text = text.trim().trim_matches('"').trim();
+    if text.is_empty() {
+        return Ok(());
+    }
+    ensure!(!text.contains("://"), "Opening URLs is not supported.");
+    let mut path = PathBuf::from(text);
+    if long_path::extended(&path).is_dir() {
+        let files = navigation::supported_files(&long_path::extended(&path))?;
+        let first = files
+            .first()
+            .context("The folder has no images pinion can open.")?;
+        path = path.join(first.file_name().unwrap_or_default());
+    }
+    open_files(h_wnd, vec![path])?;
+    // the arrow keys step through the files again.
+    unsafe { SetFocus(h_wnd) };
+    update_path_box(h_wnd);
+    Ok(())
+}
+
+/// Shows the file of the active tab in the path box, unless the user is typing there.
+fn update_path_box(h_wnd: HWND) {
+    let h_edit = controls::item(h_wnd, ID_PATH_BOX);
+    if unsafe { GetFocus() } == h_edit {
+        return;
     }
+    let file_path = with_state(h_wnd, |state| {
+        state
+            .tab()
+            .filter(|tab| tab.is_file())
+            .map(|tab| tab.file_path.clone())
+    })
+    .unwrap_or_default();
+    path_box::set_path(h_edit, file_path.as_deref());
+}
+
+fn choose_file(h_wnd: HWND) -> Result<Option<PathBuf>> {
+    let initial_dir = settings().initial_dir().map(Path::to_path_buf);
+    let file_path = open_dialog(h_wnd, initial_dir.as_deref());
+    // give the keyboard back to the viewer so the arrow keys keep working.
+    unsafe { SetFocus(h_wnd) };
+    file_path
 }
 
-fn read_image(file_path: &str) -> Result<()> {
-    let img = open_image(file_path)?;
-    let width = img.width();
-    let height = img.height();
+fn notify(h_wnd: HWND, l_param: LPARAM) -> Result<()> {
+    let header = unsafe { &*(l_param.0 as *const NMHDR) };
+    if header.code == TCN_SELCHANGE && header.idFrom == ID_TAB_STRIP as usize {
+        if let Some(index) = tab_strip::selection(header.hwndFrom) {
+            activate_tab(h_wnd, index)?;
+        }
+    }
+    Ok(())
+}
 
-    let img = if width > 640 || height > 480 {
-        let new_size = if width as f32 / height as f32 > 1.333 {
-            640
-        } else if width > height {
-            (480.0 / height as f32 * width as f32) as u32
+fn key_down(h_wnd: HWND, w_param: WPARAM) -> Result<()> {
+    let key = w_param.0 as u16;
+    if key == VK_F5.0 {
+        let active = with_state(h_wnd, |state| state.slideshow.is_active()).context("no state")?;
+        return if active {
+            stop_slideshow(h_wnd)
         } else {
-            480
+            start_slideshow(h_wnd)
         };
-        img.resize(new_size, new_size, imageops::Lanczos3)
-    } else {
-        img
-    };
-
-    let width = img.width();
-    let height = img.height();
-    let mut rgb = img.into_rgb8();
-    ensure!(rgb.len() <= 640 * 480 * 3, "Invalid data length.");
+    }
+    if key == VK_ESCAPE.0 {
+        // clears a measurement or a marked region first.
+        let cleared = with_state(h_wnd, |state| match state.tab_mut() {
+            Some(tab) if tab.measure().is_some() => {
+                tab.set_measure(None);
+                true
+            }
+            Some(tab) if tab.selection().is_some() => {
+                tab.set_selection(None);
+                true
+            }
+            _ => false,
+        })
+        .context("no state")?;
+        if cleared {
+            redraw(h_wnd);
+            request_stats(h_wnd);
+            return Ok(());
+        }
+        return stop_slideshow(h_wnd);
+    }
 
-    // change from RGB to BGR.
-    rgb.chunks_mut(3).for_each(|c| c.swap(0, 2));
+    if unsafe { GetKeyState(VK_CONTROL.0 as i32) } < 0 {
+        let shift = unsafe { GetKeyState(VK_SHIFT.0 as i32) } < 0;
+        return match key {
+            k if k == b'B' as u16 => batch_convert(h_wnd),
+            k if k == b'C' as u16 && shift => copy_stats(h_wnd),
+            k if k == b'C' as u16 => copy(h_wnd),
+            k if k == b'E' as u16 && shift => copy_view(h_wnd),
+            k if k == b'E' as u16 => export_view(h_wnd),
+            k if k == b'V' as u16 && shift => watch_clipboard(h_wnd),
+            k if k == b'V' as u16 => paste(h_wnd, true).map(|_| ()),
+            k if k == b'F' as u16 => apply_filter(h_wnd, shift_filter(shift)),
+            k if k == b'Z' as u16 => undo_filter(h_wnd),
+            k if k == b'T' as u16 => match choose_file(h_wnd)? {
+                Some(file_path) => open_in_new_tab(h_wnd, &file_path),
+                None => Ok(()),
+            },
+            k if k == b'W' as u16 => close_tab(h_wnd),
+            k if k == b'S' as u16 => save_as(h_wnd),
+            k if k == b'N' as u16 => new_window(h_wnd),
+            k if k == b'L' as u16 => save_list(h_wnd),
+            k if k == b'P' as u16 => print_preview(h_wnd),
+            k if k == VK_TAB.0 => cycle_tab(h_wnd, shift),
+            _ => Ok(()),
+        };
+    }
 
-    let remain = (3 * width as usize) % 4;
+    match key {
+        k if k == VK_RIGHT.0 => navigate(h_wnd, true),
+        k if k == VK_LEFT.0 => navigate(h_wnd, false),
+        k if k == VK_BACK.0 => view_back(h_wnd),
+        k if k == VK_SPACE.0 => toggle_pause(h_wnd),
+        k if k == VK_OEM_PLUS.0 || k == VK_ADD.0 => step_interval(h_wnd, true),
+        k if k == VK_OEM_MINUS.0 || k == VK_SUBTRACT.0 => step_interval(h_wnd, false),
+        k if k == b'I' as u16 => {
+            with_state(h_wnd, |state| state.info = !state.info);
+            redraw(h_wnd);
+            Ok(())
+        }
+        k if k == b'F' as u16 => {
+            let shift = unsafe { GetKeyState(VK_SHIFT.0 as i32) } < 0;
+            preview(h_wnd, Some(shift_filter(shift)))
+        }
+        k if k == b'S' as u16 => {
+            with_state(h_wnd, |state| state.stats = !state.stats);
+            request_stats(h_wnd);
+            redraw(h_wnd);
+            Ok(())
+        }
+        k if k == b'C' as u16 => toggle_color_management(),
+        k if k == b'R' as u16 => {
+            let back = unsafe { GetKeyState(VK_SHIFT.0 as i32) } < 0;
+            rotate(h_wnd, if back { 3 } else { 1 })
+        }
+        k if k == b'E' as u16 => toggle_same_extension(h_wnd),
+        k if k == b'N' as u16 => toggle_scaling(h_wnd),
+        k if (b'0' as u16..=b'4' as u16).contains(&k) => {
+            isolate_channel(h_wnd, Channel::from_number((k - b'0' as u16) as u8))
+        }
+        k if k == b'D' as u16 => {
+            let shift = unsafe { GetKeyState(VK_SHIFT.0 as i32) } < 0;
+            set_diff_style(h_wnd, |style| match (style, shift) {
+                (Some(style), true) => Some(style.toggled()),
+                (None, true) => Some(diff::Style::Heatmap),
+                (Some(_), false) => None,
+                (None, false) => Some(diff::Style::default()),
+            })
+        }
+        k if k == b'A' as u16 => set_diff_style(h_wnd, |style| style.map(diff::Style::amplified)),
+        k if k == b'G' as u16 => {
+            let grid = with_state(h_wnd, |state| {
+                state.grid = !state.grid;
+                state.grid
+            })
+            .context("no state")?;
+            let zoom = grid::MIN_CELL * 100.0;
+            flash_title(
+                h_wnd,
+                if grid {
+                    format!("pixel grid from {zoom}%")
+                } else {
+                    "no pixel grid".to_string()
+                },
+            );
+            redraw(h_wnd);
+            Ok(())
+        }
+        k if k == b'B' as u16 => {
+            let background = if unsafe { GetKeyState(VK_SHIFT.0 as i32) } < 0 {
+                match theme::choose_background(h_wnd) {
+                    Some(rgb) => Some(rgb),
+                    None => return Ok(()),
+                }
+            } else {
+                let current = settings().background;
+                theme::next_background(current)
+            };
+            set_background(background)
+        }
+        _ => Ok(()),
+    }
+}
 
-    if remain > 0 {
-        let scan_line = 3 * width as usize;
-        let scan_line_with_padding = scan_line + 4 - remain;
-        let data_len = scan_line_with_padding * height as usize;
-        let mut p = unsafe { BUF.as_mut_ptr() };
-        rgb.chunks(scan_line).for_each(|c| unsafe {
-            ptr::copy_nonoverlapping(c.as_ptr(), p, scan_line);
-            p = p.add(scan_line_with_padding);
-        });
-        unsafe { DATA_LEN = data_len };
+/// The filter of the F key: blur, or sharpen with Shift.
+fn shift_filter(shift: bool) -> Filter {
+    if shift {
+        Filter::Sharpen
     } else {
-        let data_len = (width * height * 3) as usize;
-        unsafe {
-            DATA_LEN = data_len;
-            ptr::copy_nonoverlapping(rgb.as_ptr(), BUF.as_mut_ptr(), data_len);
+        Filter::Blur
+    }
+}
+
+/// Shows `filter` over the image while its key is held, `None` when released.
+fn preview(h_wnd: HWND, filter: Option<Filter>) -> Result<()> {
+    with_state(h_wnd, |state| {
+        if let Some(tab) = state.tab_mut() {
+            tab.preview(filter);
         }
-    };
+    });
+    redraw(h_wnd);
+    Ok(())
+}
 
-    let rc = RECT {
-        top: 32,
-        left: 0,
-        right: 640,
-        bottom: 512,
+fn apply_filter(h_wnd: HWND, filter: Filter) -> Result<()> {
+    with_state(h_wnd, |state| {
+        if let Some(tab) = state.tab_mut() {
+            tab.apply_filter(filter);
+        }
+    });
+    redraw(h_wnd);
+    update_title(h_wnd);
+    request_stats(h_wnd);
+    update_diff(h_wnd)
+}
+
+/// Ctrl+Z: takes back the last applied filter, reloading the file with the others.
+fn undo_filter(h_wnd: HWND) -> Result<()> {
+    let undone = with_state(h_wnd, |state| {
+        let undone = state.tab_mut().is_some_and(Tab::undo_filter);
+        undone.then_some(state.active)
+    })
+    .context("no state")?;
+    let Some(active) = undone else {
+        return Ok(());
     };
-    unsafe {
-        let win = H_WINDOW.context("no window")?;
-        InvalidateRect(win, Some(&rc), true);
-        SetWindowTextW(win, PCWSTR::from_raw(l(file_path).as_ptr()));
-        WIDTH = width as i32;
-        HEIGHT = height as i32;
-    }
-    Ok(())
+    activate_tab(h_wnd, active)?;
+    request_stats(h_wnd);
+    update_diff(h_wnd)
 }
 
-fn open_dialog(h_wnd: HWND) -> Result<String> {
-    const MAX_PATH: u32 = 260;
-    let mut buf = [0u16; MAX_PATH as usize];
+/// Turns the image clockwise by `quarter_turns`.
+fn rotate(h_wnd: HWND, quarter_turns: u32) -> Result<()> {
+    with_state(h_wnd, |state| {
+        if let Some(tab) = state.tab_mut() {
+            tab.rotate(quarter_turns);
+        }
+    });
+    redraw(h_wnd);
+    request_stats(h_wnd);
+    update_diff(h_wnd)
+}
 
-    let filter = w!("Image file (jpg, png, gif, bmp, lz4i)\0*.jpg;*.png;*.gif;*.bmp;*.lz4i\0");
-    let title = w!("Choose a image file");
+fn is_jpeg(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"))
+}
 
-    let mut ofn = OPENFILENAMEW {
-        lStructSize: mem::size_of::<OPENFILENAMEW>() as u32,
-        lpstrFilter: filter,
-        lpstrTitle: title,
-        lpstrFile: PWSTR::from_raw(buf.as_mut_ptr()),
-        nMaxFile: MAX_PATH,
-        Flags: OFN_FILEMUSTEXIST,
-        hwndOwner: h_wnd,
-        ..Default::default()
+/// Saves the image under a new name or format, with its rotation and applied filters but not
+/// a filter being previewed. An unfiltered JPEG saved as JPEG keeps its compressed data,
+/// rotated losslessly when `jpeg_transform` can handle it.
+fn save_as(h_wnd: HWND) -> Result<()> {
+    let Some((file_path, rotation, filtered)) = with_state(h_wnd, |state| {
+        let tab = state.tab().filter(|tab| tab.is_loaded())?;
+        Some((
+            tab.file_path.clone(),
+            tab.rotation(),
+            !tab.filters().is_empty(),
+        ))
+    })
+    .context("no state")?
+    else {
+        return Ok(());
+    };
+    let original = if is_jpeg(&file_path) && !filtered {
+        Some(fs::read(long_path::extended(&file_path))?)
+    } else {
+        None
+    };
+    let rotatable = match &original {
+        Some(original) if rotation != 0 => jpeg_transform::check_rotatable(original),
+        _ => Ok(()),
+    };
+    let lossless = rotatable.is_ok();
+    let note = match (&original, rotatable) {
+        (Some(_), Err(reason)) => Some(format!(
+            "This JPEG cannot be rotated losslessly ({reason}), so saving it rotated as JPEG \
+             re-encodes it with some loss of quality."
+        )),
+        _ => None,
+    };
+    let Some(target) = save_dialog(h_wnd, &file_path, note.as_deref())? else {
+        return Ok(());
     };
 
-    ensure!(
-        unsafe { GetOpenFileNameW(&mut ofn).as_bool() },
-        "Cannot get file path."
-    );
-
-    let result = unsafe { ofn.lpstrFile.to_string()? };
-    Ok(result)
+    let path = long_path::extended(&target);
+    match original {
+        Some(original) if lossless && is_jpeg(&target) => {
+            let data = if rotation == 0 {
+                original
+            } else {
+                jpeg_transform::rotate(&original, rotation)?
+            };
+            fs::write(path, data)?;
+        }
+        _ => with_state(h_wnd, |state| {
+            let img = state.tab().and_then(Tab::image).context("no image")?;
+            save_image(img, &path, JPEG_QUALITY)
+        })
+        .context("no state")??,
+    }
+    Ok(())
 }
 
-fn paint(h_wnd: HWND) -> Result<()> {
-    let mut ps = PAINTSTRUCT::default();
-    let hdc = unsafe { BeginPaint(h_wnd, &mut ps) };
+/// The time of day, naming pasted images.
+fn clock() -> String {
+    let time = unsafe { GetLocalTime() };
+    format!("{:02}:{:02}:{:02}", time.wHour, time.wMinute, time.wSecond)
+}
 
-    let bi = BITMAPINFO {
-        bmiHeader: BITMAPINFOHEADER {
-            biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
-            biWidth: unsafe { WIDTH },
-            biHeight: unsafe { -HEIGHT },
-            biPlanes: 1,
-            biBitCount: 24,
-            biCompression: BI_RGB.0 as u32,
-            biSizeImage: unsafe { DATA_LEN as u32 },
-            ..Default::default()
-        },
-        ..Default::default()
+/// Shows the image on the clipboard in a new tab, or with `new_tab` false in the active tab
+/// when that shows an earlier clipboard image. Returns whether there was an image.
+fn paste(h_wnd: HWND, new_tab: bool) -> Result<bool> {
+    let Some(img) = clipboard::get_image(h_wnd)? else {
+        return Ok(false);
     };
+    let time = clock();
+    with_state(h_wnd, |state| {
+        let reuse = !new_tab && state.tab().is_some_and(|tab| !tab.is_file());
+        if !reuse {
+            state
+                .tabs
+                .push(Tab::new(Path::new(""), Navigation::default()));
+            state.activate(state.tabs.len() - 1);
+        }
+        if let Some(tab) = state.tab_mut() {
+            tab.paste(img, &time);
+        }
+        state.trim_cache();
+    });
+    tabs_changed(h_wnd)?;
+    Ok(true)
+}
 
-    let h_bmp = unsafe { CreateCompatibleBitmap(hdc, WIDTH, HEIGHT) };
+/// Ctrl+C: copies the numbers of the measurement while one shows, the image otherwise.
+fn copy(h_wnd: HWND) -> Result<()> {
+    let measure = with_state(h_wnd, |state| {
+        state.tab().and_then(Tab::measure).map(Measure::describe)
+    })
+    .context("no state")?;
+    match measure {
+        Some(text) => clipboard::set_text(h_wnd, &text),
+        None => copy_image(h_wnd),
+    }
+}
 
-    unsafe {
-        SetDIBits(
-            hdc,
-            h_bmp,
-            0,
-            HEIGHT as u32,
-            BUF.as_ptr() as *const c_void,
-            &bi,
-            DIB_RGB_COLORS,
-        )
-    };
-    let h_mdc = unsafe { CreateCompatibleDC(hdc) };
-    unsafe { SelectObject(h_mdc, h_bmp) };
+/// Copies the image as shown, with its rotation and filters.
+fn copy_image(h_wnd: HWND) -> Result<()> {
+    let img =
+        with_state(h_wnd, |state| state.tab().and_then(Tab::shared_image)).context("no state")?;
+    match img {
+        Some(img) => clipboard::set_image(h_wnd, &img),
+        None => Ok(()),
+    }
+}
 
-    unsafe {
-        let padding_left = (640 - WIDTH) / 2;
-        let padding_top = (480 - HEIGHT) / 2;
-        BitBlt(
-            hdc,
-            padding_left,
-            padding_top + 32,
-            WIDTH,
-            HEIGHT,
-            h_mdc,
-            0,
-            0,
-            SRCCOPY,
+/// Ctrl+Shift+V: starts or stops showing images as they are copied to the clipboard.
+fn watch_clipboard(h_wnd: HWND) -> Result<()> {
+    let watching = with_state(h_wnd, |state| state.watching_clipboard).context("no state")?;
+    if watching {
+        unsafe { RemoveClipboardFormatListener(h_wnd) };
+    } else {
+        ensure!(
+            unsafe { AddClipboardFormatListener(h_wnd) }.as_bool(),
+            "cannot watch the clipboard."
         );
-        DeleteDC(h_mdc);
-        DeleteObject(h_bmp);
-        EndPaint(h_wnd, &ps);
     }
+    with_state(h_wnd, |state| state.watching_clipboard = !watching);
+    redraw(h_wnd);
     Ok(())
 }
 
-fn msg_box(e: Error) -> Result<()> {
-    unsafe {
-        MessageBoxW(
-            H_WINDOW.context("no window")?,
-            PCWSTR::from_raw(l(&e.to_string()).as_ptr()),
+fn clipboard_update(h_wnd: HWND) {
+    // pinion's own copies would come straight back, and text is of no interest.
+    if clipboard::is_own() || !clipboard::has_image() {
+        return;
+    }
+    // a message box for every copy would get in the way.
+    paste(h_wnd, false).map_err(log_error).ok();
+}
+
+/// Opens the batch conversion dialog for the active tab's navigation list.
+fn batch_convert(h_wnd: HWND) -> Result<()> {
+    let files = with_state(h_wnd, |state| {
+        state
+            .tab()
+            .map(|tab| tab.navigation.files().to_vec())
+            .unwrap_or_default()
+    })
+    .context("no state")?;
+    batch::show(h_wnd, files, unsafe { H_FONT.context("no font")? })
+}
+
+/// Opens the print preview of the active tab's image.
+fn print_preview(h_wnd: HWND) -> Result<()> {
+    let image = with_state(h_wnd, |state| {
+        state
+            .tab()
+            .and_then(|tab| Some((tab.shared_image()?, tab.title())))
+    })
+    .context("no state")?;
+    let Some((img, title)) = image else {
+        return Ok(());
+    };
+    print::show(h_wnd, img, title, unsafe { H_FONT.context("no font")? })
+}
+
+/// Encodes `img` in the format of the extension of `path`, JPEG at `quality` from 1 to 100.
+fn save_image(img: &DynamicImage, path: &Path, quality: u8) -> Result<()> {
+    let format = ImageFormat::from_path(path)?;
+    if format == ImageFormat::Jpeg {
+        // JPEG has no alpha.
+        let file = BufWriter::new(File::create(path)?);
+        JpegEncoder::new_with_quality(file, quality).encode_image(&img.to_rgb8())?;
+    } else {
+        img.save_with_format(path, format)?;
+    }
+    Ok(())
+}
+
+fn key_up(h_wnd: HWND, w_param: WPARAM) -> Result<()> {
+    if w_param.0 as u16 == b'F' as u16 {
+        return preview(h_wnd, None);
+    }
+    if w_param.0 as u16 == b'Z' as u16 {
+        // Z pressed on its own goes back, held for a drag it selected.
+        let selected = with_state(h_wnd, |state| std::mem::take(&mut state.zoom_selected))
+            .context("no state")?;
+        if !selected {
+            return view_back(h_wnd);
+        }
+    }
+    Ok(())
+}
+
+/// Returns to the view before the last zoom to a selection.
+fn view_back(h_wnd: HWND) -> Result<()> {
+    if with_state(h_wnd, |state| state.tab_mut().is_some_and(Tab::view_back)).context("no state")? {
+        redraw(h_wnd);
+    }
+    Ok(())
+}
+
+/// Shows the next file in the navigation list, or the previous one.
+fn navigate(h_wnd: HWND, next: bool) -> Result<()> {
+    let file_path = with_state(h_wnd, |state| {
+        let navigation = &mut state.tab_mut()?.navigation;
+        if next {
+            navigation.next().map(Path::to_path_buf)
+        } else {
+            navigation.prev().map(Path::to_path_buf)
+        }
+    })
+    .context("no state")?;
+    let Some(file_path) = file_path else {
+        return Ok(());
+    };
+    read_image(h_wnd, &file_path)?;
+    // a running slideshow gives the chosen image a full interval, a paused one stays paused.
+    let timer = with_state(h_wnd, |state| state.slideshow.navigated()).context("no state")?;
+    slideshow_timer(h_wnd, timer)
+}
+
+fn point(l_param: LPARAM) -> POINT {
+    POINT {
+        x: (l_param.0 & 0xffff) as i16 as i32,
+        y: ((l_param.0 >> 16) & 0xffff) as i16 as i32,
+    }
+}
+
+fn tab_strip(h_wnd: HWND) -> HWND {
+    unsafe { GetDlgItem(h_wnd, ID_TAB_STRIP) }
+}
+
+fn tab_strip_visible(h_wnd: HWND) -> bool {
+    unsafe { IsWindowVisible(tab_strip(h_wnd)) }.as_bool()
+}
+
+/// The client area below the toolbar and tab strip, where the image is drawn.
+fn viewport(h_wnd: HWND) -> RECT {
+    let mut rc = RECT::default();
+    unsafe { GetClientRect(h_wnd, &mut rc) };
+    let mut top = scaled(TOOLBAR_HEIGHT);
+    if tab_strip_visible(h_wnd) {
+        top += scaled(tab_strip::HEIGHT);
+    }
+    rc.top = top.min(rc.bottom);
+    rc
+}
+
+/// Fits the path box and the tab strip to the window width after a resize.
+fn layout(h_wnd: HWND) {
+    let mut rc = RECT::default();
+    unsafe { GetClientRect(h_wnd, &mut rc) };
+    // right of the Open button, to the window edge.
+    path_box::move_to(
+        controls::item(h_wnd, ID_PATH_BOX),
+        scaled(92),
+        scaled(4),
+        rc.right - scaled(96),
+        scaled(24),
+    );
+    let height = scaled(tab_strip::HEIGHT);
+    tab_strip::move_to(
+        tab_strip(h_wnd),
+        0,
+        scaled(TOOLBAR_HEIGHT),
+        rc.right,
+        height,
+    );
+    redraw(h_wnd);
+}
+
+fn redraw(h_wnd: HWND) {
+    // paint covers the whole viewport, so there is nothing to erase.
+    unsafe { InvalidateRect(h_wnd, Some(&viewport(h_wnd)), false) };
+}
+
+/// Drags the image around when it is larger than the window, and out of the window otherwise
+/// or with Ctrl held. With Z held it drags a rectangle to zoom into, with Shift one to mark.
+fn l_button_down(h_wnd: HWND, w_param: WPARAM, l_param: LPARAM) -> Result<()> {
+    // take the keyboard back from the path box.
+    unsafe { SetFocus(h_wnd) };
+    let mut pt = point(l_param);
+    let viewport = viewport(h_wnd);
+    let ctrl = w_param.0 as u32 & MK_CONTROL.0 != 0;
+    let shift = w_param.0 as u32 & MK_SHIFT.0 != 0;
+    let zoom = unsafe { GetKeyState(b'Z' as i32) } < 0;
+    let measure = unsafe { GetKeyState(b'M' as i32) } < 0;
+    let shown = with_state(h_wnd, |state| state.view_mut().is_some()).context("no state")?;
+    if shown && measure {
+        unsafe { SetCapture(h_wnd) };
+        with_state(h_wnd, |state| {
+            let Some(tab) = state.tab_mut() else {
+                return;
+            };
+            if let Some(at) = measure_point(tab, pt, &viewport) {
+                // Ctrl measures a rectangle.
+                tab.set_measure(Some(Measure::new(at, ctrl)));
+                state.measuring = true;
+            }
+        });
+        redraw(h_wnd);
+        return Ok(());
+    }
+    if shown && (zoom || shift) {
+        unsafe { SetCapture(h_wnd) };
+        with_state(h_wnd, |state| {
+            state.selection = Some(Selection::new(pt));
+            state.marking = !zoom;
+            state.zoom_selected |= zoom;
+        });
+        return Ok(());
+    }
+    if !ctrl && with_state(h_wnd, |state| state.can_pan(&viewport)).context("no state")? {
+        unsafe { SetCapture(h_wnd) };
+        with_state(h_wnd, |state| state.pan_from = Some(pt));
+        return Ok(());
+    }
+
+    let image_rect = with_state(h_wnd, |state| {
+        let tab = state.tab()?;
+        tab.image_size()
+            .map(|size| tab.view.image_rect(&viewport, size))
+    })
+    .context("no state")?;
+    if !image_rect.is_some_and(|rc| unsafe { PtInRect(&rc, pt) }.as_bool()) {
+        return Ok(());
+    }
+    unsafe { ClientToScreen(h_wnd, &mut pt) };
+    // returns once the mouse left the system drag rectangle or the button was released.
+    if unsafe { !DragDetect(h_wnd, pt).as_bool() } {
+        return Ok(());
+    }
+    let data = with_state(h_wnd, |state| match state.tab() {
+        Some(tab) => tab
+            .image()
+            .map(|img| {
+                let file_path = tab.is_file().then_some(tab.file_path.as_path());
+                drag_out::data_object(file_path, img)
+            })
+            .transpose(),
+        None => Ok(None),
+    })
+    .context("no state")??;
+    // the drag loop dispatches messages, so the state must not be borrowed here.
+    match data {
+        Some(data) => drag_out::drag(h_wnd, &data),
+        None => Ok(()),
+    }
+}
+
+/// The pixel corner nearest to the client point `pt`, where measurements start and end.
+fn measure_point(tab: &Tab, pt: POINT, viewport: &RECT) -> Option<(f32, f32)> {
+    let size = tab.image_size()?;
+    let at = tab
+        .view
+        .client_to_image((pt.x as f32, pt.y as f32), viewport, size);
+    Some(measure::snap(at, size))
+}
+
+fn l_button_up(h_wnd: HWND) -> Result<()> {
+    if with_state(h_wnd, |state| std::mem::take(&mut state.measuring)).context("no state")? {
+        unsafe { ReleaseCapture() };
+        // a click clears the measurement.
+        with_state(h_wnd, |state| {
+            if let Some(tab) = state.tab_mut() {
+                if tab.measure().is_some_and(Measure::is_empty) {
+                    tab.set_measure(None);
+                }
+            }
+        });
+        redraw(h_wnd);
+        return Ok(());
+    }
+    // taken first, releasing the capture cancels a selection.
+    let (selection, marking) = with_state(h_wnd, |state| {
+        (state.selection.take(), std::mem::take(&mut state.marking))
+    })
+    .context("no state")?;
+    unsafe { ReleaseCapture() };
+    let Some(selection) = selection else {
+        return Ok(());
+    };
+    let degenerate = selection.is_degenerate(scaled(MIN_SELECTION));
+    if marking {
+        // a click clears the mark.
+        mark(h_wnd, (!degenerate).then(|| selection.rect()));
+        request_stats(h_wnd);
+    } else if !degenerate {
+        zoom_to(h_wnd, &selection.rect());
+    }
+    redraw(h_wnd);
+    Ok(())
+}
+
+/// Marks the part of the image inside the client rectangle `rc`, or nothing.
+fn mark(h_wnd: HWND, rc: Option<RECT>) {
+    let viewport = viewport(h_wnd);
+    with_state(h_wnd, |state| {
+        let Some(tab) = state.tab_mut() else {
+            return;
+        };
+        let Some(size) = tab.image_size() else {
+            return;
+        };
+        let region = rc.and_then(|rc| {
+            let (left, top) =
+                tab.view
+                    .client_to_image((rc.left as f32, rc.top as f32), &viewport, size);
+            let (right, bottom) =
+                tab.view
+                    .client_to_image((rc.right as f32, rc.bottom as f32), &viewport, size);
+            // whole pixels touched by the rectangle.
+            let clamp = |v: f32, max: u32| v.clamp(0.0, max as f32) as u32;
+            let region = [
+                clamp(left.floor(), size.0),
+                clamp(top.floor(), size.1),
+                clamp(right.ceil(), size.0),
+                clamp(bottom.ceil(), size.1),
+            ];
+            (region[0] < region[2] && region[1] < region[3]).then_some(region)
+        });
+        tab.set_selection(region);
+    });
+}
+
+/// The marked region of `tab` in client coordinates.
+fn marked_rect(tab: &Tab, viewport: &RECT) -> Option<RECT> {
+    let [left, top, right, bottom] = tab.selection()?;
+    let size = tab.image_size()?;
+    let corner = |x: u32, y: u32| {
+        let (x, y) = tab
+            .view
+            .image_to_client((x as f32, y as f32), viewport, size);
+        POINT {
+            x: x.round() as i32,
+            y: y.round() as i32,
+        }
+    };
+    let (top_left, bottom_right) = (corner(left, top), corner(right, bottom));
+    Some(RECT {
+        left: top_left.x,
+        top: top_left.y,
+        right: bottom_right.x,
+        bottom: bottom_right.y,
+    })
+}
+
+/// The images a difference is computed from, the active tab's and the other's.
+struct DiffPair {
+    image: Weak<DynamicImage>,
+    other: Weak<DynamicImage>,
+}
+
+impl DiffPair {
+    fn new(image: &Arc<DynamicImage>, other: &Arc<DynamicImage>) -> DiffPair {
+        DiffPair {
+            image: Arc::downgrade(image),
+            other: Arc::downgrade(other),
+        }
+    }
+
+    /// Whether these are still the same images, not just the same files.
+    fn is(&self, image: &Arc<DynamicImage>, other: &Arc<DynamicImage>) -> bool {
+        ptr::eq(self.image.as_ptr(), Arc::as_ptr(image))
+            && ptr::eq(self.other.as_ptr(), Arc::as_ptr(other))
+    }
+
+    fn same(&self, pair: &DiffPair) -> bool {
+        self.image.ptr_eq(&pair.image) && self.other.ptr_eq(&pair.other)
+    }
+}
+
+struct DiffCache {
+    pair: DiffPair,
+    diff: Diff,
+    /// The difference as last shown.
+    rendered: Option<(diff::Style, Arc<DynamicImage>)>,
+}
+
+impl DiffCache {
+    fn render(&mut self, style: diff::Style) -> Arc<DynamicImage> {
+        match &self.rendered {
+            Some((rendered, img)) if *rendered == style => img.clone(),
+            _ => {
+                let img = Arc::new(self.diff.render(style));
+                self.rendered = Some((style, img.clone()));
+                img
+            }
+        }
+    }
+}
+
+/// A difference computed on a background thread, see [`update_diff`].
+struct DiffResult {
+    pair: DiffPair,
+    diff: Diff,
+}
+
+/// D, Shift+D and A: turns the difference view on or off, or changes how it shows.
+fn set_diff_style(
+    h_wnd: HWND,
+    change: impl FnOnce(Option<diff::Style>) -> Option<diff::Style>,
+) -> Result<()> {
+    let style = with_state(h_wnd, |state| {
+        let style = change(state.diff_style);
+        state.diff_style = style;
+        if style.is_none() {
+            state.diff = None;
+        }
+        style
+    })
+    .context("no state")?;
+    if style.is_some() && with_state(h_wnd, |state| state.tabs.len() < 2).context("no state")? {
+        flash_title(h_wnd, "open a second image to compare".to_string());
+    }
+    update_diff(h_wnd)
+}
+
+/// While the difference view is on, shows the active tab's difference to the tab active before
+/// it. It is computed once per pair of images on a background thread and arrives as
+/// `WM_DIFF`.
+fn update_diff(h_wnd: HWND) -> Result<()> {
+    // the other tab is decoded again when its pixels were dropped.
+    let reload = with_state(h_wnd, |state| {
+        state.diff_style?;
+        let other = state.diff_other()?;
+        let tab = &state.tabs[other];
+        (!tab.is_loaded()).then(|| (other, tab.file_path.clone(), tab.was_loaded()))
+    })
+    .context("no state")?;
+    if let Some((other, file_path, confirmed)) = reload {
+        let img = load_image(h_wnd, &file_path, confirmed)?;
+        with_state(h_wnd, |state| {
+            if let Some(tab) = state.tabs.get_mut(other) {
+                tab.set_image(&file_path, img);
+            }
+        });
+    }
+
+    let job = with_state(h_wnd, |state| {
+        let images = state.diff_style.and_then(|_| {
+            let other = state.diff_other()?;
+            Some((
+                state.tab()?.shared_image()?,
+                state.tabs[other].shared_image()?,
+            ))
+        });
+        let mut shown = None;
+        let mut job = None;
+        if let (Some(style), Some((image, other))) = (state.diff_style, images) {
+            match &mut state.diff {
+                Some(cache) if cache.pair.is(&image, &other) => shown = Some(cache.render(style)),
+                _ if state
+                    .pending_diff
+                    .as_ref()
+                    .is_some_and(|pair| pair.is(&image, &other)) => {}
+                _ => {
+                    state.pending_diff = Some(DiffPair::new(&image, &other));
+                    job = Some((image, other));
+                }
+            }
+        }
+        let active = state.active;
+        for (i, tab) in state.tabs.iter_mut().enumerate() {
+            tab.show_instead(if i == active { shown.clone() } else { None });
+        }
+        job
+    })
+    .context("no state")?;
+    if let Some((image, other)) = job {
+        thread::spawn(move || {
+            let result = Box::into_raw(Box::new(DiffResult {
+                pair: DiffPair::new(&image, &other),
+                diff: diff::compute(&image, &other),
+            }));
+            let posted =
+                unsafe { PostMessageW(h_wnd, WM_DIFF, WPARAM(0), LPARAM(result as isize)) };
+            // the window may be gone by now.
+            if !posted.as_bool() {
+                drop(unsafe { Box::from_raw(result) });
+            }
+        });
+    }
+    redraw(h_wnd);
+    update_title(h_wnd);
+    Ok(())
+}
+
+fn diff_done(h_wnd: HWND, l_param: LPARAM) -> Result<()> {
+    let result = unsafe { Box::from_raw(l_param.0 as *mut DiffResult) };
+    let DiffResult { pair, diff } = *result;
+    with_state(h_wnd, |state| {
+        if state
+            .pending_diff
+            .as_ref()
+            .is_some_and(|pending| pending.same(&pair))
+        {
+            state.pending_diff = None;
+        }
+        if state.diff_style.is_some() {
+            state.diff = Some(DiffCache {
+                pair,
+                diff,
+                rendered: None,
+            });
+        }
+    });
+    // the tabs may have changed meanwhile.
+    update_diff(h_wnd)
+}
+
+/// Statistics computed on a background thread, see [`request_stats`].
+struct StatsResult {
+    image: Weak<DynamicImage>,
+    region: Option<[u32; 4]>,
+    stats: Stats,
+}
+
+/// Computes the statistics of the active tab's selection or image on a background thread,
+/// unless they are known or already being computed. They arrive as `WM_STATS`.
+fn request_stats(h_wnd: HWND) {
+    let job = with_state(h_wnd, |state| {
+        if !state.stats {
+            return None;
+        }
+        let tab = state.tab().filter(|tab| tab.stats().is_none())?;
+        let img = tab.shared_image()?;
+        let region = tab.selection();
+        let pending = state
+            .pending_stats
+            .as_ref()
+            .is_some_and(|(image, pending)| {
+                ptr::eq(image.as_ptr(), Arc::as_ptr(&img)) && *pending == region
+            });
+        if pending {
+            return None;
+        }
+        state.pending_stats = Some((Arc::downgrade(&img), region));
+        Some((region, img))
+    })
+    .unwrap_or_default();
+    let Some((region, img)) = job else {
+        return;
+    };
+    thread::spawn(move || {
+        let result = Box::into_raw(Box::new(StatsResult {
+            image: Arc::downgrade(&img),
+            region,
+            stats: stats::compute(&img, region),
+        }));
+        let posted = unsafe { PostMessageW(h_wnd, WM_STATS, WPARAM(0), LPARAM(result as isize)) };
+        // the window may be gone by now.
+        if !posted.as_bool() {
+            drop(unsafe { Box::from_raw(result) });
+        }
+    });
+}
+
+fn stats_done(h_wnd: HWND, l_param: LPARAM) -> Result<()> {
+    let result = unsafe { Box::from_raw(l_param.0 as *mut StatsResult) };
+    let StatsResult {
+        image,
+        region,
+        stats,
+    } = *result;
+    with_state(h_wnd, |state| {
+        if state
+            .pending_stats
+            .as_ref()
+            .is_some_and(|(pending, pending_region)| {
+                pending.ptr_eq(&image) && *pending_region == region
+            })
+        {
+            state.pending_stats = None;
+        }
+        // the image, not just the file: it may have been rotated or filtered meanwhile.
+        let of_image = |tab: &&mut Tab| {
+            tab.shared_image()
+                .is_some_and(|img| ptr::eq(image.as_ptr(), Arc::as_ptr(&img)))
+        };
+        for tab in state.tabs.iter_mut().filter(of_image) {
+            tab.set_stats(region, stats.clone());
+        }
+    });
+    // the selection or the file may have changed meanwhile.
+    request_stats(h_wnd);
+    redraw(h_wnd);
+    Ok(())
+}
+
+/// What the info panel shows about the statistics of `tab`.
+fn stats_lines(tab: &Tab) -> Vec<String> {
+    let mut lines = vec![match tab.selection() {
+        Some([left, top, right, bottom]) => format!(
+            "Statistics of the selection, {} x {} at {}, {}:",
+            right - left,
+            bottom - top,
+            left,
+            top
+        ),
+        None => "Statistics of the whole image:".to_string(),
+    }];
+    match tab.stats() {
+        Some(stats) => lines.extend(stats.to_string().lines().map(str::to_string)),
+        None => lines.push("Computing...".to_string()),
+    }
+    lines
+}
+
+/// Copies the statistics shown for the active tab as text.
+fn copy_stats(h_wnd: HWND) -> Result<()> {
+    let text = with_state(h_wnd, |state| {
+        state
+            .tab()
+            .filter(|tab| tab.stats().is_some())
+            .map(|tab| stats_lines(tab).join("\r\n"))
+    })
+    .context("no state")?;
+    match text {
+        Some(text) => clipboard::set_text(h_wnd, &text),
+        None => Ok(()),
+    }
+}
+
+/// Zooms so the part of the image inside the client rectangle `rc` fills the viewport.
+fn zoom_to(h_wnd: HWND, rc: &RECT) {
+    let viewport = viewport(h_wnd);
+    with_state(h_wnd, |state| {
+        let Some(tab) = state.tab_mut() else {
+            return;
+        };
+        let Some(size) = tab.image_size() else {
+            return;
+        };
+        let (left, top) =
+            tab.view
+                .client_to_image((rc.left as f32, rc.top as f32), &viewport, size);
+        let (right, bottom) =
+            tab.view
+                .client_to_image((rc.right as f32, rc.bottom as f32), &viewport, size);
+        tab.push_view();
+        tab.view.frame([left, top, right, bottom], &viewport, size);
+    });
+}
+
+fn mouse_move(h_wnd: HWND, l_param: LPARAM) -> Result<()> {
+    let pt = point(l_param);
+    let viewport = viewport(h_wnd);
+    let straight = unsafe { GetKeyState(VK_SHIFT.0 as i32) } < 0;
+    let moved = with_state(h_wnd, |state| {
+        if state.measuring {
+            let Some(tab) = state.tab_mut() else {
+                return false;
+            };
+            let Some(to) = measure_point(tab, pt, &viewport) else {
+                return false;
+            };
+            if let Some(measure) = tab.measure_mut() {
+                measure.drag_to(to, straight);
+            }
+            return true;
+        }
+        if let Some(selection) = &mut state.selection {
+            selection.cursor = pt;
+            return true;
+        }
+        let Some(from) = state.pan_from else {
+            return false;
+        };
+        state.pan_from = Some(pt);
+        let Some((view, size)) = state.view_mut() else {
+            return false;
+        };
+        let (dx, dy) = ((pt.x - from.x) as f32, (pt.y - from.y) as f32);
+        view.pan_by(dx, dy, &viewport, size);
+        true
+    })
+    .context("no state")?;
+    if moved {
+        redraw(h_wnd);
+    }
+    Ok(())
+}
+
+/// Zooms about the mouse cursor.
+fn mouse_wheel(h_wnd: HWND, w_param: WPARAM, l_param: LPARAM) -> Result<()> {
+    let delta = (w_param.0 >> 16) as i16 as f32 / WHEEL_DELTA as f32;
+    // unlike other mouse messages, the wheel reports screen coordinates.
+    let mut pt = point(l_param);
+    unsafe { ScreenToClient(h_wnd, &mut pt) };
+    zoom_at(h_wnd, WHEEL_ZOOM.powf(delta), pt);
+    Ok(())
+}
+
+fn zoom_at(h_wnd: HWND, factor: f32, pt: POINT) {
+    let viewport = viewport(h_wnd);
+    with_state(h_wnd, |state| {
+        if let Some((view, size)) = state.view_mut() {
+            let anchor = (pt.x as f32, pt.y as f32);
+            view.zoom_at(factor, anchor, &viewport, size);
+        }
+    });
+    redraw(h_wnd);
+}
+
+/// Handles `WM_GESTURE`. Returns false for messages `DefWindowProcW` must see.
+fn gesture(h_wnd: HWND, l_param: LPARAM) -> Result<bool> {
+    let h_info = HGESTUREINFO(l_param.0);
+    let mut info = GESTUREINFO {
+        cbSize: std::mem::size_of::<GESTUREINFO>() as u32,
+        ..Default::default()
+    };
+    if unsafe { !GetGestureInfo(h_info, &mut info).as_bool() }
+        || info.dwID == GID_BEGIN.0
+        || info.dwID == GID_END.0
+    {
+        return Ok(false);
+    }
+    unsafe { CloseGestureInfoHandle(h_info) };
+
+    // the process is DPI aware, so the screen position maps onto client pixels one to one.
+    let mut pt = POINT {
+        x: info.ptsLocation.x as i32,
+        y: info.ptsLocation.y as i32,
+    };
+    unsafe { ScreenToClient(h_wnd, &mut pt) };
+    let viewport = viewport(h_wnd);
+    let action = with_state(h_wnd, |state| {
+        let pannable = state.can_pan(&viewport);
+        state.gestures.interpret(&info, pt, pannable)
+    })
+    .context("no state")?;
+
+    match action {
+        Action::Zoom { factor, center } => zoom_at(h_wnd, factor, center),
+        Action::Pan { dx, dy } => {
+            with_state(h_wnd, |state| {
+                if let Some((view, size)) = state.view_mut() {
+                    view.pan_by(dx as f32, dy as f32, &viewport, size);
+                }
+            });
+            redraw(h_wnd);
+        }
+        Action::Swipe { next } => navigate(h_wnd, next)?,
+        Action::ToggleActualSize { center } => {
+            with_state(h_wnd, |state| {
+                let Some((view, size)) = state.view_mut() else {
+                    return;
+                };
+                if view.is_fit() {
+                    let anchor = (center.x as f32, center.y as f32);
+                    view.set_zoom_at(1.0, anchor, &viewport, size);
+                } else {
+                    view.fit();
+                }
+            });
+            redraw(h_wnd);
+        }
+        Action::None => {}
+    }
+    Ok(true)
+}
+
+/// Does what `timer` says with the slideshow timer.
+fn slideshow_timer(h_wnd: HWND, timer: slideshow::Timer) -> Result<()> {
+    match timer {
+        slideshow::Timer::Set(interval_ms) => ensure!(
+            unsafe { SetTimer(h_wnd, ID_SLIDESHOW_TIMER, interval_ms, None) } != 0,
+            "SetTimer failed."
+        ),
+        slideshow::Timer::Kill => unsafe {
+            KillTimer(h_wnd, ID_SLIDESHOW_TIMER);
+        },
+        slideshow::Timer::Keep => {}
+    }
+    Ok(())
+}
+
+fn start_slideshow(h_wnd: HWND) -> Result<()> {
+    let timer = with_state(h_wnd, |state| state.slideshow.start()).context("no state")?;
+    slideshow_timer(h_wnd, timer).inspect_err(|_| {
+        with_state(h_wnd, |state| state.slideshow.stop());
+    })?;
+    with_state(h_wnd, |state| state.update_taskbar());
+    update_title(h_wnd);
+    Ok(())
+}
+
+fn stop_slideshow(h_wnd: HWND) -> Result<()> {
+    let timer = with_state(h_wnd, |state| state.slideshow.stop()).context("no state")?;
+    if timer != slideshow::Timer::Keep {
+        slideshow_timer(h_wnd, timer)?;
+        with_state(h_wnd, |state| state.update_taskbar());
+        update_title(h_wnd);
+    }
+    Ok(())
+}
+
+/// Pauses or resumes a running slideshow.
+fn toggle_pause(h_wnd: HWND) -> Result<()> {
+    let timer = with_state(h_wnd, |state| state.slideshow.toggle_pause()).context("no state")?;
+    slideshow_timer(h_wnd, timer)?;
+    update_title(h_wnd);
+    Ok(())
+}
+
+/// Lengthens or shortens the slideshow interval, shows it in the title for a moment and
+/// remembers it.
+fn step_interval(h_wnd: HWND, longer: bool) -> Result<()> {
+    let Some((timer, interval_ms)) = with_state(h_wnd, |state| {
+        if !state.slideshow.is_active() {
+            return None;
+        }
+        let timer = state.slideshow.step_interval(longer);
+        Some((timer, state.slideshow.interval_ms()))
+    })
+    .context("no state")?
+    else {
+        return Ok(());
+    };
+    slideshow_timer(h_wnd, timer)?;
+    flash_title(
+        h_wnd,
+        format!("{} s per image", slideshow::seconds(interval_ms)),
+    );
+    let mut settings = settings();
+    if settings.slideshow_interval_ms != interval_ms {
+        settings.slideshow_interval_ms = interval_ms;
+        settings.save()?;
+    }
+    Ok(())
+}
+
+fn slideshow_tick(h_wnd: HWND) -> Result<()> {
+    let file_path = with_state(h_wnd, |state| {
+        state.tab_mut()?.navigation.next().map(Path::to_path_buf)
+    })
+    .context("no state")?;
+    let Some(file_path) = file_path else {
+        return stop_slideshow(h_wnd);
+    };
+    // the timer keeps firing behind an error box, so stop before reporting.
+    read_image(h_wnd, &file_path).inspect_err(|_| {
+        stop_slideshow(h_wnd).ok();
+    })
+}
+
+/// Opens the files another pinion process handed over. Returns whether the message carried
+/// them, the sender opens them itself otherwise.
+fn copy_data(h_wnd: HWND, l_param: LPARAM) -> bool {
+    let cds = unsafe { &*(l_param.0 as *const COPYDATASTRUCT) };
+    let Some(files) = instance::received_files(cds) else {
+        return false;
+    };
+    restore_window(h_wnd);
+    open_files(h_wnd, files).map_err(|e| msg_box(h_wnd, e)).ok();
+    true
+}
+
+/// Hides the minimized window behind an icon in the notification area. It stays a normal
+/// minimized window when the icon cannot be added.
+fn to_tray(h_wnd: HWND) {
+    if with_state(h_wnd, |state| state.in_tray).unwrap_or_default() || !tray::add(h_wnd) {
+        return;
+    }
+    with_state(h_wnd, |state| state.in_tray = true);
+    unsafe { ShowWindow(h_wnd, SW_HIDE) };
+}
+
+/// Shows a minimized window again, also one in the notification area.
+fn restore_window(h_wnd: HWND) {
+    if with_state(h_wnd, |state| std::mem::take(&mut state.in_tray)).unwrap_or_default() {
+        tray::remove(h_wnd);
+        unsafe { ShowWindow(h_wnd, SW_SHOW) };
+    }
+    unsafe {
+        if IsIconic(h_wnd).as_bool() {
+            ShowWindow(h_wnd, SW_RESTORE);
+        }
+    }
+}
+
+/// Clicks on the notification area icon: the left button restores the window, the right one
+/// shows the icon's menu.
+fn tray_message(h_wnd: HWND, l_param: LPARAM) -> Result<()> {
+    match l_param.0 as u32 {
+        WM_LBUTTONUP => {
+            restore_window(h_wnd);
+            unsafe { SetForegroundWindow(h_wnd) };
+        }
+        WM_RBUTTONUP => {
+            let (active, paused) = with_state(h_wnd, |state| {
+                (state.slideshow.is_active(), state.slideshow.is_paused())
+            })
+            .context("no state")?;
+            match tray::menu(h_wnd, active, paused) {
+                Some(tray::Command::Restore) => restore_window(h_wnd),
+                Some(tray::Command::PauseSlideshow) => toggle_pause(h_wnd)?,
+                Some(tray::Command::Open) => {
+                    restore_window(h_wnd);
+                    if let Some(file_path) = choose_file(h_wnd)? {
+                        open_file(h_wnd, &file_path)?;
+                    }
+                }
+                Some(tray::Command::Exit) => for_each_window(|h_wnd| unsafe {
+                    PostMessageW(h_wnd, WM_CLOSE, WPARAM::default(), LPARAM::default());
+                }),
+                None => {}
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The navigation list of the folder of `file_path`. With `same_extension` it holds the files
+/// of that extension, otherwise those of the `navigate` setting.
+fn folder_navigation(file_path: &Path, same_extension: bool) -> Navigation {
+    let extensions: Vec<String> = if same_extension {
+        file_path
+            .extension()
+            .map(|ext| vec![ext.to_string_lossy().into_owned()])
+            .unwrap_or_default()
+    } else {
+        settings()
+            .navigate_extensions
+            .clone()
+            .unwrap_or_else(|| EXTENSIONS.map(String::from).to_vec())
+    };
+    Navigation::folder(file_path, &extensions)
+        .unwrap_or_else(|_| Navigation::playlist(vec![file_path.into()]))
+}
+
+/// Restricts the folder navigation of every tab to the extension of its file, or back to the
+/// `navigate` setting. Playlists are left as they are.
+fn toggle_same_extension(h_wnd: HWND) -> Result<()> {
+    let (same_extension, folders, ext) = with_state(h_wnd, |state| {
+        state.same_extension = !state.same_extension;
+        let folders: Vec<(usize, PathBuf)> = state
+            .tabs
+            .iter()
+            .enumerate()
+            .filter(|(_, tab)| tab.is_file() && tab.navigation.is_folder())
+            .map(|(i, tab)| (i, tab.file_path.clone()))
+            .collect();
+        let ext = state
+            .tab()
+            .and_then(|tab| tab.file_path.extension())
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        (state.same_extension, folders, ext)
+    })
+    .context("no state")?;
+    let navigations: Vec<(usize, Navigation)> = folders
+        .into_iter()
+        .map(|(i, file_path)| (i, folder_navigation(&file_path, same_extension)))
+        .collect();
+    with_state(h_wnd, |state| {
+        for (i, navigation) in navigations {
+            if let Some(tab) = state.tabs.get_mut(i) {
+                tab.navigation = navigation;
+            }
+        }
+        state.update_taskbar();
+    });
+    let note = match (same_extension, ext) {
+        (true, Some(ext)) => format!("only .{ext} files"),
+        (true, None) => "only files of the same type".to_string(),
+        (false, _) => "all files".to_string(),
+    };
+    flash_title(h_wnd, note);
+    Ok(())
+}
+
+/// Shows only `channel` of the active tab, or its whole image with `None`.
+fn isolate_channel(h_wnd: HWND, channel: Option<Channel>) -> Result<()> {
+    with_state(h_wnd, |state| {
+        if let Some(tab) = state.tab_mut() {
+            tab.isolate(channel);
+        }
+    });
+    update_title(h_wnd);
+    redraw(h_wnd);
+    Ok(())
+}
+
+/// Switches the active tab between smooth and nearest neighbor display scaling.
+fn toggle_scaling(h_wnd: HWND) -> Result<()> {
+    let Some(scaling) =
+        with_state(h_wnd, |state| state.tab_mut().map(Tab::toggle_scaling)).context("no state")?
+    else {
+        return Ok(());
+    };
+    flash_title(h_wnd, scaling_name(scaling).to_string());
+    redraw(h_wnd);
+    Ok(())
+}
+
+fn scaling_name(scaling: Scaling) -> &'static str {
+    match scaling {
+        Scaling::Smooth => "smooth scaling",
+        Scaling::Nearest => "nearest neighbor",
+    }
+}
+
+/// Opens `file_path` and makes its folder the navigation list.
+fn open_file(h_wnd: HWND, file_path: &Path) -> Result<()> {
+    read_image(h_wnd, file_path)?;
+    remember(file_path);
+    let same_extension = with_state(h_wnd, |state| state.same_extension).context("no state")?;
+    let navigation = folder_navigation(file_path, same_extension);
+    with_state(h_wnd, |state| {
+        if let Some(tab) = state.tab_mut() {
+            tab.navigation = navigation;
+        }
+    });
+    Ok(())
+}
+
+/// Opens `file_path` in a new tab, which navigates its folder.
+fn open_in_new_tab(h_wnd: HWND, file_path: &Path) -> Result<()> {
+    let img = load_image(h_wnd, file_path, false)?;
+    let same_extension = with_state(h_wnd, |state| state.same_extension).context("no state")?;
+    let navigation = folder_navigation(file_path, same_extension);
+    with_state(h_wnd, |state| {
+        let mut tab = Tab::new(file_path, navigation);
+        tab.set_image(file_path, img);
+        state.tabs.push(tab);
+        state.activate(state.tabs.len() - 1);
+        state.trim_cache();
+    });
+    remember(file_path);
+    tabs_changed(h_wnd)
+}
+
+/// Shows tab `index`, decoding its file again if the cache dropped it.
+fn activate_tab(h_wnd: HWND, index: usize) -> Result<()> {
+    let reload = with_state(h_wnd, |state| {
+        state.activate(index);
+        state
+            .tab()
+            .filter(|tab| !tab.is_loaded())
+            .map(|tab| (tab.file_path.clone(), tab.was_loaded()))
+    })
+    .context("no state")?;
+    if let Some((file_path, confirmed)) = reload {
+        let img = load_image(h_wnd, &file_path, confirmed);
+        if let Ok(img) = img {
+            with_state(h_wnd, |state| {
+                if let Some(tab) = state.tab_mut() {
+                    tab.set_image(&file_path, img);
+                }
+                state.trim_cache();
+            });
+        } else {
+            // still switch, so the strip and the title match.
+            tabs_changed(h_wnd)?;
+            return img.map(|_| ());
+        }
+    }
+    with_state(h_wnd, |state| state.update_taskbar());
+    tabs_changed(h_wnd)
+}
+
+/// Ctrl+Tab and Ctrl+Shift+Tab.
+fn cycle_tab(h_wnd: HWND, back: bool) -> Result<()> {
+    let index = with_state(h_wnd, |state| {
+        let len = state.tabs.len();
+        (len > 1).then(|| {
+            if back {
+                (state.active + len - 1) % len
+            } else {
+                (state.active + 1) % len
+            }
+        })
+    })
+    .context("no state")?;
+    match index {
+        Some(index) => activate_tab(h_wnd, index),
+        None => Ok(()),
+    }
+}
+
+/// Closes the active tab. Closing the last one returns to the empty window.
+fn close_tab(h_wnd: HWND) -> Result<()> {
+    let next = with_state(h_wnd, |state| {
+        if state.tabs.is_empty() {
+            return None;
+        }
+        state.tabs.remove(state.active);
+        Some(state.active.min(state.tabs.len().saturating_sub(1)))
+    })
+    .context("no state")?;
+    match next {
+        Some(index) if with_state(h_wnd, |state| !state.tabs.is_empty()).context("no state")? => {
+            activate_tab(h_wnd, index)
+        }
+        Some(_) => {
+            stop_slideshow(h_wnd)?;
+            tabs_changed(h_wnd)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Brings the tab strip, the title and the image in line with the tabs.
+fn tabs_changed(h_wnd: HWND) -> Result<()> {
+    let (titles, active) = with_state(h_wnd, |state| {
+        let titles: Vec<String> = state.tabs.iter().map(Tab::title).collect();
+        (titles, state.active)
+    })
+    .context("no state")?;
+    let was_visible = tab_strip_visible(h_wnd);
+    tab_strip::sync(tab_strip(h_wnd), &titles, active);
+    if was_visible != tab_strip_visible(h_wnd) {
+        // the viewport moved, the strip itself repaints.
+        unsafe { InvalidateRect(h_wnd, None, true) };
+    }
+    redraw(h_wnd);
+    update_title(h_wnd);
+    update_path_box(h_wnd);
+    request_stats(h_wnd);
+    update_diff(h_wnd)
+}
+
+/// Shows `note` after the title for a moment.
+fn flash_title(h_wnd: HWND, note: String) {
+    with_state(h_wnd, |state| state.title_note = Some(note));
+    unsafe { SetTimer(h_wnd, ID_TITLE_NOTE_TIMER, TITLE_NOTE_MS, None) };
+    update_title(h_wnd);
+}
+
+/// Shows the active tab in the title, followed by the channel shown alone, the difference view
+/// and the state of the slideshow.
+fn update_title(h_wnd: HWND) {
+    let title = with_state(h_wnd, |state| {
+        let mut title = match state.tab() {
+            Some(tab) if tab.is_file() => long_path::to_wide(&long_path::display(&tab.file_path)),
+            Some(tab) => l(&tab.title()),
+            None => l(&app_title()),
+        };
+        let channel = state
+            .tab()
+            .and_then(Tab::channel)
+            .map(|channel| format!("{} channel", channel.name()));
+        // applied filters stay when the file is reloaded, so they are kept in sight.
+        let filters = state
+            .tab()
+            .filter(|tab| !tab.filters().is_empty())
+            .map(|tab| {
+                let names: Vec<&str> = tab.filters().iter().map(|filter| filter.name()).collect();
+                format!("{} applied", names.join(", "))
+            });
+        let diff = state.diff_style.map(|style| {
+            let status = match (&state.diff, state.tab()) {
+                (Some(cache), Some(tab)) if tab.is_replaced() => cache.diff.summary(),
+                _ if state.pending_diff.is_some() => "comparing...".to_string(),
+                _ => "no second image".to_string(),
+            };
+            format!("{}: {status}", style.name())
+        });
+        let note = match (&state.title_note, state.slideshow.is_paused()) {
+            (Some(note), _) => Some(note.as_str()),
+            (None, true) => Some("slideshow paused"),
+            (None, false) => None,
+        };
+        let notes = channel
+            .as_deref()
+            .into_iter()
+            .chain(filters.as_deref())
+            .chain(diff.as_deref());
+        for note in notes.chain(note) {
+            // before the terminating zero.
+            title.pop();
+            title.extend(format!(" - {note}").encode_utf16());
+            title.push(0);
+        }
+        title
+    })
+    .unwrap_or_default();
+    unsafe { SetWindowTextW(h_wnd, PCWSTR::from_raw(title.as_ptr())) };
+}
+
+/// Opens the first of `files` and makes all of them the navigation list. A single file
+/// navigates its folder instead.
+///
+/// Files that don't exist are skipped and reported together afterwards.
+fn open_files(h_wnd: HWND, files: Vec<PathBuf>) -> Result<()> {
+    let (files, missing): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| long_path::extended(file).is_file());
+    if let [file_path] = &files[..] {
+        open_file(h_wnd, file_path)?;
+    } else {
+        show_playlist(h_wnd, files)?;
+    }
+    report_missing(&missing)
+}
+
+/// Opens the first of the files of a playlist and steps through all of them, even just one.
+/// Files that don't exist are skipped and reported together afterwards.
+fn open_playlist(h_wnd: HWND, files: Vec<PathBuf>) -> Result<()> {
+    let (files, missing): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| long_path::extended(file).is_file());
+    show_playlist(h_wnd, files)?;
+    report_missing(&missing)
+}
+
+/// Shows the first of `files` with all of them as the navigation list.
+fn show_playlist(h_wnd: HWND, files: Vec<PathBuf>) -> Result<()> {
+    let Some(file_path) = files.first().cloned() else {
+        return Ok(());
+    };
+    read_image(h_wnd, &file_path)?;
+    with_state(h_wnd, |state| {
+        if let Some(tab) = state.tab_mut() {
+            tab.navigation = Navigation::playlist(files);
+        }
+    });
+    remember(&file_path);
+    Ok(())
+}
+
+/// Saves the navigation list of the active tab as a playlist for `--list`.
+fn save_list(h_wnd: HWND) -> Result<()> {
+    let Some(files) = with_state(h_wnd, |state| {
+        let files = state.tab()?.navigation.files().to_vec();
+        (!files.is_empty()).then_some(files)
+    })
+    .context("no state")?
+    else {
+        return Ok(());
+    };
+    let dir = files[0].parent().unwrap_or(Path::new("."));
+    let proposed = std::path::absolute(dir)?.join("playlist.txt");
+    let Some(target) = save_list_dialog(h_wnd, &proposed)? else {
+        return Ok(());
+    };
+    let files: Vec<PathBuf> = files
+        .iter()
+        .map(|file| std::path::absolute(file).unwrap_or_else(|_| file.clone()))
+        .collect();
+    playlist::save(&long_path::extended(&target), &files)
+}
+
+fn report_missing(missing: &[PathBuf]) -> Result<()> {
+    if !missing.is_empty() {
+        let list = missing
+            .iter()
+            .map(|file| long_path::display(file).display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(anyhow!(
+            "Skipped {} file(s) that don't exist:\n{}",
+            missing.len(),
+            list
+        ));
+    }
+    Ok(())
+}
+
+/// A single dropped file replaces the current image, several open in new tabs.
+fn drop_files(h_wnd: HWND, h_drop: HDROP) -> Result<()> {
+    let count = unsafe { DragQueryFileW(h_drop, u32::MAX, None) };
+    let files: Vec<PathBuf> = (0..count)
+        .map(|i| {
+            // query the length first, dropped paths are not limited to MAX_PATH.
+            let len = unsafe { DragQueryFileW(h_drop, i, None) } as usize;
+            let mut buf = vec![0u16; len + 1];
+            unsafe { DragQueryFileW(h_drop, i, Some(&mut buf)) };
+            PathBuf::from(OsString::from_wide(&buf[..len]))
+        })
+        .collect();
+    unsafe { DragFinish(h_drop) };
+
+    if let [file_path] = &files[..] {
+        return open_file(h_wnd, file_path);
+    }
+    let mut errors = Vec::new();
+    for file_path in files.iter().filter(|file| navigation::is_supported(file)) {
+        if let Err(e) = open_in_new_tab(h_wnd, file_path) {
+            errors.push(format!(
+                "{}: {}",
+                long_path::display(file_path).display(),
+                e
+            ));
+        }
+    }
+    ensure!(errors.is_empty(), "{}", errors.join("\n"));
+    Ok(())
+}
+
+/// Reads `path` in chunks, reporting the bytes read so far and the file size.
+fn read_file(path: &Path, progress: &mut dyn FnMut(u64, u64)) -> Result<Vec<u8>> {
+    const CHUNK: u64 = 1024 * 1024;
+    let mut file = File::open(path)?;
+    let total = file.metadata()?.len();
+    let mut buf = Vec::with_capacity(total as usize);
+    while (&mut file).take(CHUNK).read_to_end(&mut buf)? > 0 {
+        progress(buf.len() as u64, total);
+    }
+    Ok(buf)
+}
+
+/// Decodes `file_path`, reporting the bytes read as `(done, total)`. The decoders can't tell
+/// how far they are, decoding follows once `done` reaches `total`.
+fn open_image(file_path: &Path, progress: &mut dyn FnMut(u64, u64)) -> Result<DynamicImage> {
+    let ext = file_path.extension().context("no extension")?;
+    let path = long_path::extended(file_path);
+    let raw = read_file(&path, progress)?;
+    let img = if ext.eq_ignore_ascii_case("lz4i") {
+        decode_lz4i(&raw)?
+    } else {
+        image::load_from_memory_with_format(&raw, ImageFormat::from_path(file_path)?)?
+    };
+    Ok(img)
+}
+
+/// The user chose not to open a file. Not reported, the user knows.
+#[derive(Debug)]
+struct Declined;
+
+impl fmt::Display for Declined {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The file was not opened.")
+    }
+}
+
+impl std::error::Error for Declined {}
+
+const MB: u64 = 1024 * 1024;
+
+/// `bytes` for people, such as `8.2 GB`.
+fn file_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["bytes", "KB", "MB", "GB"] {
+        if size < 1024.0 {
+            return if unit == "bytes" {
+                format!("{bytes} bytes")
+            } else {
+                format!("{size:.1} {unit}")
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1} TB")
+}
+
+/// The image size from the header of `path`, when it can be read without decoding.
+fn probe_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let mut head = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(probe::PROBE_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    probe::dimensions(&head)
+}
+
+/// Refuses a file larger than the `max_size` setting and asks before opening one larger than
+/// `confirm_size`, unless it was `confirmed` before.
+fn check_size(h_wnd: HWND, file_path: &Path, size: u64, confirmed: bool) -> Result<()> {
+    let (confirm_mb, max_mb) = {
+        let settings = settings();
+        (settings.confirm_size_mb, settings.max_size_mb)
+    };
+    let over_max = max_mb.is_some_and(|max| size > max.saturating_mul(MB));
+    let ask = confirm_mb > 0
+        && size > confirm_mb.saturating_mul(MB)
+        && !confirmed
+        && !OPEN_LARGE_FILES.load(Ordering::Relaxed);
+    if !over_max && !ask {
+        return Ok(());
+    }
+    let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let dimensions = probe_dimensions(&long_path::extended(file_path))
+        .map(|(width, height)| format!(", {width} x {height} pixels"))
+        .unwrap_or_default();
+    let described = format!("{name} is {}{dimensions}", file_size(size));
+    if let (true, Some(max)) = (over_max, max_mb) {
+        return Err(anyhow!("{described}, larger than the limit of {max} MB."));
+    }
+    let (open, dont_ask) = confirm::ask(
+        h_wnd,
+        "Large file",
+        &format!("{described}. Open it anyway?"),
+        "Open",
+        "Don't ask again until pinion closes",
+        unsafe { H_FONT.context("no font")? },
+    );
+    if !open {
+        return Err(Declined.into());
+    }
+    if dont_ask {
+        OPEN_LARGE_FILES.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// Decodes `file_path`, showing the progress of reading large files on the taskbar button and
+/// a moving bar while they decode. Files above the size settings are refused, or confirmed
+/// first unless `confirmed` when the tab opened them before.
+fn load_image(h_wnd: HWND, file_path: &Path, confirmed: bool) -> Result<DynamicImage> {
+    let size = fs::metadata(long_path::extended(file_path)).map_or(0, |m| m.len());
+    check_size(h_wnd, file_path, size, confirmed)?;
+    let large = size >= LARGE_FILE;
+    let img = open_image(file_path, &mut |done, total| {
+        if large {
+            with_state(h_wnd, |state| {
+                if done < total {
+                    state.taskbar.set_progress(done, total);
+                } else {
+                    state.taskbar.set_busy();
+                }
+            });
+        }
+    });
+    // clears the progress, on failure too.
+    with_state(h_wnd, |state| state.update_taskbar());
+    img
+}
+
+/// Shows `file_path` in the active tab, or in a first tab when none is open.
+fn read_image(h_wnd: HWND, file_path: &Path) -> Result<()> {
+    let img = load_image(h_wnd, file_path, false)?;
+    with_state(h_wnd, |state| {
+        if state.tabs.is_empty() {
+            state.tabs.push(Tab::new(file_path, Navigation::default()));
+            state.activate(0);
+        }
+        if let Some(tab) = state.tab_mut() {
+            tab.set_image(file_path, img);
+            tab.reset_view();
+        }
+        state.trim_cache();
+    });
+    tabs_changed(h_wnd)
+}
+
+fn save_session(h_wnd: HWND) -> Result<()> {
+    // pasted images are gone with the window.
+    let session = with_state(h_wnd, |state| Session {
+        tabs: state
+            .tabs
+            .iter()
+            .filter(|tab| tab.is_file())
+            .map(|tab| SessionTab {
+                file_path: std::path::absolute(&tab.file_path)
+                    .unwrap_or_else(|_| tab.file_path.clone()),
+                zoom: tab.view.zoom(),
+                scaling: tab.chosen_scaling(),
+                rotation: tab.rotation(),
+            })
+            .collect(),
+        active: state.tabs[..state.active.min(state.tabs.len())]
+            .iter()
+            .filter(|tab| tab.is_file())
+            .count(),
+    })
+    .context("no state")?;
+    session.save()
+}
+
+/// Reopens the tabs of the last session. Only the tab in front is decoded now, the others
+/// when they are first activated.
+fn restore_session(h_wnd: HWND) -> Result<()> {
+    let session = Session::load();
+    if session.tabs.is_empty() {
+        return Ok(());
+    }
+    with_state(h_wnd, |state| {
+        let same_extension = state.same_extension;
+        state.tabs = session
+            .tabs
+            .iter()
+            .map(|saved| {
+                let navigation = folder_navigation(&saved.file_path, same_extension);
+                let mut tab = Tab::new(&saved.file_path, navigation);
+                tab.view = View::with_zoom(saved.zoom);
+                tab.set_scaling(saved.scaling);
+                tab.set_rotation(saved.rotation);
+                tab
+            })
+            .collect();
+    });
+    activate_tab(h_wnd, session.active)
+}
+
+/// Records an explicitly opened file in the settings and the jump list.
+fn remember(file_path: &Path) {
+    // failing to persist must not turn a successful load into an error.
+    let changed = settings().remember_file(file_path).unwrap_or(false);
+    if changed {
+        refresh_jump_list();
+    }
+}
+
+fn refresh_jump_list() {
+    let recent = {
+        let mut settings = settings();
+        settings.prune_recent().ok();
+        settings.recent.clone()
+    };
+    jump_list::refresh(&recent).map_err(log_error).ok();
+}
+
+fn paint(h_wnd: HWND) -> Result<()> {
+    let mut ps = PAINTSTRUCT::default();
+    let hdc = unsafe { BeginPaint(h_wnd, &mut ps) };
+    let viewport = viewport(h_wnd);
+    let width = viewport.right - viewport.left;
+    let height = viewport.bottom - viewport.top;
+
+    // draw off-screen first, so panning and zooming don't flicker.
+    unsafe {
+        let h_mdc = CreateCompatibleDC(hdc);
+        let h_bmp = CreateCompatibleBitmap(hdc, width, height);
+        let old = SelectObject(h_mdc, h_bmp);
+        SetViewportOrgEx(h_mdc, -viewport.left, -viewport.top, None);
+        with_state(h_wnd, |state| render(state, HDC(h_mdc.0), &viewport, true));
+        SetViewportOrgEx(h_mdc, 0, 0, None);
+        BitBlt(
+            hdc,
+            viewport.left,
+            viewport.top,
+            width,
+            height,
+            h_mdc,
+            0,
+            0,
+            SRCCOPY,
+        );
+        SelectObject(h_mdc, old);
+        DeleteObject(h_bmp);
+        DeleteDC(h_mdc);
+        EndPaint(h_wnd, &ps);
+    }
+    Ok(())
+}
+
+/// Draws the active tab as the window shows it into `hdc`, filling `viewport`. With `overlays`
+/// the selection and the info panel go on top.
+fn render(state: &mut AppState, hdc: HDC, viewport: &RECT, overlays: bool) {
+    unsafe { FillRect(hdc, viewport, theme::background()) };
+    if let Some(tab) = state.tabs.get_mut(state.active) {
+        tab.draw(hdc, viewport, state.resizing, state.color.as_ref());
+    }
+    if !overlays {
+        return;
+    }
+    if state.grid {
+        draw_grid(state, hdc, viewport);
+    }
+    if let Some(tab) = state.tab() {
+        draw_measure(tab, hdc, viewport);
+    }
+    let marked = state.tab().and_then(|tab| marked_rect(tab, viewport));
+    let selection = state.selection.or(marked.map(|rc| Selection {
+        anchor: POINT {
+            x: rc.left,
+            y: rc.top,
+        },
+        cursor: POINT {
+            x: rc.right,
+            y: rc.bottom,
+        },
+    }));
+    if let Some(selection) = selection {
+        selection.draw(hdc);
+    }
+    if let (true, Some(font)) = (state.info || state.stats, unsafe { H_FONT }) {
+        let lines = info_lines(state, viewport);
+        info_panel::draw(hdc, viewport, &lines, font, scaled(4));
+    }
+}
+
+/// Draws the measurement of `tab` with its numbers next to its end.
+fn draw_measure(tab: &Tab, hdc: HDC, viewport: &RECT) {
+    let (Some(measure), Some(size)) = (tab.measure(), tab.image_size()) else {
+        return;
+    };
+    let to_client = |pt| {
+        let (x, y) = tab.view.image_to_client(pt, viewport, size);
+        POINT {
+            x: x.round() as i32,
+            y: y.round() as i32,
+        }
+    };
+    let (from, to) = (to_client(measure.from), to_client(measure.to));
+    measure.draw(hdc, from, to);
+    if let Some(font) = unsafe { H_FONT } {
+        let at = POINT {
+            x: to.x + scaled(12),
+            y: to.y + scaled(12),
+        };
+        let lines = [measure.describe()];
+        info_panel::draw_at(hdc, at, viewport, &lines, font, scaled(4));
+    }
+}
+
+/// Draws the pixel grid over the active tab as `render` drew it into `viewport`.
+fn draw_grid(state: &AppState, hdc: HDC, viewport: &RECT) {
+    let Some(tab) = state.tab() else {
+        return;
+    };
+    if let Some(size) = tab.image_size() {
+        let rect = tab.view.image_rect(viewport, size);
+        let guide = settings().grid_guide;
+        grid::draw(hdc, viewport, &rect, size, guide);
+    }
+}
+
+/// Renders the view of the active tab off-screen, `scale` times the size of the window, without
+/// the selection and the info panel. The pixel grid is drawn only with `grid`.
+fn render_view(h_wnd: HWND, scale: u32, grid: bool) -> Result<RgbaImage> {
+    let viewport = viewport(h_wnd);
+    let width = (viewport.right - viewport.left).max(1) as u32 * scale;
+    let height = (viewport.bottom - viewport.top).max(1) as u32 * scale;
+    let target = RECT {
+        left: 0,
+        top: 0,
+        right: width as i32,
+        bottom: height as i32,
+    };
+    with_state(h_wnd, |state| {
+        let tab = state
+            .tab_mut()
+            .filter(|tab| tab.is_loaded())
+            .context("no image")?;
+        let size = tab
+            .image()
+            .map(|img| (img.width(), img.height()))
+            .context("no image")?;
+        let view = tab.view;
+        if scale > 1 {
+            tab.view = view.magnified(scale as f32, &viewport, size);
+        }
+        // the display copies are converted to the monitor profile, the export stays sRGB.
+        let color = state.color.take();
+        if color.is_some() {
+            state.tab_mut().context("no tab")?.invalidate_display();
+        }
+        let img = dib::capture(width, height, |hdc| {
+            render(state, hdc, &target, false);
+            if grid {
+                draw_grid(state, hdc, &target);
+            }
+        });
+        if let Some(tab) = state.tab_mut() {
+            tab.view = view;
+            if color.is_some() {
+                tab.invalidate_display();
+            }
+        }
+        state.color = color;
+        img
+    })
+    .context("no state")?
+}
+
+/// Saves the view of the active tab as an image, at a multiple of the window size chosen in the
+/// save dialog.
+fn export_view(h_wnd: HWND) -> Result<()> {
+    let Some((file_path, mut scale, showing_grid)) = with_state(h_wnd, |state| {
+        let tab = state.tab().filter(|tab| tab.is_loaded())?;
+        let stem = tab
+            .file_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let name = format!("{stem} view.png");
+        Some((
+            tab.file_path.with_file_name(name),
+            state.export_scale,
+            state.grid,
+        ))
+    })
+    .context("no state")?
+    else {
+        return Ok(());
+    };
+    // offered only while it shows, and off unless asked for.
+    let mut grid = false;
+    let Some(target) = export_dialog(
+        h_wnd,
+        &file_path,
+        &mut scale,
+        showing_grid.then_some(&mut grid),
+    )?
+    else {
+        return Ok(());
+    };
+    with_state(h_wnd, |state| state.export_scale = scale);
+    let img = DynamicImage::ImageRgba8(render_view(h_wnd, scale, grid)?);
+    save_image(&img, &long_path::extended(&target), JPEG_QUALITY)
+}
+
+/// Copies the view of the active tab, at the size last chosen for exporting.
+fn copy_view(h_wnd: HWND) -> Result<()> {
+    let scale = with_state(h_wnd, |state| state.export_scale).context("no state")?;
+    let img = DynamicImage::ImageRgba8(render_view(h_wnd, scale, false)?);
+    clipboard::set_image(h_wnd, &img)
+}
+
+/// What the info panel shows about the active tab.
+fn info_lines(state: &AppState, viewport: &RECT) -> Vec<String> {
+    let mut lines = Vec::new();
+    if !state.info {
+        return state.tab().map(stats_lines).unwrap_or_default();
+    }
+    if let Some(tab) = state.tab() {
+        lines.push(if tab.is_file() {
+            tab.file_path.display().to_string()
+        } else {
+            tab.title()
+        });
+        if let Some(img) = tab.image() {
+            let size = (img.width(), img.height());
+            lines.push(format!("{} x {} pixels, {:?}", size.0, size.1, img.color()));
+            let scale = tab.view.scale(viewport, size);
+            lines.push(format!(
+                "Zoom {:.0}%, {}",
+                scale * 100.0,
+                scaling_name(tab.scaling())
+            ));
+        }
+        if !tab.filters().is_empty() {
+            let names: Vec<&str> = tab.filters().iter().map(|filter| filter.name()).collect();
+            lines.push(format!("Filters: {}", names.join(", ")));
+        }
+    }
+    lines.push(match &state.color {
+        Some(color) => format!("Color profile: {}", color.profile),
+        None if settings().color_management => "Color profile: sRGB".to_string(),
+        None => "Color management: off".to_string(),
+    });
+    if state.watching_clipboard {
+        lines.push("Showing images copied to the clipboard".to_string());
+    }
+    if let Some(tab) = state.tab().filter(|_| state.stats) {
+        lines.extend(stats_lines(tab));
+    }
+    lines
+}
+
+fn app_title() -> String {
+    format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+/// Converts a length at 96 DPI to screen pixels.
+fn scaled(px: i32) -> i32 {
+    px * unsafe { DPI } / 96
+}
+
+fn msg_box(h_wnd: HWND, e: Error) {
+    if e.is::<Declined>() {
+        return;
+    }
+    unsafe {
+        MessageBoxW(
+            h_wnd,
+            PCWSTR::from_raw(l(&e.to_string()).as_ptr()),
             w!("Error"),
             MB_OK,
         )
     };
-    Ok(())
+}
+
+/// For failures that must not interrupt the user.
+fn log_error(e: Error) {
+    if e.is::<Declined>() {
+        return;
+    }
+    let text = format!("pinion: {e:#}\n");
+    unsafe { OutputDebugStringW(PCWSTR::from_raw(l(&text).as_ptr())) };
 }
 
 fn l(source: &str) -> Vec<u16> {
@@ -0,0 +1,133 @@
+use crate::selection::Selection;
+use windows::Win32::{
+    Foundation::{COLORREF, POINT},
+    Graphics::Gdi::{CreatePen, DeleteObject, LineTo, MoveToEx, SelectObject, HDC, PS_SOLID},
+};
+
+/// A distance measured by dragging with M held, between two pixel corners of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measure {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    /// Shows the rectangle spanned by the ends rather than the line between them.
+    pub rectangle: bool,
+}
+
+/// The pixel corner of an image of `size` nearest to the image point `pt`.
+pub fn snap(pt: (f32, f32), size: (u32, u32)) -> (f32, f32) {
+    (
+        pt.0.round().clamp(0.0, size.0 as f32),
+        pt.1.round().clamp(0.0, size.1 as f32),
+    )
+}
+
+impl Measure {
+    pub fn new(at: (f32, f32), rectangle: bool) -> Measure {
+        Measure {
+            from: at,
+            to: at,
+            rectangle,
+        }
+    }
+
+    /// Moves the end to `to`. With `straight` the line stays horizontal or vertical, whichever
+    /// is closer.
+    pub fn drag_to(&mut self, to: (f32, f32), straight: bool) {
+        let (dx, dy) = (to.0 - self.from.0, to.1 - self.from.1);
+        self.to = match straight {
+            true if dx.abs() >= dy.abs() => (to.0, self.from.1),
+            true => (self.from.0, to.1),
+            false => to,
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.from == self.to
+    }
+
+    pub fn width(&self) -> f32 {
+        (self.to.0 - self.from.0).abs()
+    }
+
+    pub fn height(&self) -> f32 {
+        (self.to.1 - self.from.1).abs()
+    }
+
+    pub fn length(&self) -> f32 {
+        self.width().hypot(self.height())
+    }
+
+    /// The numbers, such as `120 x 40 pixels, length 126.49`.
+    pub fn describe(&self) -> String {
+        let length = if self.rectangle { "diagonal" } else { "length" };
+        format!(
+            "{} x {} pixels, {length} {:.2}",
+            self.width(),
+            self.height(),
+            self.length()
+        )
+    }
+
+    /// Draws the line or rectangle between the client points `from` and `to`, in black and
+    /// white so it shows on any image.
+    pub fn draw(&self, hdc: HDC, from: POINT, to: POINT) {
+        if self.rectangle {
+            Selection {
+                anchor: from,
+                cursor: to,
+            }
+            .draw(hdc);
+            return;
+        }
+        for (width, color) in [(3, 0), (1, 0x00ff_ffff)] {
+            unsafe {
+                let pen = CreatePen(PS_SOLID, width, COLORREF(color));
+                let old = SelectObject(hdc, pen);
+                MoveToEx(hdc, from.x, from.y, None);
+                LineTo(hdc, to.x, to.y);
+                SelectObject(hdc, old);
+                DeleteObject(pen);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drag_to_measures_the_distance() {
+        let mut measure = Measure::new((10.0, 20.0), false);
+        assert!(measure.is_empty());
+        measure.drag_to((130.0, 60.0), false);
+        assert_eq!((measure.width(), measure.height()), (120.0, 40.0));
+        assert_eq!(measure.describe(), "120 x 40 pixels, length 126.49");
+        // backwards gives the same numbers.
+        measure.drag_to((-110.0, -20.0), false);
+        assert_eq!((measure.width(), measure.height()), (120.0, 40.0));
+    }
+
+    #[test]
+    fn straight_drags_keep_to_the_closer_axis() {
+        let mut measure = Measure::new((10.0, 20.0), false);
+        measure.drag_to((130.0, 60.0), true);
+        assert_eq!(measure.to, (130.0, 20.0));
+        measure.drag_to((30.0, -100.0), true);
+        assert_eq!(measure.to, (10.0, -100.0));
+        assert_eq!(measure.length(), 120.0);
+    }
+
+    #[test]
+    fn rectangles_report_the_diagonal() {
+        let mut measure = Measure::new((0.0, 0.0), true);
+        measure.drag_to((3.0, 4.0), false);
+        assert_eq!(measure.describe(), "3 x 4 pixels, diagonal 5.00");
+    }
+
+    #[test]
+    fn snap_rounds_to_a_corner_inside_the_image() {
+        assert_eq!(snap((10.4, 7.6), (100, 50)), (10.0, 8.0));
+        assert_eq!(snap((-3.0, 80.0), (100, 50)), (0.0, 50.0));
+    }
+}
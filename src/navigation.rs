@@ -0,0 +1,257 @@
+use crate::EXTENSIONS;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The list of files the Left and Right keys step through.
+#[derive(Default)]
+pub struct Navigation {
+    files: Vec<PathBuf>,
+    index: usize,
+    /// Whether `files` are those of a folder rather than a playlist.
+    folder: bool,
+}
+
+/// Whether the extension of `path` is one of `extensions`, ignoring case.
+fn has_extension(path: &Path, extensions: &[impl AsRef<str>]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|e| e.as_ref().eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Whether pinion can open `path`, judging by its extension.
+pub fn is_supported(path: &Path) -> bool {
+    has_extension(path, &EXTENSIONS)
+}
+
+/// The files in `dir` with one of `extensions`, sorted by name.
+pub fn files_with(dir: &Path, extensions: &[impl AsRef<str>]) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .map(|entry| entry.path())
+        .filter(|path| has_extension(path, extensions) && is_supported(path))
+        .collect();
+    files.sort_by_key(|path| path.file_name().map(|name| name.to_ascii_lowercase()));
+    Ok(files)
+}
+
+/// The supported files in `dir`, sorted by name.
+pub fn supported_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    files_with(dir, &EXTENSIONS)
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of characters and `?` for
+/// any one, ignoring case as Windows does.
+fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // the last `*` seen and where in `name` its match ends so far.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            // let the last `*` take one more character and try again.
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The supported files matching `pattern`, sorted by name, when its file name has `*` or `?`;
+/// the shell leaves them to the program on Windows. Otherwise, or when nothing matches, just
+/// `pattern`, so that it is reported as missing.
+pub fn expand_wildcards(pattern: PathBuf) -> Vec<PathBuf> {
+    let Some(name) = pattern.file_name().and_then(|name| name.to_str()) else {
+        return vec![pattern];
+    };
+    if !name.contains(['*', '?']) {
+        return vec![pattern];
+    }
+    let dir = match pattern.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter(|entry| {
+            let file_name = entry.file_name();
+            file_name
+                .to_str()
+                .is_some_and(|f| matches_wildcard(name, f))
+        })
+        .map(|entry| pattern.with_file_name(entry.file_name()))
+        .filter(|path| is_supported(path))
+        .collect();
+    if files.is_empty() {
+        return vec![pattern];
+    }
+    files.sort_by_key(|path| path.file_name().map(|name| name.to_ascii_lowercase()));
+    files
+}
+
+impl Navigation {
+    /// Steps through exactly `files`, in the given order, starting at the first.
+    pub fn playlist(files: Vec<PathBuf>) -> Navigation {
+        Navigation {
+            files,
+            index: 0,
+            folder: false,
+        }
+    }
+
+    /// Steps through the files next to `file_path` with one of `extensions`, sorted by name.
+    /// `file_path` itself is among them whatever its extension.
+    pub fn folder(file_path: &Path, extensions: &[impl AsRef<str>]) -> Result<Navigation> {
+        let dir = file_path.parent().context("no parent directory")?;
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let mut files = files_with(dir, extensions)?;
+
+        let name = file_path.file_name();
+        // names differing only in case are the same file on Windows.
+        let same_name = |path: &PathBuf| {
+            path.file_name()
+                .zip(name)
+                .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+        };
+        let index = match files.iter().position(same_name) {
+            Some(index) => index,
+            None => {
+                let key = |path: &Path| path.file_name().map(|name| name.to_ascii_lowercase());
+                let index = files.partition_point(|path| key(path) < key(file_path));
+                files.insert(index, dir.join(name.unwrap_or_default()));
+                index
+            }
+        };
+        Ok(Navigation {
+            files,
+            index,
+            folder: true,
+        })
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn is_folder(&self) -> bool {
+        self.folder
+    }
+
+    /// The zero-based index of the current file and the number of files.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        (!self.files.is_empty()).then_some((self.index, self.files.len()))
+    }
+
+    pub fn next(&mut self) -> Option<&Path> {
+        if self.index + 1 >= self.files.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(&self.files[self.index])
+    }
+
+    pub fn prev(&mut self) -> Option<&Path> {
+        if self.index == 0 || self.files.is_empty() {
+            return None;
+        }
+        self.index -= 1;
+        Some(&self.files[self.index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh folder in the temporary directory holding `files`, and a folder named like an
+    /// image, which must be skipped.
+    fn folder_with(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pinion-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub.png")).unwrap();
+        for file in files {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+        dir
+    }
+
+    fn names(files: &[PathBuf]) -> Vec<String> {
+        files
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn files_with_filters_and_sorts_ignoring_case() {
+        let dir = folder_with("files", &["b.PNG", "a.jpg", "C.png", "notes.txt", "d.webp"]);
+        let all = files_with(&dir, &EXTENSIONS).unwrap();
+        assert_eq!(names(&all), ["a.jpg", "b.PNG", "C.png"]);
+        let png = files_with(&dir, &["png"]).unwrap();
+        assert_eq!(names(&png), ["b.PNG", "C.png"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn folder_starts_at_the_file_and_steps_through() {
+        let dir = folder_with("folder", &["a.png", "b.jpg", "c.png"]);
+        let mut navigation = Navigation::folder(&dir.join("b.jpg"), &EXTENSIONS).unwrap();
+        assert!(navigation.is_folder());
+        assert_eq!(navigation.position(), Some((1, 3)));
+        assert_eq!(navigation.next(), Some(dir.join("c.png").as_path()));
+        assert_eq!(navigation.next(), None);
+        assert_eq!(navigation.prev(), Some(dir.join("b.jpg").as_path()));
+        assert_eq!(navigation.prev(), Some(dir.join("a.png").as_path()));
+        assert_eq!(navigation.prev(), None);
+        // a name typed in another case is the file already there, not a new one.
+        let navigation = Navigation::folder(&dir.join("B.JPG"), &EXTENSIONS).unwrap();
+        assert_eq!(names(navigation.files()), ["a.png", "b.jpg", "c.png"]);
+        assert_eq!(navigation.position(), Some((1, 3)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn folder_keeps_a_file_of_another_extension() {
+        let dir = folder_with("other", &["a.png", "b.jpg", "c.png"]);
+        let navigation = Navigation::folder(&dir.join("b.jpg"), &["png"]).unwrap();
+        assert_eq!(names(navigation.files()), ["a.png", "b.jpg", "c.png"]);
+        assert_eq!(navigation.position(), Some((1, 3)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn wildcards_match_ignoring_case() {
+        assert!(matches_wildcard("*.PNG", "shot.png"));
+        assert!(matches_wildcard("a?c*", "abcdef"));
+        assert!(matches_wildcard("*b*b", "abab"));
+        assert!(!matches_wildcard("a?c", "ac"));
+        assert!(!matches_wildcard("*.png", "shot.jpg"));
+    }
+}
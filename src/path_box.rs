@@ -0,0 +1,68 @@
+use crate::{controls, long_path};
+use anyhow::{ensure, Result};
+use std::path::Path;
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::{HWND, LPARAM, WPARAM},
+        Graphics::Gdi::HFONT,
+        UI::{
+            Controls::{EM_SETSEL, WC_EDITW},
+            Input::KeyboardAndMouse::{SetFocus, VIRTUAL_KEY, VK_ESCAPE, VK_RETURN},
+            WindowsAndMessaging::{
+                GetDlgCtrlID, GetParent, MoveWindow, PostMessageW, SetWindowTextW, ES_AUTOHSCROLL,
+                MSG, WINDOW_STYLE, WM_APP, WM_KEYDOWN,
+            },
+        },
+    },
+};
+
+/// Posted to the window when Enter is pressed in its path box.
+pub const WM_PATH_ENTERED: u32 = WM_APP + 4;
+
+/// Creates the box to type or paste a path into, placed by [`move_to`].
+pub fn create(parent: HWND, id: i32, font: HFONT) -> Result<HWND> {
+    let style = WINDOW_STYLE(ES_AUTOHSCROLL as u32);
+    let h_edit = controls::create(parent, WC_EDITW, "", style, id, [0, 0, 0, 0], font);
+    ensure!(h_edit.0 != 0, "failed to create the path box.");
+    Ok(h_edit)
+}
+
+pub fn move_to(h_edit: HWND, x: i32, y: i32, width: i32, height: i32) {
+    unsafe { MoveWindow(h_edit, x, y, width.max(0), height, true) };
+}
+
+/// Handles Enter and Esc in the path box `id` before the edit control sees them. Enter posts
+/// [`WM_PATH_ENTERED`] to the window, Esc gives the keyboard back to the viewer. Returns
+/// whether `msg` was one of them.
+pub fn key(msg: &MSG, id: i32) -> bool {
+    if msg.message != WM_KEYDOWN || unsafe { GetDlgCtrlID(msg.hwnd) } != id {
+        return false;
+    }
+    let parent = unsafe { GetParent(msg.hwnd) };
+    match VIRTUAL_KEY(msg.wParam.0 as u16) {
+        // the edit control never sees it, so it doesn't beep either.
+        VK_RETURN => unsafe {
+            PostMessageW(parent, WM_PATH_ENTERED, WPARAM(0), LPARAM(0));
+        },
+        VK_ESCAPE => unsafe {
+            SetFocus(parent);
+        },
+        _ => return false,
+    }
+    true
+}
+
+/// Selects the whole text once the click that focused the box has placed the caret, so what
+/// is typed or pasted replaces it.
+pub fn select_all(h_edit: HWND) {
+    unsafe { PostMessageW(h_edit, EM_SETSEL, WPARAM(0), LPARAM(-1)) };
+}
+
+pub fn set_path(h_edit: HWND, path: Option<&Path>) {
+    let text = match path {
+        Some(path) => long_path::to_wide(&long_path::display(path)),
+        None => vec![0],
+    };
+    unsafe { SetWindowTextW(h_edit, PCWSTR::from_raw(text.as_ptr())) };
+}
@@ -0,0 +1,173 @@
+use crate::settings::SavedPlacement;
+use anyhow::{ensure, Result};
+use std::mem;
+use windows::Win32::{
+    Foundation::{BOOL, HWND, LPARAM, POINT, RECT},
+    Graphics::Gdi::{
+        EnumDisplayMonitors, GetMonitorInfoW, MonitorFromPoint, MonitorFromRect, MonitorFromWindow,
+        HDC, HMONITOR, MONITORINFO, MONITOR_DEFAULTTONEAREST, MONITOR_DEFAULTTONULL,
+        MONITOR_DEFAULTTOPRIMARY,
+    },
+    UI::WindowsAndMessaging::{
+        GetClassNameW, GetCursorPos, GetForegroundWindow, GetWindowPlacement, SetWindowPlacement,
+        SetWindowPos, ShowWindow, SWP_NOACTIVATE, SWP_NOZORDER, SW_SHOW, SW_SHOWMAXIMIZED,
+        SW_SHOWMINIMIZED, SW_SHOWNORMAL, WINDOWPLACEMENT, WPF_RESTORETOMAXIMIZED,
+    },
+};
+
+/// Identifies the current arrangement of monitors, so a placement saved while docked isn't
+/// applied undocked.
+pub fn layout_key() -> u64 {
+    let mut rects: Vec<RECT> = Vec::new();
+    unsafe {
+        EnumDisplayMonitors(
+            None,
+            None,
+            Some(collect_monitor),
+            LPARAM(&mut rects as *mut _ as isize),
+        )
+    };
+    rects.sort_by_key(|rc| (rc.left, rc.top, rc.right, rc.bottom));
+
+    // FNV-1a, unlike std's hasher it is guaranteed to stay the same across builds.
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for v in rects
+        .iter()
+        .flat_map(|rc| [rc.left, rc.top, rc.right, rc.bottom])
+    {
+        for b in v.to_le_bytes() {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+unsafe extern "system" fn collect_monitor(
+    _: HMONITOR,
+    _: HDC,
+    rc: *mut RECT,
+    data: LPARAM,
+) -> BOOL {
+    let rects = &mut *(data.0 as *mut Vec<RECT>);
+    rects.push(*rc);
+    true.into()
+}
+
+fn is_explorer(h_wnd: HWND) -> bool {
+    let mut buf = [0u16; 32];
+    let len = unsafe { GetClassNameW(h_wnd, &mut buf) } as usize;
+    let class = String::from_utf16_lossy(&buf[..len]);
+    class == "CabinetWClass" || class == "ExploreWClass"
+}
+
+/// The monitor pinion was started from: that of the Explorer window in front, otherwise the one
+/// under the cursor.
+fn launch_monitor() -> HMONITOR {
+    unsafe {
+        let foreground = GetForegroundWindow();
+        if foreground.0 != 0 && is_explorer(foreground) {
+            let h_monitor = MonitorFromWindow(foreground, MONITOR_DEFAULTTONULL);
+            if !h_monitor.is_invalid() {
+                return h_monitor;
+            }
+        }
+        let mut pt = POINT::default();
+        GetCursorPos(&mut pt);
+        MonitorFromPoint(pt, MONITOR_DEFAULTTOPRIMARY)
+    }
+}
+
+/// A `width` by `height` window rectangle centered in the work area of the launch monitor,
+/// shrunk when the work area is smaller.
+pub fn initial_rect(width: i32, height: i32) -> RECT {
+    centered(launch_monitor(), width, height)
+}
+
+/// A `width` by `height` rectangle centered in the work area of `h_monitor`, in screen
+/// coordinates, shrunk when the work area is smaller.
+fn centered(h_monitor: HMONITOR, width: i32, height: i32) -> RECT {
+    let mut info = MONITORINFO {
+        cbSize: mem::size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    unsafe { GetMonitorInfoW(h_monitor, &mut info) };
+    let work = info.rcWork;
+    let width = width.min(work.right - work.left);
+    let height = height.min(work.bottom - work.top);
+    let left = work.left + (work.right - work.left - width) / 2;
+    let top = work.top + (work.bottom - work.top - height) / 2;
+    RECT {
+        left,
+        top,
+        right: left + width,
+        bottom: top + height,
+    }
+}
+
+/// Shows the window for the first time, where it was last closed on this monitor layout. When
+/// pinion was started from another monitor, the window keeps its size but goes there.
+pub fn show(h_wnd: HWND, saved: Option<SavedPlacement>) {
+    let Some(saved) = saved else {
+        unsafe { ShowWindow(h_wnd, SW_SHOW) };
+        return;
+    };
+    let [left, top, right, bottom] = saved.rect;
+    let rect = RECT {
+        left,
+        top,
+        right,
+        bottom,
+    };
+    let show_cmd = if saved.maximized {
+        SW_SHOWMAXIMIZED
+    } else {
+        SW_SHOWNORMAL
+    };
+    // the placement is in workspace coordinates, which differ from screen coordinates only by
+    // the taskbar, too little to change the monitor.
+    let launch = launch_monitor();
+    if unsafe { MonitorFromRect(&rect, MONITOR_DEFAULTTONEAREST) } != launch {
+        let rc = centered(launch, right - left, bottom - top);
+        unsafe {
+            SetWindowPos(
+                h_wnd,
+                None,
+                rc.left,
+                rc.top,
+                rc.right - rc.left,
+                rc.bottom - rc.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+            ShowWindow(h_wnd, show_cmd);
+        }
+        return;
+    }
+    let placement = WINDOWPLACEMENT {
+        length: mem::size_of::<WINDOWPLACEMENT>() as u32,
+        showCmd: show_cmd,
+        rcNormalPosition: rect,
+        ..Default::default()
+    };
+    unsafe { SetWindowPlacement(h_wnd, &placement) };
+}
+
+/// The window's placement, for [`show`] on the next start.
+pub fn current(h_wnd: HWND) -> Result<SavedPlacement> {
+    let mut placement = WINDOWPLACEMENT {
+        length: mem::size_of::<WINDOWPLACEMENT>() as u32,
+        ..Default::default()
+    };
+    ensure!(
+        unsafe { GetWindowPlacement(h_wnd, &mut placement) }.as_bool(),
+        "GetWindowPlacement failed."
+    );
+    let rc = placement.rcNormalPosition;
+    Ok(SavedPlacement {
+        layout: layout_key(),
+        maximized: placement.showCmd == SW_SHOWMAXIMIZED
+            || (placement.showCmd == SW_SHOWMINIMIZED
+                && placement.flags.0 & WPF_RESTORETOMAXIMIZED.0 != 0),
+        rect: [rc.left, rc.top, rc.right, rc.bottom],
+    })
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The files of a playlist: one path per line, relative ones against `base`. Blank lines and
+/// lines starting with `#` are skipped, as is a byte order mark.
+pub fn parse(text: &str, base: &Path) -> Vec<PathBuf> {
+    text.trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect()
+}
+
+/// Writes `files` one per line, relative to `base` when inside it.
+pub fn serialize(files: &[PathBuf], base: &Path) -> String {
+    files
+        .iter()
+        .map(|file| {
+            let relative = file.strip_prefix(base).unwrap_or(file);
+            format!("{}\r\n", relative.display())
+        })
+        .collect()
+}
+
+/// Reads the playlist `path`, a UTF-8 text file.
+pub fn read(path: &Path) -> Result<Vec<PathBuf>> {
+    let bytes = fs::read(path).with_context(|| format!("Cannot read {}.", path.display()))?;
+    let text =
+        String::from_utf8(bytes).with_context(|| format!("{} is not UTF-8.", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(parse(&text, base))
+}
+
+/// Saves `files` as the playlist `path`, relative to its folder where possible.
+pub fn save(path: &Path, files: &[PathBuf]) -> Result<()> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    fs::write(path, serialize(files, base))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<PathBuf> {
+        [r"C:\shows\a.png", r"C:\shows\sub\b.jpg", r"D:\photos\c.gif"]
+            .map(PathBuf::from)
+            .to_vec()
+    }
+
+    #[test]
+    fn parse_skips_the_bom_comments_and_blank_lines() {
+        let text =
+            "\u{feff}# holiday\r\na.png\r\n\r\n  sub\\b.jpg  \r\n#D:\\old.png\nD:\\photos\\c.gif";
+        assert_eq!(parse(text, Path::new(r"C:\shows")), files());
+    }
+
+    #[test]
+    fn serialize_writes_crlf_lines_relative_inside_the_base() {
+        let base = Path::new(r"C:\shows");
+        let text = serialize(&files(), base);
+        assert_eq!(text, "a.png\r\nsub\\b.jpg\r\nD:\\photos\\c.gif\r\n");
+        assert_eq!(parse(&text, base), files());
+    }
+}
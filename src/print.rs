@@ -0,0 +1,682 @@
+use crate::dib::Dib;
+use crate::{controls, l, msg_box, scaled};
+use anyhow::{anyhow, ensure, Context, Result};
+use image::{imageops, DynamicImage};
+use std::cell::RefCell;
+use std::mem;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::{Arc, Once};
+use windows::{
+    core::PCWSTR,
+    w,
+    Win32::{
+        Foundation::{HGLOBAL, HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Graphics::Gdi::{
+            BeginPaint, CreateDCW, CreatedHDC, DeleteDC, EndPaint, FillRect, FrameRect,
+            GetDeviceCaps, GetStockObject, GetSysColorBrush, IntersectClipRect, InvalidateRect,
+            RestoreDC, SaveDC, COLOR_APPWORKSPACE, COLOR_BTNFACE, COLOR_BTNSHADOW, DEVMODEW,
+            DMORIENT_LANDSCAPE, DMORIENT_PORTRAIT, DM_ORIENTATION, HBRUSH, HDC, HFONT, HORZRES,
+            LOGPIXELSX, LOGPIXELSY, PAINTSTRUCT, PHYSICALHEIGHT, PHYSICALOFFSETX, PHYSICALOFFSETY,
+            PHYSICALWIDTH, VERTRES, WHITE_BRUSH,
+        },
+        Storage::Xps::{AbortDoc, EndDoc, EndPage, StartDocW, StartPage, DOCINFOW},
+        System::Memory::{GlobalFree, GlobalLock, GlobalUnlock},
+        UI::{
+            Controls::{
+                Dialogs::{
+                    PrintDlgW, DEVNAMES, PD_NOPAGENUMS, PD_NOSELECTION, PD_RETURNDEFAULT, PRINTDLGW,
+                },
+                WC_BUTTONW, WC_EDITW, WC_STATICW,
+            },
+            WindowsAndMessaging::{
+                AdjustWindowRectEx, CreateWindowExW, DefWindowProcW, DestroyWindow, GetClientRect,
+                GetWindowLongPtrW, GetWindowRect, IsDialogMessageW, LoadCursorW, RegisterClassW,
+                SetForegroundWindow, SetWindowLongPtrW, SetWindowTextW, BN_CLICKED,
+                BS_AUTOCHECKBOX, BS_DEFPUSHBUTTON, CREATESTRUCTW, EN_CHANGE, ES_NUMBER,
+                GWLP_USERDATA, IDC_ARROW, MSG, WINDOW_EX_STYLE, WINDOW_STYLE, WM_COMMAND,
+                WM_CREATE, WM_DESTROY, WM_ERASEBKGND, WM_NCCREATE, WM_NCDESTROY, WM_PAINT, WM_SIZE,
+                WNDCLASSW, WS_CLIPCHILDREN, WS_OVERLAPPEDWINDOW, WS_TABSTOP, WS_VISIBLE,
+            },
+        },
+    },
+};
+
+/// How the image is put on the paper.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Setup {
+    pub landscape: bool,
+    /// Cover the area inside the margins, cropping the image, rather than fit into it.
+    pub fill: bool,
+    /// Blank space along each edge of the paper, the printer's unprintable edge if wider.
+    pub margin_mm: u32,
+}
+
+/// A sheet of paper in printer pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Page {
+    pub width: i32,
+    pub height: i32,
+    /// The part the printer reaches, relative to the corner of the paper.
+    pub printable: RECT,
+    /// Printer pixels per inch, across and down.
+    pub dpi: (i32, i32),
+}
+
+/// Where the image goes, in printer pixels relative to the corner of the paper.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    /// Inside the margins, the image is clipped to it.
+    pub area: RECT,
+    pub image: RECT,
+}
+
+/// Places an image of `size` pixels on `page`. The preview draws and the print job prints the
+/// result as is, so the two cannot disagree.
+pub fn layout(page: &Page, size: (u32, u32), setup: &Setup) -> Layout {
+    let margin = |dpi: i32| (setup.margin_mm as f64 / 25.4 * dpi as f64).round() as i32;
+    let (mx, my) = (margin(page.dpi.0), margin(page.dpi.1));
+    let left = mx.max(page.printable.left);
+    let top = my.max(page.printable.top);
+    let area = RECT {
+        left,
+        top,
+        right: (page.width - mx).min(page.printable.right).max(left),
+        bottom: (page.height - my).min(page.printable.bottom).max(top),
+    };
+
+    // in inches, the dots of a printer need not be square.
+    let area_width = (area.right - area.left) as f64 / page.dpi.0 as f64;
+    let area_height = (area.bottom - area.top) as f64 / page.dpi.1 as f64;
+    let (width, height) = (size.0.max(1) as f64, size.1.max(1) as f64);
+    let (across, down) = (area_width / width, area_height / height);
+    let inches_per_pixel = if setup.fill {
+        across.max(down)
+    } else {
+        across.min(down)
+    };
+    let image_width = (width * inches_per_pixel * page.dpi.0 as f64).round() as i32;
+    let image_height = (height * inches_per_pixel * page.dpi.1 as f64).round() as i32;
+    let left = (area.left + area.right - image_width) / 2;
+    let top = (area.top + area.bottom - image_height) / 2;
+    Layout {
+        area,
+        image: RECT {
+            left,
+            top,
+            right: left + image_width,
+            bottom: top + image_height,
+        },
+    }
+}
+
+/// The printer to print on with its settings, as kept by the print dialog.
+pub struct Printer {
+    dev_mode: HGLOBAL,
+    dev_names: HGLOBAL,
+}
+
+impl Printer {
+    /// The default printer, `None` when there is none.
+    pub fn default_printer(owner: HWND) -> Option<Printer> {
+        let mut pd = PRINTDLGW {
+            lStructSize: mem::size_of::<PRINTDLGW>() as u32,
+            hwndOwner: owner,
+            Flags: PD_RETURNDEFAULT,
+            ..Default::default()
+        };
+        unsafe { PrintDlgW(&mut pd) }.as_bool().then_some(Printer {
+            dev_mode: pd.hDevMode,
+            dev_names: pd.hDevNames,
+        })
+    }
+
+    /// Shows the print dialog to pick another printer or change its settings. Returns whether
+    /// the user confirmed.
+    pub fn choose(&mut self, owner: HWND) -> bool {
+        let mut pd = PRINTDLGW {
+            lStructSize: mem::size_of::<PRINTDLGW>() as u32,
+            hwndOwner: owner,
+            hDevMode: self.dev_mode,
+            hDevNames: self.dev_names,
+            Flags: PD_NOPAGENUMS | PD_NOSELECTION,
+            nCopies: 1,
+            ..Default::default()
+        };
+        let ok = unsafe { PrintDlgW(&mut pd) }.as_bool();
+        // the dialog may have replaced the blocks, freeing ours.
+        self.dev_mode = pd.hDevMode;
+        self.dev_names = pd.hDevNames;
+        ok
+    }
+
+    /// The driver and device names, null-terminated.
+    fn names(&self) -> Result<(Vec<u16>, Vec<u16>)> {
+        unsafe {
+            let base = GlobalLock(self.dev_names) as *const u16;
+            ensure!(!base.is_null(), "cannot read the printer name.");
+            let names = &*(base as *const DEVNAMES);
+            let wide = |offset: u16| {
+                let mut name = PCWSTR::from_raw(base.add(offset as usize))
+                    .as_wide()
+                    .to_vec();
+                name.push(0);
+                name
+            };
+            let names = (wide(names.wDriverOffset), wide(names.wDeviceOffset));
+            GlobalUnlock(self.dev_names);
+            Ok(names)
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.names()
+            .map(|(_, device)| String::from_utf16_lossy(&device[..device.len() - 1]))
+            .unwrap_or_default()
+    }
+
+    /// Whether the settings ask for landscape, as they may after `choose`.
+    pub fn landscape(&self) -> bool {
+        unsafe {
+            let dev_mode = GlobalLock(self.dev_mode) as *const DEVMODEW;
+            if dev_mode.is_null() {
+                return false;
+            }
+            let orientation = (*dev_mode).Anonymous1.Anonymous1.dmOrientation;
+            GlobalUnlock(self.dev_mode);
+            orientation as u32 == DMORIENT_LANDSCAPE
+        }
+    }
+
+    /// A device context of the printer turned to `landscape` or portrait.
+    fn create_dc(&self, landscape: bool) -> Result<CreatedHDC> {
+        let (driver, device) = self.names()?;
+        unsafe {
+            let dev_mode = GlobalLock(self.dev_mode) as *mut DEVMODEW;
+            ensure!(!dev_mode.is_null(), "cannot read the printer settings.");
+            (*dev_mode).Anonymous1.Anonymous1.dmOrientation = if landscape {
+                DMORIENT_LANDSCAPE
+            } else {
+                DMORIENT_PORTRAIT
+            } as i16;
+            (*dev_mode).dmFields |= DM_ORIENTATION;
+            let dc = CreateDCW(
+                PCWSTR::from_raw(driver.as_ptr()),
+                PCWSTR::from_raw(device.as_ptr()),
+                PCWSTR::null(),
+                Some(dev_mode),
+            );
+            GlobalUnlock(self.dev_mode);
+            ensure!(dc.0 != 0, "cannot open the printer {}.", self.name());
+            Ok(dc)
+        }
+    }
+
+    /// The paper in the printer, turned to `landscape` or portrait.
+    pub fn page(&self, landscape: bool) -> Result<Page> {
+        let dc = self.create_dc(landscape)?;
+        let caps = |index| unsafe { GetDeviceCaps(dc, index) };
+        let (left, top) = (caps(PHYSICALOFFSETX), caps(PHYSICALOFFSETY));
+        let page = Page {
+            width: caps(PHYSICALWIDTH),
+            height: caps(PHYSICALHEIGHT),
+            printable: RECT {
+                left,
+                top,
+                right: left + caps(HORZRES),
+                bottom: top + caps(VERTRES),
+            },
+            dpi: (caps(LOGPIXELSX).max(1), caps(LOGPIXELSY).max(1)),
+        };
+        unsafe { DeleteDC(dc) };
+        ensure!(
+            page.width > 0 && page.height > 0,
+            "{} reports no paper size.",
+            self.name()
+        );
+        Ok(page)
+    }
+
+    /// Prints `img` on one page as `layout` places it, named `title` in the print queue.
+    pub fn print(
+        &self,
+        img: &DynamicImage,
+        layout: &Layout,
+        landscape: bool,
+        title: &str,
+    ) -> Result<()> {
+        let dc = self.create_dc(landscape)?;
+        let hdc = HDC(dc.0);
+        let title = l(title);
+        let info = DOCINFOW {
+            cbSize: mem::size_of::<DOCINFOW>() as i32,
+            lpszDocName: PCWSTR::from_raw(title.as_ptr()),
+            ..Default::default()
+        };
+        let result = unsafe {
+            if StartDocW(hdc, &info) <= 0 {
+                Err(anyhow!("cannot start printing on {}.", self.name()))
+            } else if StartPage(hdc) <= 0 {
+                AbortDoc(hdc);
+                Err(anyhow!("cannot start the page on {}.", self.name()))
+            } else {
+                // the origin of the printer's device context is the corner of the printable
+                // part, not of the paper.
+                let (x, y) = (
+                    GetDeviceCaps(hdc, PHYSICALOFFSETX),
+                    GetDeviceCaps(hdc, PHYSICALOFFSETY),
+                );
+                let offset = |rc: &RECT| RECT {
+                    left: rc.left - x,
+                    top: rc.top - y,
+                    right: rc.right - x,
+                    bottom: rc.bottom - y,
+                };
+                let area = offset(&layout.area);
+                IntersectClipRect(hdc, area.left, area.top, area.right, area.bottom);
+                Dib::new(img, [255, 255, 255]).draw(hdc, &offset(&layout.image));
+                if EndPage(hdc) <= 0 {
+                    AbortDoc(hdc);
+                    Err(anyhow!("printing on {} failed.", self.name()))
+                } else {
+                    EndDoc(hdc);
+                    Ok(())
+                }
+            }
+        };
+        unsafe { DeleteDC(dc) };
+        result
+    }
+}
+
+impl Drop for Printer {
+    fn drop(&mut self) {
+        unsafe {
+            GlobalFree(self.dev_mode).ok();
+            GlobalFree(self.dev_names).ok();
+        }
+    }
+}
+
+const CLASS_NAME: PCWSTR = w!("pinion_print_class");
+
+const ID_LANDSCAPE: i32 = 4000;
+const ID_FILL: i32 = 4001;
+const ID_MARGIN: i32 = 4002;
+const ID_PRINTER: i32 = 4003;
+const ID_PRINT: i32 = 4004;
+
+/// Client size of the window at 96 DPI.
+const WIDTH: i32 = 640;
+const HEIGHT: i32 = 560;
+/// Height of the row of controls above the page, at 96 DPI.
+const TOOLBAR: i32 = 40;
+/// Longest side of the copy of the image the preview draws.
+const PREVIEW_SIZE: u32 = 1600;
+
+/// The open preview, there is at most one.
+static PREVIEW: AtomicIsize = AtomicIsize::new(0);
+
+struct Preview {
+    img: Arc<DynamicImage>,
+    /// `img` shrunk for the screen.
+    dib: Dib,
+    title: String,
+    printer: Printer,
+    setup: Setup,
+    page: Page,
+    font: HFONT,
+}
+
+/// Opens the print preview of `img`, or brings it to the front if it is open.
+pub fn show(owner: HWND, img: Arc<DynamicImage>, title: String, font: HFONT) -> Result<()> {
+    let open = HWND(PREVIEW.load(Ordering::Relaxed));
+    if open.0 != 0 {
+        unsafe { DestroyWindow(open) };
+    }
+    let printer = Printer::default_printer(owner).context("No printer is installed.")?;
+    let setup = Setup {
+        landscape: img.width() > img.height(),
+        fill: false,
+        margin_mm: 10,
+    };
+    let page = printer.page(setup.landscape)?;
+    let dib = if img.width() > PREVIEW_SIZE || img.height() > PREVIEW_SIZE {
+        Dib::new(
+            &img.resize(PREVIEW_SIZE, PREVIEW_SIZE, imageops::Triangle),
+            [255, 255, 255],
+        )
+    } else {
+        Dib::new(&img, [255, 255, 255])
+    };
+
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let wnd_class = WNDCLASSW {
+            lpszClassName: CLASS_NAME,
+            lpfnWndProc: Some(window_proc),
+            hCursor: unsafe { LoadCursorW(None, IDC_ARROW) }.unwrap_or_default(),
+            ..Default::default()
+        };
+        unsafe { RegisterClassW(&wnd_class) };
+    });
+
+    let style = WS_OVERLAPPEDWINDOW | WS_CLIPCHILDREN;
+    let mut rc = RECT {
+        left: 0,
+        top: 0,
+        right: scaled(WIDTH),
+        bottom: scaled(HEIGHT),
+    };
+    let mut owner_rc = RECT::default();
+    unsafe {
+        AdjustWindowRectEx(&mut rc, style, false, WINDOW_EX_STYLE::default());
+        GetWindowRect(owner, &mut owner_rc);
+    }
+    let (width, height) = (rc.right - rc.left, rc.bottom - rc.top);
+    let state = Box::new(RefCell::new(Preview {
+        img,
+        dib,
+        title,
+        printer,
+        setup,
+        page,
+        font,
+    }));
+    let h_wnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            CLASS_NAME,
+            w!("Print preview"),
+            style | WS_VISIBLE,
+            // centered on the owner.
+            (owner_rc.left + owner_rc.right - width) / 2,
+            (owner_rc.top + owner_rc.bottom - height) / 2,
+            width,
+            height,
+            owner,
+            None,
+            None,
+            Some(Box::into_raw(state) as *const _),
+        )
+    };
+    if h_wnd.0 == 0 {
+        return Err(anyhow!("failed to create the print preview."));
+    }
+    PREVIEW.store(h_wnd.0, Ordering::Relaxed);
+    unsafe { SetForegroundWindow(h_wnd) };
+    Ok(())
+}
+
+/// Routes keyboard input for the preview, so Tab moves between its controls. Returns whether
+/// `msg` was handled.
+pub fn is_dialog_message(msg: &MSG) -> bool {
+    let h_wnd = HWND(PREVIEW.load(Ordering::Relaxed));
+    h_wnd.0 != 0 && unsafe { IsDialogMessageW(h_wnd, msg) }.as_bool()
+}
+
+fn with_state<R>(h_wnd: HWND, f: impl FnOnce(&mut Preview) -> R) -> Option<R> {
+    let state = unsafe { GetWindowLongPtrW(h_wnd, GWLP_USERDATA) } as *const RefCell<Preview>;
+    if state.is_null() {
+        return None;
+    }
+    Some(f(&mut unsafe { &*state }.borrow_mut()))
+}
+
+unsafe extern "system" fn window_proc(
+    h_wnd: HWND,
+    msg: u32,
+    w_param: WPARAM,
+    l_param: LPARAM,
+) -> LRESULT {
+    match msg {
+        WM_NCCREATE => {
+            let create = &*(l_param.0 as *const CREATESTRUCTW);
+            SetWindowLongPtrW(h_wnd, GWLP_USERDATA, create.lpCreateParams as isize);
+            return DefWindowProcW(h_wnd, msg, w_param, l_param);
+        }
+        WM_NCDESTROY => {
+            let state = SetWindowLongPtrW(h_wnd, GWLP_USERDATA, 0) as *mut RefCell<Preview>;
+            if !state.is_null() {
+                drop(Box::from_raw(state));
+            }
+            return DefWindowProcW(h_wnd, msg, w_param, l_param);
+        }
+        WM_CREATE => create(h_wnd),
+        WM_COMMAND => command(h_wnd, w_param),
+        WM_PAINT => {
+            paint(h_wnd);
+            Ok(())
+        }
+        // `paint` covers the whole client area.
+        WM_ERASEBKGND => return LRESULT(1),
+        WM_SIZE => {
+            InvalidateRect(h_wnd, None, false);
+            Ok(())
+        }
+        WM_DESTROY => {
+            PREVIEW.store(0, Ordering::Relaxed);
+            Ok(())
+        }
+        _ => return DefWindowProcW(h_wnd, msg, w_param, l_param),
+    }
+    .map_err(|e| msg_box(h_wnd, e))
+    .ok();
+
+    LRESULT::default()
+}
+
+fn create(h_wnd: HWND) -> Result<()> {
+    let (setup, font) = with_state(h_wnd, |state| (state.setup, state.font)).context("no state")?;
+    let check_box = WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32);
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Landscape",
+        check_box,
+        ID_LANDSCAPE,
+        [12, 10, 96, 20],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Fill the page",
+        check_box,
+        ID_FILL,
+        [112, 10, 104, 20],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_STATICW,
+        "Margins (mm)",
+        WINDOW_STYLE(0),
+        -1,
+        [224, 11, 88, 20],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_EDITW,
+        &setup.margin_mm.to_string(),
+        WS_TABSTOP | WINDOW_STYLE(ES_NUMBER as u32),
+        ID_MARGIN,
+        [312, 8, 48, 24],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Printer...",
+        WS_TABSTOP,
+        ID_PRINTER,
+        [372, 7, 84, 26],
+        font,
+    );
+    controls::create(
+        h_wnd,
+        WC_BUTTONW,
+        "Print",
+        WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32),
+        ID_PRINT,
+        [464, 7, 84, 26],
+        font,
+    );
+    controls::set_checked(h_wnd, ID_LANDSCAPE, setup.landscape);
+    update_title(h_wnd);
+    Ok(())
+}
+
+/// Names the printer in the title bar.
+fn update_title(h_wnd: HWND) {
+    let name = with_state(h_wnd, |state| state.printer.name()).unwrap_or_default();
+    let title = l(&format!("Print preview - {name}"));
+    unsafe { SetWindowTextW(h_wnd, PCWSTR::from_raw(title.as_ptr())) };
+}
+
+fn command(h_wnd: HWND, w_param: WPARAM) -> Result<()> {
+    let msg = (w_param.0 as u32) >> 16;
+    let id = ((w_param.0 as u32) & 0xffff) as i32;
+    match (id, msg) {
+        (ID_LANDSCAPE, BN_CLICKED) => {
+            let landscape = controls::is_checked(h_wnd, ID_LANDSCAPE);
+            with_state(h_wnd, |state| {
+                state.page = state.printer.page(landscape)?;
+                state.setup.landscape = landscape;
+                anyhow::Ok(())
+            })
+            .context("no state")??;
+        }
+        (ID_FILL, BN_CLICKED) => {
+            let fill = controls::is_checked(h_wnd, ID_FILL);
+            with_state(h_wnd, |state| state.setup.fill = fill);
+        }
+        (ID_MARGIN, EN_CHANGE) => {
+            // an empty box is no margin, the printer's edge still applies.
+            let margin_mm = controls::text(h_wnd, ID_MARGIN).parse().unwrap_or(0);
+            with_state(h_wnd, |state| state.setup.margin_mm = margin_mm);
+        }
+        (ID_PRINTER, BN_CLICKED) => {
+            // the dialog is modal, the state is not borrowed meanwhile.
+            let state =
+                unsafe { GetWindowLongPtrW(h_wnd, GWLP_USERDATA) } as *const RefCell<Preview>;
+            let mut printer = match unsafe { state.as_ref() } {
+                Some(state) => state.borrow_mut().take_printer(),
+                None => return Ok(()),
+            };
+            let chosen = printer.choose(h_wnd);
+            let landscape = chosen.then(|| printer.landscape());
+            with_state(h_wnd, |state| {
+                state.printer = printer;
+                if let Some(landscape) = landscape {
+                    state.setup.landscape = landscape;
+                }
+                state.page = state.printer.page(state.setup.landscape)?;
+                anyhow::Ok(())
+            })
+            .context("no state")??;
+            if let Some(landscape) = landscape {
+                controls::set_checked(h_wnd, ID_LANDSCAPE, landscape);
+            }
+            update_title(h_wnd);
+        }
+        (ID_PRINT, BN_CLICKED) => {
+            // the spooler may pump messages, the state is not borrowed meanwhile.
+            let (img, layout, landscape, title, printer) = with_state(h_wnd, |state| {
+                (
+                    state.img.clone(),
+                    layout(&state.page, state.image_size(), &state.setup),
+                    state.setup.landscape,
+                    state.title.clone(),
+                    state.take_printer(),
+                )
+            })
+            .context("no state")?;
+            let printed = printer.print(&img, &layout, landscape, &title);
+            with_state(h_wnd, |state| state.printer = printer);
+            printed?;
+            unsafe { DestroyWindow(h_wnd) };
+            return Ok(());
+        }
+        _ => return Ok(()),
+    }
+    unsafe { InvalidateRect(h_wnd, None, false) };
+    Ok(())
+}
+
+impl Preview {
+    /// Leaves a printer without settings in place of the current one, which is returned.
+    fn take_printer(&mut self) -> Printer {
+        mem::replace(
+            &mut self.printer,
+            Printer {
+                dev_mode: HGLOBAL::default(),
+                dev_names: HGLOBAL::default(),
+            },
+        )
+    }
+
+    /// The size of the image to print, the copy for the screen may be smaller.
+    fn image_size(&self) -> (u32, u32) {
+        (self.img.width(), self.img.height())
+    }
+}
+
+/// Draws the page shrunk to the window, with the printable part outlined and the image where
+/// `layout` puts it.
+fn paint(h_wnd: HWND) {
+    let mut ps = PAINTSTRUCT::default();
+    let hdc = unsafe { BeginPaint(h_wnd, &mut ps) };
+    let mut client = RECT::default();
+    unsafe { GetClientRect(h_wnd, &mut client) };
+    let toolbar = RECT {
+        bottom: scaled(TOOLBAR),
+        ..client
+    };
+    let backdrop = RECT {
+        top: toolbar.bottom,
+        ..client
+    };
+    unsafe {
+        FillRect(hdc, &toolbar, GetSysColorBrush(COLOR_BTNFACE));
+        FillRect(hdc, &backdrop, GetSysColorBrush(COLOR_APPWORKSPACE));
+    }
+
+    with_state(h_wnd, |state| {
+        let page = &state.page;
+        let layout = layout(page, state.image_size(), &state.setup);
+        // client pixels per inch of paper.
+        let gap = scaled(16);
+        let room_width = (backdrop.right - backdrop.left - 2 * gap).max(1) as f64;
+        let room_height = (backdrop.bottom - backdrop.top - 2 * gap).max(1) as f64;
+        let paper_width = page.width as f64 / page.dpi.0 as f64;
+        let paper_height = page.height as f64 / page.dpi.1 as f64;
+        let scale = (room_width / paper_width).min(room_height / paper_height);
+        // the corner of the paper, centered in the room.
+        let x0 = backdrop.left + gap + ((room_width - paper_width * scale) / 2.0) as i32;
+        let y0 = backdrop.top + gap + ((room_height - paper_height * scale) / 2.0) as i32;
+        let to_client = |rc: &RECT| RECT {
+            left: x0 + (rc.left as f64 / page.dpi.0 as f64 * scale).round() as i32,
+            top: y0 + (rc.top as f64 / page.dpi.1 as f64 * scale).round() as i32,
+            right: x0 + (rc.right as f64 / page.dpi.0 as f64 * scale).round() as i32,
+            bottom: y0 + (rc.bottom as f64 / page.dpi.1 as f64 * scale).round() as i32,
+        };
+        let sheet = to_client(&RECT {
+            left: 0,
+            top: 0,
+            right: page.width,
+            bottom: page.height,
+        });
+        let printable = to_client(&page.printable);
+        let area = to_client(&layout.area);
+        unsafe {
+            FillRect(hdc, &sheet, HBRUSH(GetStockObject(WHITE_BRUSH).0));
+            FrameRect(hdc, &printable, GetSysColorBrush(COLOR_BTNSHADOW));
+            let saved = SaveDC(hdc);
+            IntersectClipRect(hdc, area.left, area.top, area.right, area.bottom);
+            state.dib.draw(hdc, &to_client(&layout.image));
+            RestoreDC(hdc, saved);
+        }
+    });
+    unsafe { EndPaint(h_wnd, &ps) };
+}
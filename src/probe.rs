@@ -0,0 +1,142 @@
+/// How much of a file `dimensions` looks at. JPEG metadata before the frame header can be
+/// long.
+pub const PROBE_LEN: usize = 256 * 1024;
+
+fn u16_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([b[0], b[1]]) as u32)
+}
+
+fn u16_le(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([b[0], b[1]]) as u32)
+}
+
+fn u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn i32_le(bytes: &[u8], offset: usize) -> Option<i32> {
+    let b = bytes.get(offset..offset + 4)?;
+    Some(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The width and height of the PNG, JPEG, GIF, BMP or LZ4I image starting with `head`, `None`
+/// when the format is unknown or `head` ends before the size.
+pub fn dimensions(head: &[u8]) -> Option<(u32, u32)> {
+    if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // IHDR is always the first chunk.
+        (head.get(12..16)? == b"IHDR").then_some(())?;
+        Some((u32_be(head, 16)?, u32_be(head, 20)?))
+    } else if head.starts_with(&[0xff, 0xd8]) {
+        jpeg(head)
+    } else if head.starts_with(b"GIF8") {
+        Some((u16_le(head, 6)?, u16_le(head, 8)?))
+    } else if head.starts_with(b"BM") {
+        // an OS/2 BITMAPCOREHEADER has 16-bit sizes, the later headers 32-bit ones with a
+        // negative height for top-down rows.
+        if i32_le(head, 14)? == 12 {
+            Some((u16_le(head, 18)?, u16_le(head, 20)?))
+        } else {
+            Some((
+                i32_le(head, 18)?.unsigned_abs(),
+                i32_le(head, 22)?.unsigned_abs(),
+            ))
+        }
+    } else if head.starts_with(b"lz4i") {
+        Some((u32_be(head, 4)?, u32_be(head, 8)?))
+    } else {
+        None
+    }
+}
+
+/// Walks the JPEG markers up to the first frame header.
+fn jpeg(head: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    loop {
+        // markers may be padded with any number of 0xff.
+        while *head.get(pos)? == 0xff && *head.get(pos + 1)? == 0xff {
+            pos += 1;
+        }
+        (*head.get(pos)? == 0xff).then_some(())?;
+        let marker = *head.get(pos + 1)?;
+        match marker {
+            // SOF0 to SOF15, except DHT, JPG and DAC which share the range.
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                let height = u16_be(head, pos + 5)?;
+                let width = u16_be(head, pos + 7)?;
+                return Some((width, height));
+            }
+            // standalone markers without a length.
+            0x01 | 0xd0..=0xd7 => pos += 2,
+            // the scan starts without a frame header, not a valid file.
+            0xd9 | 0xda => return None,
+            _ => pos += 2 + u16_be(head, pos + 2)? as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageOutputFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn encoded(format: ImageOutputFormat) -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(300, 17));
+        let mut data = Cursor::new(Vec::new());
+        img.write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn reads_the_size_of_each_format() {
+        for format in [
+            ImageOutputFormat::Png,
+            ImageOutputFormat::Jpeg(80),
+            ImageOutputFormat::Gif,
+            ImageOutputFormat::Bmp,
+        ] {
+            let data = encoded(format.clone());
+            assert_eq!(dimensions(&data), Some((300, 17)), "{format:?}");
+        }
+        let mut lz4i = b"lz4i".to_vec();
+        lz4i.extend_from_slice(&300u32.to_be_bytes());
+        lz4i.extend_from_slice(&17u32.to_be_bytes());
+        assert_eq!(dimensions(&lz4i), Some((300, 17)));
+    }
+
+    #[test]
+    fn reads_top_down_and_os2_bitmaps() {
+        let mut bmp = encoded(ImageOutputFormat::Bmp);
+        bmp[22..26].copy_from_slice(&(-17i32).to_le_bytes());
+        assert_eq!(dimensions(&bmp), Some((300, 17)));
+        let mut os2 = b"BM".to_vec();
+        os2.extend_from_slice(&[0; 12]);
+        os2.extend_from_slice(&12i32.to_le_bytes());
+        os2.extend_from_slice(&300u16.to_le_bytes());
+        os2.extend_from_slice(&17u16.to_le_bytes());
+        assert_eq!(dimensions(&os2), Some((300, 17)));
+    }
+
+    #[test]
+    fn skips_jpeg_segments_before_the_frame() {
+        let jpeg = encoded(ImageOutputFormat::Jpeg(80));
+        // an APP1 segment and fill bytes between SOI and the rest.
+        let mut padded = jpeg[..2].to_vec();
+        padded.extend_from_slice(&[0xff, 0xe1, 0x00, 0x06, b'E', b'x', b'i', b'f', 0xff]);
+        padded.extend_from_slice(&jpeg[2..]);
+        assert_eq!(dimensions(&padded), Some((300, 17)));
+    }
+
+    #[test]
+    fn truncated_or_unknown_heads_have_no_size() {
+        let png = encoded(ImageOutputFormat::Png);
+        assert_eq!(dimensions(&png[..20]), None);
+        let jpeg = encoded(ImageOutputFormat::Jpeg(80));
+        assert_eq!(dimensions(&jpeg[..4]), None);
+        assert_eq!(dimensions(b"RIFF\0\0\0\0WEBP"), None);
+        assert_eq!(dimensions(&[]), None);
+    }
+}
@@ -0,0 +1,50 @@
+use windows::Win32::{
+    Foundation::{POINT, RECT},
+    Graphics::Gdi::{
+        FrameRect, GetStockObject, InflateRect, BLACK_BRUSH, HBRUSH, HDC, WHITE_BRUSH,
+    },
+};
+
+/// A rectangle being dragged out with the mouse, in client coordinates.
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    /// Where the drag started.
+    pub anchor: POINT,
+    /// Where the mouse is now.
+    pub cursor: POINT,
+}
+
+impl Selection {
+    pub fn new(pt: POINT) -> Selection {
+        Selection {
+            anchor: pt,
+            cursor: pt,
+        }
+    }
+
+    /// The dragged rectangle, whichever way the mouse went.
+    pub fn rect(&self) -> RECT {
+        RECT {
+            left: self.anchor.x.min(self.cursor.x),
+            top: self.anchor.y.min(self.cursor.y),
+            right: self.anchor.x.max(self.cursor.x),
+            bottom: self.anchor.y.max(self.cursor.y),
+        }
+    }
+
+    /// Whether the rectangle is narrower or lower than `min` pixels, as after a twitchy click.
+    pub fn is_degenerate(&self, min: i32) -> bool {
+        let rc = self.rect();
+        rc.right - rc.left < min || rc.bottom - rc.top < min
+    }
+
+    /// Outlines the rectangle in black and white, so it shows on any image.
+    pub fn draw(&self, hdc: HDC) {
+        let mut rc = self.rect();
+        unsafe {
+            FrameRect(hdc, &rc, HBRUSH(GetStockObject(BLACK_BRUSH).0));
+            InflateRect(&mut rc, -1, -1);
+            FrameRect(hdc, &rc, HBRUSH(GetStockObject(WHITE_BRUSH).0));
+        }
+    }
+}
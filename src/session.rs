@@ -0,0 +1,143 @@
+use crate::dib::Scaling;
+use crate::long_path;
+use crate::settings::data_dir;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+/// What the last closed window showed, restored on the next start. Stored as `key=value`
+/// lines in `%APPDATA%\pinion\session.ini`.
+#[derive(Default)]
+pub struct Session {
+    pub tabs: Vec<SessionTab>,
+    /// Index into `tabs` of the tab in front.
+    pub active: usize,
+}
+
+pub struct SessionTab {
+    pub file_path: PathBuf,
+    /// `None` means fit.
+    pub zoom: Option<f32>,
+    /// The scaling chosen with N, `None` when guessed from the image.
+    pub scaling: Option<Scaling>,
+    /// Clockwise quarter turns.
+    pub rotation: u32,
+}
+
+fn session_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("session.ini"))
+}
+
+impl Session {
+    /// Reads the saved session. Anything unreadable is ignored, and tabs whose file is gone
+    /// are dropped, so this never fails.
+    pub fn load() -> Session {
+        session_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| Session::parse(&text))
+            .unwrap_or_default()
+    }
+
+    fn parse(text: &str) -> Session {
+        let mut session = Session::default();
+        let mut active: usize = 0;
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                "active" => active = value.trim().parse().unwrap_or(0),
+                // `fit` or the zoom factor, optionally the scaling and the rotation, then the
+                // path, exactly as written.
+                "tab" => {
+                    let Some((zoom, mut path)) = value.split_once(',') else {
+                        continue;
+                    };
+                    let mut scaling = None;
+                    let mut rotation = 0;
+                    while let Some((field, rest)) = path.split_once(',') {
+                        if let Some(chosen) = Scaling::parse(field) {
+                            scaling = Some(chosen);
+                        } else if let Some(turns) = parse_rotation(field) {
+                            rotation = turns;
+                        } else {
+                            break;
+                        }
+                        path = rest;
+                    }
+                    let zoom = match zoom.trim() {
+                        "fit" => None,
+                        zoom => match zoom.parse::<f32>() {
+                            Ok(zoom) if zoom.is_finite() && zoom > 0.0 => Some(zoom),
+                            _ => continue,
+                        },
+                    };
+                    session.tabs.push(SessionTab {
+                        file_path: long_path::decode(path),
+                        zoom,
+                        scaling,
+                        rotation,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        // keep the same tab in front when files before it have gone.
+        let mut index = 0;
+        let mut removed_before_active = 0;
+        session.tabs.retain(|tab| {
+            let keep = tab.file_path.is_file();
+            if !keep && index < active {
+                removed_before_active += 1;
+            }
+            index += 1;
+            keep
+        });
+        session.active = active
+            .saturating_sub(removed_before_active)
+            .min(session.tabs.len().saturating_sub(1));
+        session
+    }
+
+    fn serialize(&self) -> String {
+        let mut text = format!("active={}\n", self.active);
+        for tab in &self.tabs {
+            let zoom = tab
+                .zoom
+                .map_or_else(|| "fit".to_string(), |zoom| zoom.to_string());
+            let scaling = tab
+                .scaling
+                .map_or_else(String::new, |scaling| format!("{},", scaling.as_str()));
+            let rotation = match tab.rotation % 4 {
+                0 => String::new(),
+                turns => format!("r{},", turns * 90),
+            };
+            text.push_str(&format!(
+                "tab={},{}{}{}\n",
+                zoom,
+                scaling,
+                rotation,
+                long_path::encode(&tab.file_path)
+            ));
+        }
+        text
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = session_path().context("APPDATA is not set.")?;
+        fs::create_dir_all(path.parent().context("no parent")?)?;
+        fs::write(path, self.serialize())?;
+        Ok(())
+    }
+}
+
+/// Parses `r90`, `r180` or `r270` as clockwise quarter turns.
+fn parse_rotation(field: &str) -> Option<u32> {
+    match field {
+        "r90" => Some(1),
+        "r180" => Some(2),
+        "r270" => Some(3),
+        _ => None,
+    }
+}
@@ -0,0 +1,324 @@
+use crate::long_path;
+use crate::slideshow::{self, MAX_INTERVAL_MS, MIN_INTERVAL_MS};
+use crate::theme::Theme;
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// User settings persisted across runs as `key=value` lines in
+/// `%APPDATA%\pinion\settings.ini`.
+pub struct Settings {
+    /// Directory of the last successfully opened file.
+    pub last_dir: Option<PathBuf>,
+    /// Hand files passed on the command line to an already running window.
+    pub single_instance: bool,
+    /// Recently opened files, most recent first.
+    pub recent: Vec<PathBuf>,
+    /// The window placement for each monitor layout seen, most recent first.
+    pub placements: Vec<SavedPlacement>,
+    /// Light or dark window surround, `auto` follows Windows.
+    pub theme: Theme,
+    /// Color around the image and behind transparency as red, green and blue, `None` follows
+    /// the theme.
+    pub background: Option<[u8; 3]>,
+    /// Convert images to the monitor's color profile.
+    pub color_management: bool,
+    /// Time each image of a slideshow shows, stored in seconds.
+    pub slideshow_interval_ms: u32,
+    /// Files larger than this many megabytes ask before opening, 0 never asks.
+    pub confirm_size_mb: u64,
+    /// Files larger than this many megabytes are refused, `None` for no limit.
+    pub max_size_mb: Option<u64>,
+    /// The extensions folder navigation steps through, lowercase and without the dot. `None`
+    /// for all that pinion opens.
+    pub navigate_extensions: Option<Vec<String>>,
+    /// Minimizing hides the window behind an icon in the notification area.
+    pub minimize_to_tray: bool,
+    /// Every this many pixels the pixel grid draws a stronger line, `None` for none.
+    pub grid_guide: Option<u32>,
+}
+
+/// Where the window was on a particular monitor layout.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SavedPlacement {
+    /// See `placement::layout_key`.
+    pub layout: u64,
+    pub maximized: bool,
+    /// The restored window rectangle in workspace coordinates, as left, top, right, bottom.
+    pub rect: [i32; 4],
+}
+
+/// How many files the recent list keeps.
+const MAX_RECENT: usize = 10;
+
+/// How many monitor layouts keep their window placement.
+const MAX_PLACEMENTS: usize = 8;
+
+impl SavedPlacement {
+    /// Parses `layout,maximized,left,top,right,bottom` with the layout in hex.
+    fn parse(value: &str) -> Option<SavedPlacement> {
+        let mut fields = value.split(',').map(str::trim);
+        let layout = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let maximized = fields.next()? == "1";
+        let mut rect = [0; 4];
+        for v in &mut rect {
+            *v = fields.next()?.parse().ok()?;
+        }
+        Some(SavedPlacement {
+            layout,
+            maximized,
+            rect,
+        })
+    }
+
+    fn serialize(&self) -> String {
+        let [left, top, right, bottom] = self.rect;
+        format!(
+            "{:016x},{},{},{},{},{}",
+            self.layout, self.maximized as u8, left, top, right, bottom
+        )
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            last_dir: None,
+            single_instance: false,
+            recent: Vec::new(),
+            placements: Vec::new(),
+            theme: Theme::Auto,
+            background: None,
+            color_management: true,
+            slideshow_interval_ms: slideshow::DEFAULT_INTERVAL_MS,
+            confirm_size_mb: 500,
+            max_size_mb: None,
+            navigate_extensions: None,
+            minimize_to_tray: false,
+            grid_guide: None,
+        }
+    }
+}
+
+static SETTINGS: OnceLock<Mutex<Settings>> = OnceLock::new();
+
+/// Returns the process-wide settings, loading them from disk on first use.
+///
+/// Don't hold the guard across anything that runs a modal loop.
+pub fn settings() -> MutexGuard<'static, Settings> {
+    SETTINGS
+        .get_or_init(|| Mutex::new(Settings::load()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// `%APPDATA%\pinion`, where pinion keeps its files.
+pub fn data_dir() -> Option<PathBuf> {
+    let app_data = env::var_os("APPDATA")?;
+    Some(Path::new(&app_data).join("pinion"))
+}
+
+fn settings_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("settings.ini"))
+}
+
+impl Settings {
+    /// Reads the settings file. A missing or unreadable file yields the defaults.
+    fn load() -> Settings {
+        settings_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| Settings::parse(&text))
+            .unwrap_or_default()
+    }
+
+    fn parse(text: &str) -> Settings {
+        let mut settings = Settings::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            // paths are kept exactly, spaces and all.
+            let path = long_path::decode(value);
+            let value = value.trim();
+            match key.trim() {
+                "last_dir" if !value.is_empty() => settings.last_dir = Some(path),
+                "single_instance" => settings.single_instance = value == "true",
+                "recent" if !value.is_empty() => settings.recent.push(path),
+                "theme" => settings.theme = Theme::parse(value).unwrap_or_default(),
+                "color_management" => settings.color_management = value != "false",
+                "background" => settings.background = parse_color(value),
+                "placement" => settings.placements.extend(SavedPlacement::parse(value)),
+                "confirm_size" => {
+                    if let Ok(mb) = value.parse() {
+                        settings.confirm_size_mb = mb;
+                    }
+                }
+                "max_size" => settings.max_size_mb = value.parse().ok(),
+                "navigate" => settings.navigate_extensions = parse_extensions(value),
+                "minimize_to_tray" => settings.minimize_to_tray = value == "true",
+                "grid_guide" => settings.grid_guide = value.parse().ok().filter(|&n| n > 1),
+                "slideshow_interval" => {
+                    if let Ok(seconds) = value.parse::<f64>() {
+                        settings.slideshow_interval_ms = ((seconds * 1000.0).round() as u32)
+                            .clamp(MIN_INTERVAL_MS, MAX_INTERVAL_MS);
+                    }
+                }
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    fn serialize(&self) -> String {
+        let mut text = String::new();
+        if let Some(dir) = &self.last_dir {
+            text.push_str(&format!("last_dir={}\n", long_path::encode(dir)));
+        }
+        text.push_str(&format!("single_instance={}\n", self.single_instance));
+        text.push_str(&format!("theme={}\n", self.theme.as_str()));
+        text.push_str(&format!("color_management={}\n", self.color_management));
+        match self.background {
+            Some([r, g, b]) => text.push_str(&format!("background=#{r:02x}{g:02x}{b:02x}\n")),
+            None => text.push_str("background=theme\n"),
+        }
+        text.push_str(&format!("confirm_size={}\n", self.confirm_size_mb));
+        match self.max_size_mb {
+            Some(mb) => text.push_str(&format!("max_size={mb}\n")),
+            None => text.push_str("max_size=off\n"),
+        }
+        match &self.navigate_extensions {
+            Some(extensions) => text.push_str(&format!("navigate={}\n", extensions.join(","))),
+            None => text.push_str("navigate=all\n"),
+        }
+        text.push_str(&format!("minimize_to_tray={}\n", self.minimize_to_tray));
+        match self.grid_guide {
+            Some(n) => text.push_str(&format!("grid_guide={n}\n")),
+            None => text.push_str("grid_guide=off\n"),
+        }
+        text.push_str(&format!(
+            "slideshow_interval={}\n",
+            slideshow::seconds(self.slideshow_interval_ms)
+        ));
+        for file in &self.recent {
+            text.push_str(&format!("recent={}\n", long_path::encode(file)));
+        }
+        for placement in &self.placements {
+            text.push_str(&format!("placement={}\n", placement.serialize()));
+        }
+        text
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = settings_path().context("APPDATA is not set.")?;
+        fs::create_dir_all(path.parent().context("no parent")?)?;
+        fs::write(path, self.serialize())?;
+        Ok(())
+    }
+
+    /// The remembered directory, if it still exists.
+    pub fn initial_dir(&self) -> Option<&Path> {
+        self.last_dir.as_deref().filter(|dir| dir.is_dir())
+    }
+
+    /// Remembers `file_path` and its directory. Call only after the file loaded successfully.
+    ///
+    /// Returns whether the recent list changed.
+    pub fn remember_file(&mut self, file_path: &Path) -> Result<bool> {
+        let file_path = path::absolute(file_path)?;
+        let dir = file_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(normalize_dir);
+
+        let recent_changed = self.recent.first() != Some(&file_path);
+        if recent_changed {
+            self.recent.retain(|file| file != &file_path);
+            self.recent.insert(0, file_path);
+            self.recent.truncate(MAX_RECENT);
+        }
+        if recent_changed || (dir.is_some() && self.last_dir != dir) {
+            self.last_dir = dir.or(self.last_dir.take());
+            self.save()?;
+        }
+        Ok(recent_changed)
+    }
+
+    /// The placement last saved on the monitor layout `layout`.
+    pub fn placement(&self, layout: u64) -> Option<SavedPlacement> {
+        self.placements.iter().find(|p| p.layout == layout).copied()
+    }
+
+    /// Saves `placement`, replacing the one of the same monitor layout.
+    pub fn remember_placement(&mut self, placement: SavedPlacement) -> Result<()> {
+        if self.placements.first() == Some(&placement) {
+            return Ok(());
+        }
+        self.placements.retain(|p| p.layout != placement.layout);
+        self.placements.insert(0, placement);
+        self.placements.truncate(MAX_PLACEMENTS);
+        self.save()
+    }
+
+    /// Drops recent files that no longer exist. Returns whether any were dropped.
+    pub fn prune_recent(&mut self) -> Result<bool> {
+        let len = self.recent.len();
+        self.recent.retain(|file| file.is_file());
+        if self.recent.len() == len {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+}
+
+/// Parses a comma-separated list such as `png, .jpg`. `all` or an empty list yields `None`.
+fn parse_extensions(value: &str) -> Option<Vec<String>> {
+    let extensions: Vec<String> = value
+        .split(',')
+        .map(|ext| ext.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect();
+    (!extensions.is_empty() && extensions != ["all"]).then_some(extensions)
+}
+
+/// Parses `#rrggbb`. Anything else, such as `theme`, yields `None`.
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Drops trailing separators while keeping roots such as `C:\` and `\\server\share\` intact.
+fn normalize_dir(dir: &Path) -> PathBuf {
+    dir.components().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+
+    #[test]
+    fn paths_round_trip() {
+        let mut units: Vec<u16> = r"C:\photos\ ".encode_utf16().collect();
+        units.push(0xdc00);
+        let odd = PathBuf::from(OsString::from_wide(&units));
+        let settings = Settings {
+            last_dir: Some(odd.clone()),
+            recent: vec![
+                PathBuf::from("C:\\photos\\\u{1f600}.png"),
+                odd.join("50%.png"),
+            ],
+            ..Default::default()
+        };
+        let parsed = Settings::parse(&settings.serialize());
+        assert_eq!(parsed.last_dir, Some(odd));
+        assert_eq!(parsed.recent, settings.recent);
+    }
+}
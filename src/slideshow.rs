@@ -0,0 +1,162 @@
+/// Interval limits and the default, in milliseconds.
+pub const MIN_INTERVAL_MS: u32 = 500;
+pub const MAX_INTERVAL_MS: u32 = 300_000;
+pub const DEFAULT_INTERVAL_MS: u32 = 3000;
+
+/// The intervals + and - step through, in milliseconds.
+const STEPS: [u32; 16] = [
+    500, 1000, 2000, 3000, 5000, 10_000, 15_000, 20_000, 30_000, 45_000, 60_000, 90_000, 120_000,
+    180_000, 240_000, 300_000,
+];
+
+/// What the window does with its slideshow timer after an event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timer {
+    /// Start the timer at this interval, replacing a pending one.
+    Set(u32),
+    Kill,
+    Keep,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Phase {
+    #[default]
+    Stopped,
+    Running,
+    Paused,
+}
+
+/// The slideshow's bookkeeping, deciding when the timer restarts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slideshow {
+    phase: Phase,
+    interval_ms: u32,
+}
+
+impl Default for Slideshow {
+    fn default() -> Self {
+        Slideshow::new(DEFAULT_INTERVAL_MS)
+    }
+}
+
+impl Slideshow {
+    pub fn new(interval_ms: u32) -> Slideshow {
+        Slideshow {
+            phase: Phase::Stopped,
+            interval_ms: interval_ms.clamp(MIN_INTERVAL_MS, MAX_INTERVAL_MS),
+        }
+    }
+
+    /// Whether the show is on, running or paused.
+    pub fn is_active(&self) -> bool {
+        self.phase != Phase::Stopped
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.phase == Phase::Paused
+    }
+
+    pub fn interval_ms(&self) -> u32 {
+        self.interval_ms
+    }
+
+    pub fn start(&mut self) -> Timer {
+        self.phase = Phase::Running;
+        Timer::Set(self.interval_ms)
+    }
+
+    pub fn stop(&mut self) -> Timer {
+        match std::mem::take(&mut self.phase) {
+            Phase::Stopped => Timer::Keep,
+            _ => Timer::Kill,
+        }
+    }
+
+    /// Pauses a running show or resumes a paused one with a full interval.
+    pub fn toggle_pause(&mut self) -> Timer {
+        match self.phase {
+            Phase::Running => {
+                self.phase = Phase::Paused;
+                Timer::Kill
+            }
+            Phase::Paused => self.start(),
+            Phase::Stopped => Timer::Keep,
+        }
+    }
+
+    /// Steps to the next longer or shorter interval. A running show waits the new interval
+    /// from now on.
+    pub fn step_interval(&mut self, longer: bool) -> Timer {
+        let current = self.interval_ms;
+        self.interval_ms = if longer {
+            STEPS.into_iter().find(|&ms| ms > current)
+        } else {
+            STEPS.into_iter().rev().find(|&ms| ms < current)
+        }
+        .unwrap_or(current);
+        match self.phase {
+            Phase::Running => Timer::Set(self.interval_ms),
+            _ => Timer::Keep,
+        }
+    }
+
+    /// After the user moved to another image: a running show gives it a full interval, a
+    /// paused one stays paused.
+    pub fn navigated(&self) -> Timer {
+        match self.phase {
+            Phase::Running => Timer::Set(self.interval_ms),
+            _ => Timer::Keep,
+        }
+    }
+}
+
+/// `ms` as seconds for the title and the settings file, such as `0.5` or `3`.
+pub fn seconds(ms: u32) -> String {
+    format!("{}", ms as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn navigating_restarts_only_a_running_show() {
+        let mut show = Slideshow::new(2000);
+        assert_eq!(show.navigated(), Timer::Keep);
+        assert_eq!(show.start(), Timer::Set(2000));
+        assert_eq!(show.navigated(), Timer::Set(2000));
+        assert_eq!(show.toggle_pause(), Timer::Kill);
+        assert_eq!(show.navigated(), Timer::Keep);
+        assert_eq!(show.toggle_pause(), Timer::Set(2000));
+        assert_eq!(show.stop(), Timer::Kill);
+        assert_eq!(show.navigated(), Timer::Keep);
+        assert_eq!(show.stop(), Timer::Keep);
+    }
+
+    #[test]
+    fn stepping_restarts_a_running_show_at_the_new_interval() {
+        let mut show = Slideshow::new(3000);
+        assert_eq!(show.step_interval(true), Timer::Keep);
+        assert_eq!(show.interval_ms(), 5000);
+        show.start();
+        assert_eq!(show.step_interval(false), Timer::Set(3000));
+        show.toggle_pause();
+        assert_eq!(show.step_interval(false), Timer::Keep);
+        assert_eq!(show.toggle_pause(), Timer::Set(2000));
+    }
+
+    #[test]
+    fn intervals_stay_within_the_limits() {
+        assert_eq!(Slideshow::new(10).interval_ms(), MIN_INTERVAL_MS);
+        assert_eq!(Slideshow::new(u32::MAX).interval_ms(), MAX_INTERVAL_MS);
+        let mut show = Slideshow::new(MAX_INTERVAL_MS);
+        show.step_interval(true);
+        assert_eq!(show.interval_ms(), MAX_INTERVAL_MS);
+        // between two steps, the next one either way.
+        let mut show = Slideshow::new(4000);
+        show.step_interval(false);
+        assert_eq!(show.interval_ms(), 3000);
+        assert_eq!(seconds(500), "0.5");
+        assert_eq!(seconds(3000), "3");
+    }
+}
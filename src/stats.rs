@@ -0,0 +1,156 @@
+use image::{ColorType, DynamicImage, GenericImageView};
+use std::fmt;
+
+/// Per-channel numbers over an image or a region of it, in 8-bit units.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub channels: Vec<ChannelStats>,
+    pub pixels: u64,
+    /// Pixels whose color channels are all 0.
+    pub black: u64,
+    /// Pixels whose color channels are all 255.
+    pub white: u64,
+    /// Pixels with an alpha below 255.
+    pub translucent: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub name: &'static str,
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+fn channel_names(color: ColorType) -> &'static [&'static str] {
+    match color {
+        ColorType::L8 | ColorType::L16 => &["L"],
+        ColorType::La8 | ColorType::La16 => &["L", "A"],
+        c if c.has_alpha() => &["R", "G", "B", "A"],
+        _ => &["R", "G", "B"],
+    }
+}
+
+/// Computes the statistics of `img`, or of `region` given as left, top, right and bottom in
+/// image pixels.
+pub fn compute(img: &DynamicImage, region: Option<[u32; 4]>) -> Stats {
+    let [left, top, right, bottom] = region.unwrap_or([0, 0, img.width(), img.height()]);
+    let (left, top) = (left.min(img.width()), top.min(img.height()));
+    let (right, bottom) = (right.min(img.width()), bottom.min(img.height()));
+    let (width, height) = (right.saturating_sub(left), bottom.saturating_sub(top));
+    let names = channel_names(img.color());
+    let gray = names[0] == "L";
+    // R, G, B, A, whatever the image has.
+    let mut min = [u8::MAX; 4];
+    let mut max = [0u8; 4];
+    let mut sum = [0u64; 4];
+    let mut sum_sq = [0u64; 4];
+    let mut stats = Stats {
+        channels: Vec::new(),
+        pixels: width as u64 * height as u64,
+        black: 0,
+        white: 0,
+        translucent: 0,
+    };
+    for (_, _, px) in img.view(left, top, width, height).pixels() {
+        let px = px.0;
+        for c in 0..4 {
+            min[c] = min[c].min(px[c]);
+            max[c] = max[c].max(px[c]);
+            sum[c] += px[c] as u64;
+            sum_sq[c] += px[c] as u64 * px[c] as u64;
+        }
+        let color = &px[..3];
+        if color.iter().all(|&v| v == 0) {
+            stats.black += 1;
+        } else if color.iter().all(|&v| v == 255) {
+            stats.white += 1;
+        }
+        if px[3] < 255 {
+            stats.translucent += 1;
+        }
+    }
+
+    let n = stats.pixels.max(1) as f64;
+    for (i, name) in names.iter().enumerate() {
+        // gray images repeat L in R, G and B, their alpha is the fourth channel either way.
+        let c = if gray && i == 1 { 3 } else { i };
+        let mean = sum[c] as f64 / n;
+        let variance = (sum_sq[c] as f64 / n - mean * mean).max(0.0);
+        stats.channels.push(ChannelStats {
+            name,
+            min: if stats.pixels == 0 { 0 } else { min[c] },
+            max: max[c],
+            mean,
+            std_dev: variance.sqrt(),
+        });
+    }
+    stats
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in &self.channels {
+            writeln!(
+                f,
+                "{}: min {}, max {}, mean {:.2}, std dev {:.2}",
+                c.name, c.min, c.max, c.mean, c.std_dev
+            )?;
+        }
+        write!(
+            f,
+            "{} pixels: {} black, {} white, {} with alpha below 255",
+            self.pixels, self.black, self.white, self.translucent
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+    #[test]
+    fn counts_and_averages_each_channel() {
+        // black, white, half red and red at half alpha.
+        let img = RgbaImage::from_fn(2, 2, |x, y| match (x, y) {
+            (0, 0) => Rgba([0, 0, 0, 255]),
+            (1, 0) => Rgba([255, 255, 255, 255]),
+            (0, 1) => Rgba([128, 0, 0, 255]),
+            _ => Rgba([255, 0, 0, 128]),
+        });
+        let stats = compute(&DynamicImage::ImageRgba8(img), None);
+        assert_eq!(stats.pixels, 4);
+        assert_eq!((stats.black, stats.white, stats.translucent), (1, 1, 1));
+        let names: Vec<_> = stats.channels.iter().map(|c| c.name).collect();
+        assert_eq!(names, ["R", "G", "B", "A"]);
+        let red = &stats.channels[0];
+        assert_eq!((red.min, red.max), (0, 255));
+        assert!((red.mean - 159.5).abs() < 1e-9);
+        let green = &stats.channels[1];
+        assert!((green.mean - 63.75).abs() < 1e-9);
+        assert!((green.std_dev - 63.75 * 3f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn regions_are_clamped_to_the_image() {
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(4, 4, |x, _| Luma([x as u8 * 10])));
+        let stats = compute(&img, Some([2, 1, 10, 3]));
+        assert_eq!(stats.pixels, 4);
+        assert_eq!(stats.channels.len(), 1);
+        let gray = &stats.channels[0];
+        assert_eq!((gray.name, gray.min, gray.max), ("L", 20, 30));
+        assert!((gray.mean - 25.0).abs() < 1e-9);
+        assert_eq!(gray.std_dev, 5.0);
+    }
+
+    #[test]
+    fn an_empty_region_has_no_pixels() {
+        let img = DynamicImage::ImageLuma8(GrayImage::new(4, 4));
+        let stats = compute(&img, Some([3, 3, 3, 3]));
+        assert_eq!(stats.pixels, 0);
+        assert_eq!((stats.channels[0].min, stats.channels[0].max), (0, 0));
+        assert_eq!(stats.channels[0].mean, 0.0);
+    }
+}
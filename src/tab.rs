@@ -0,0 +1,629 @@
+use crate::channel::Channel;
+use crate::color::ColorTransform;
+use crate::dib::{Dib, Scaling};
+use crate::filter::Filter;
+use crate::measure::Measure;
+use crate::navigation::Navigation;
+use crate::stats::Stats;
+use crate::theme;
+use crate::view::View;
+use image::{imageops, DynamicImage, GenericImageView, Rgba};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use windows::Win32::{Foundation::RECT, Graphics::Gdi::HDC};
+
+/// One open image with its own view and navigation list.
+///
+/// The decoded pixels of a background tab may be dropped to save memory; the file is decoded
+/// again when the tab becomes active.
+pub struct Tab {
+    pub file_path: PathBuf,
+    pub navigation: Navigation,
+    pub view: View,
+    /// Kept at full resolution, shared with background work such as the statistics.
+    image: Option<Arc<DynamicImage>>,
+    /// `image` ready for drawing, and a smoothly downscaled copy for fit mode. Derived when
+    /// first drawn.
+    dib: Option<Dib>,
+    fitted: Option<Dib>,
+    /// The visible part of `image` smoothly enlarged, with that part in image pixels.
+    zoomed: Option<([u32; 4], Dib)>,
+    /// Chosen with N, `None` uses `auto_scaling`.
+    scaling: Option<Scaling>,
+    /// Guessed from the image: nearest for small or few-colored images.
+    auto_scaling: Scaling,
+    /// Shown instead of `image`, such as its difference to another tab. The same size as
+    /// `image` and dropped when that changes.
+    replacement: Option<Arc<DynamicImage>>,
+    /// Shown alone instead of all of `image`, and that channel as an image. Derived when first
+    /// drawn.
+    channel: Option<Channel>,
+    isolated: Option<Arc<DynamicImage>>,
+    /// Shown instead of `image` while its key is held, and its display copy at the size
+    /// drawn.
+    preview: Option<Filter>,
+    filtered: Option<Dib>,
+    /// Clockwise quarter turns applied to the decoded pixels.
+    rotation: u32,
+    /// Filters applied to the decoded pixels after the rotation, in order.
+    filters: Vec<Filter>,
+    /// Earlier views to go back to, the most recent last.
+    view_history: Vec<View>,
+    /// The region marked with Shift and a drag, left, top, right and bottom in image pixels.
+    selection: Option<[u32; 4]>,
+    /// The distance measured with M and a drag.
+    measure: Option<Measure>,
+    /// The statistics last computed, with the region they cover.
+    stats: Option<(Option<[u32; 4]>, Stats)>,
+    /// When the image was pasted from the clipboard, `None` for a file.
+    pasted: Option<String>,
+    /// Whether `file_path` has been decoded before, so dropping and decoding it again does not
+    /// ask about its size.
+    was_loaded: bool,
+    /// Increases whenever the tab is activated, the smallest is the least recently used.
+    pub last_active: u64,
+}
+
+/// How many earlier views [`Tab::view_back`] can return to.
+const MAX_VIEW_HISTORY: usize = 8;
+
+fn rotate_image(img: DynamicImage, quarter_turns: u32) -> DynamicImage {
+    match quarter_turns % 4 {
+        1 => img.rotate90(),
+        2 => img.rotate180(),
+        3 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Images smaller than this both ways start with nearest scaling.
+const PIXEL_ART_SIZE: u32 = 256;
+/// So do images of at most this many colors.
+const PIXEL_ART_COLORS: usize = 64;
+/// At most this many pixels on an even grid are counted, the whole of smaller images.
+const SAMPLED_PIXELS: u64 = 512 * 512;
+
+/// Nearest for what is likely pixel art, smooth otherwise.
+pub fn guess_scaling(img: &DynamicImage) -> Scaling {
+    if img.width() < PIXEL_ART_SIZE && img.height() < PIXEL_ART_SIZE {
+        return Scaling::Nearest;
+    }
+    let pixels = img.width() as u64 * img.height() as u64;
+    let step = ((pixels as f64 / SAMPLED_PIXELS as f64).sqrt().ceil() as usize).max(1);
+    let sampled = (0..img.height())
+        .step_by(step)
+        .flat_map(|y| (0..img.width()).step_by(step).map(move |x| (x, y)))
+        .map(|(x, y)| img.get_pixel(x, y));
+    // a photo gives up within the first few pixels.
+    let mut colors: Vec<Rgba<u8>> = Vec::new();
+    let mut last = None;
+    for pixel in sampled {
+        if last == Some(pixel) {
+            continue;
+        }
+        last = Some(pixel);
+        if !colors.contains(&pixel) {
+            if colors.len() == PIXEL_ART_COLORS {
+                return Scaling::Smooth;
+            }
+            colors.push(pixel);
+        }
+    }
+    Scaling::Nearest
+}
+
+/// `img` resized to `size` as the view shows it with `scaling`, Lanczos when smooth.
+pub fn resample(img: &DynamicImage, size: (u32, u32), scaling: Scaling) -> DynamicImage {
+    let filter = match scaling {
+        Scaling::Smooth => imageops::Lanczos3,
+        Scaling::Nearest => imageops::Nearest,
+    };
+    img.resize_exact(size.0, size.1, filter)
+}
+
+/// The part of `img` drawn at `rect` that shows in `viewport`, in whole image pixels, and
+/// where it goes.
+fn visible_part(img: &DynamicImage, rect: &RECT, viewport: &RECT) -> Option<([u32; 4], RECT)> {
+    let scale_x = (rect.right - rect.left) as f64 / img.width() as f64;
+    let scale_y = (rect.bottom - rect.top) as f64 / img.height() as f64;
+    let to_image = |client: i32, origin: i32, scale: f64| (client - origin) as f64 / scale;
+    let left = to_image(viewport.left, rect.left, scale_x).floor().max(0.0) as u32;
+    let top = to_image(viewport.top, rect.top, scale_y).floor().max(0.0) as u32;
+    let right =
+        (to_image(viewport.right, rect.left, scale_x).ceil().max(0.0) as u32).min(img.width());
+    let bottom =
+        (to_image(viewport.bottom, rect.top, scale_y).ceil().max(0.0) as u32).min(img.height());
+    if right <= left || bottom <= top {
+        return None;
+    }
+    let dest = RECT {
+        left: rect.left + (left as f64 * scale_x).round() as i32,
+        top: rect.top + (top as f64 * scale_y).round() as i32,
+        right: rect.left + (right as f64 * scale_x).round() as i32,
+        bottom: rect.top + (bottom as f64 * scale_y).round() as i32,
+    };
+    Some(([left, top, right, bottom], dest))
+}
+
+/// Converts `img` for drawing: transparent pixels on the background color, then through the
+/// monitor's color profile.
+fn display_dib(img: &DynamicImage, color: Option<&ColorTransform>) -> Dib {
+    Dib::with_color(img, theme::background_rgb(), color)
+}
+
+impl Tab {
+    pub fn new(file_path: &Path, navigation: Navigation) -> Tab {
+        Tab {
+            file_path: file_path.to_path_buf(),
+            navigation,
+            view: View::default(),
+            image: None,
+            dib: None,
+            fitted: None,
+            zoomed: None,
+            scaling: None,
+            auto_scaling: Scaling::default(),
+            replacement: None,
+            channel: None,
+            isolated: None,
+            preview: None,
+            filtered: None,
+            rotation: 0,
+            filters: Vec::new(),
+            view_history: Vec::new(),
+            selection: None,
+            measure: None,
+            stats: None,
+            pasted: None,
+            was_loaded: false,
+            last_active: 0,
+        }
+    }
+
+    /// Shows `img` as the tab's file, keeping the view. The rotation and applied filters are
+    /// kept when reloading the same file.
+    pub fn set_image(&mut self, file_path: &Path, img: DynamicImage) {
+        if file_path != self.file_path || self.pasted.is_some() {
+            self.forget_edits();
+        }
+        self.pasted = None;
+        self.was_loaded = true;
+        self.file_path = file_path.to_path_buf();
+        let img = rotate_image(img, self.rotation);
+        let img = self
+            .filters
+            .iter()
+            .fold(img, |img, filter| filter.apply(&img));
+        self.auto_scaling = guess_scaling(&img);
+        self.image = Some(Arc::new(img));
+        self.replacement = None;
+        self.invalidate_display();
+    }
+
+    /// Shows `img` from the clipboard, which arrived at `time`, fitted.
+    pub fn paste(&mut self, img: DynamicImage, time: &str) {
+        self.forget_edits();
+        // a name to save it under.
+        self.file_path = PathBuf::from(format!("clipboard-{}", time.replace(':', "")));
+        self.pasted = Some(time.to_string());
+        self.navigation = Navigation::default();
+        self.auto_scaling = guess_scaling(&img);
+        self.image = Some(Arc::new(img));
+        self.replacement = None;
+        self.invalidate_display();
+        self.reset_view();
+    }
+
+    /// Whether the image comes from `file_path`, rather than the clipboard.
+    pub fn is_file(&self) -> bool {
+        self.pasted.is_none()
+    }
+
+    fn forget_edits(&mut self) {
+        self.rotation = 0;
+        self.filters.clear();
+        self.preview = None;
+        self.selection = None;
+        self.measure = None;
+        self.stats = None;
+        self.scaling = None;
+        self.channel = None;
+    }
+
+    /// Turns the image clockwise by `quarter_turns` and fits it again.
+    pub fn rotate(&mut self, quarter_turns: u32) {
+        let Some(img) = self.image.take() else {
+            return;
+        };
+        // copied only while the statistics still read the old pixels.
+        let img = Arc::try_unwrap(img).unwrap_or_else(|img| (*img).clone());
+        self.image = Some(Arc::new(rotate_image(img, quarter_turns)));
+        self.rotation = (self.rotation + quarter_turns) % 4;
+        self.replacement = None;
+        self.selection = None;
+        self.measure = None;
+        self.stats = None;
+        self.invalidate_display();
+        self.reset_view();
+    }
+
+    /// Clockwise quarter turns from the file's pixels to the image shown.
+    pub fn rotation(&self) -> u32 {
+        self.rotation
+    }
+
+    /// Turns the file by `quarter_turns` once it loads, for a tab not loaded yet.
+    pub fn set_rotation(&mut self, quarter_turns: u32) {
+        self.rotation = quarter_turns % 4;
+    }
+
+    /// Shows `img` instead of the image, or the image again with `None`.
+    pub fn show_instead(&mut self, img: Option<Arc<DynamicImage>>) {
+        let same = match (&self.replacement, &img) {
+            (Some(shown), Some(img)) => Arc::ptr_eq(shown, img),
+            (shown, img) => shown.is_none() && img.is_none(),
+        };
+        if !same {
+            self.replacement = img;
+            self.invalidate_display();
+        }
+    }
+
+    /// Whether something is shown instead of the image.
+    pub fn is_replaced(&self) -> bool {
+        self.replacement.is_some()
+    }
+
+    /// The channel shown alone, `None` for the whole image.
+    pub fn channel(&self) -> Option<Channel> {
+        self.channel
+    }
+
+    /// Shows only `channel` of the image as gray, or all of it again. The image itself keeps
+    /// every channel.
+    pub fn isolate(&mut self, channel: Option<Channel>) {
+        if channel != self.channel {
+            self.channel = channel;
+            self.invalidate_display();
+        }
+    }
+
+    /// How the image is scaled for display.
+    pub fn scaling(&self) -> Scaling {
+        self.scaling.unwrap_or(self.auto_scaling)
+    }
+
+    /// The scaling chosen with [`Tab::toggle_scaling`], `None` when guessed.
+    pub fn chosen_scaling(&self) -> Option<Scaling> {
+        self.scaling
+    }
+
+    /// Chooses the scaling, as the session restores it.
+    pub fn set_scaling(&mut self, scaling: Option<Scaling>) {
+        self.scaling = scaling;
+        self.invalidate_display();
+    }
+
+    /// Switches between smooth and nearest scaling. Returns the new one.
+    pub fn toggle_scaling(&mut self) -> Scaling {
+        let scaling = self.scaling().toggled();
+        self.set_scaling(Some(scaling));
+        scaling
+    }
+
+    /// Shows `filter` over the image without changing it, or the image itself again.
+    pub fn preview(&mut self, filter: Option<Filter>) {
+        if filter != self.preview {
+            self.preview = filter;
+            self.filtered = None;
+        }
+    }
+
+    /// Filters the image at full resolution, so it is saved that way.
+    pub fn apply_filter(&mut self, filter: Filter) {
+        let Some(img) = &self.image else {
+            return;
+        };
+        self.image = Some(Arc::new(filter.apply(img)));
+        self.replacement = None;
+        self.filters.push(filter);
+        self.stats = None;
+        self.invalidate_display();
+    }
+
+    /// The filters applied to the file's pixels, in order.
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// Takes back the last applied filter by dropping the pixels, so that the file loads
+    /// again with the others. Returns whether there was one; pasted images have no file and
+    /// keep theirs.
+    pub fn undo_filter(&mut self) -> bool {
+        if !self.is_file() || self.filters.pop().is_none() {
+            return false;
+        }
+        self.stats = None;
+        self.unload();
+        true
+    }
+
+    /// Shows the image fitted, forgetting earlier views.
+    pub fn reset_view(&mut self) {
+        self.view = View::default();
+        self.view_history.clear();
+    }
+
+    /// Remembers the current view for [`Tab::view_back`], before a jump such as zooming to a
+    /// selection.
+    pub fn push_view(&mut self) {
+        if self.view_history.len() == MAX_VIEW_HISTORY {
+            self.view_history.remove(0);
+        }
+        self.view_history.push(self.view);
+    }
+
+    /// Returns to the view before the last [`Tab::push_view`]. Returns whether there was one.
+    pub fn view_back(&mut self) -> bool {
+        match self.view_history.pop() {
+            Some(view) => {
+                self.view = view;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops the display copies, so the next draw derives them again from the image. Call
+    /// when the background color or the color transform changes.
+    pub fn invalidate_display(&mut self) {
+        self.dib = None;
+        self.fitted = None;
+        self.zoomed = None;
+        self.filtered = None;
+        self.isolated = None;
+    }
+
+    pub fn image(&self) -> Option<&DynamicImage> {
+        self.image.as_deref()
+    }
+
+    /// The image, for use on another thread.
+    pub fn shared_image(&self) -> Option<Arc<DynamicImage>> {
+        self.image.clone()
+    }
+
+    pub fn selection(&self) -> Option<[u32; 4]> {
+        self.selection
+    }
+
+    /// Marks a region, or none for the whole image.
+    pub fn set_selection(&mut self, selection: Option<[u32; 4]>) {
+        self.selection = selection;
+    }
+
+    pub fn measure(&self) -> Option<&Measure> {
+        self.measure.as_ref()
+    }
+
+    pub fn measure_mut(&mut self) -> Option<&mut Measure> {
+        self.measure.as_mut()
+    }
+
+    pub fn set_measure(&mut self, measure: Option<Measure>) {
+        self.measure = measure;
+    }
+
+    /// The statistics of the selection or the whole image, if they were computed.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats
+            .as_ref()
+            .filter(|(region, _)| *region == self.selection)
+            .map(|(_, stats)| stats)
+    }
+
+    /// Keeps the statistics of `region`, unless the selection changed in the meantime.
+    pub fn set_stats(&mut self, region: Option<[u32; 4]>, stats: Stats) {
+        if region == self.selection {
+            self.stats = Some((region, stats));
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.image.is_some()
+    }
+
+    /// Whether the file has been opened in this tab before, see [`Tab::unload`].
+    pub fn was_loaded(&self) -> bool {
+        self.was_loaded
+    }
+
+    /// Drops the decoded pixels, keeping the file, view and navigation.
+    pub fn unload(&mut self) {
+        self.image = None;
+        self.replacement = None;
+        self.invalidate_display();
+    }
+
+    /// Bytes held by the decoded pixels.
+    pub fn memory(&self) -> usize {
+        [&self.image, &self.replacement, &self.isolated]
+            .iter()
+            .filter_map(|img| img.as_ref())
+            .map(|img| img.as_bytes().len())
+            .sum::<usize>()
+            + [&self.dib, &self.fitted, &self.filtered]
+                .iter()
+                .filter_map(|dib| dib.as_ref())
+                .chain(self.zoomed.as_ref().map(|(_, dib)| dib))
+                .map(Dib::len)
+                .sum::<usize>()
+    }
+
+    /// The title shown on the tab.
+    pub fn title(&self) -> String {
+        if let Some(time) = &self.pasted {
+            return format!("(clipboard) {time}");
+        }
+        self.file_path
+            .file_name()
+            .unwrap_or(self.file_path.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn image_size(&self) -> Option<(u32, u32)> {
+        self.image.as_ref().map(|img| (img.width(), img.height()))
+    }
+
+    pub fn can_pan(&self, viewport: &RECT) -> bool {
+        self.image_size()
+            .is_some_and(|size| self.view.can_pan(viewport, size))
+    }
+
+    /// Draws the image into `viewport`, converted by `color` when given. During a live resize
+    /// the slow smoothly scaled copies are skipped.
+    pub fn draw(
+        &mut self,
+        hdc: HDC,
+        viewport: &RECT,
+        resizing: bool,
+        color: Option<&ColorTransform>,
+    ) {
+        let Some(mut img) = self.replacement.clone().or_else(|| self.image.clone()) else {
+            return;
+        };
+        if let Some(channel) = self.channel {
+            img = self
+                .isolated
+                .get_or_insert_with(|| Arc::new(channel.isolate(&img)))
+                .clone();
+        }
+        let img = &*img;
+        let size = (img.width(), img.height());
+        if self.dib.is_none() {
+            self.dib = Some(display_dib(img, color));
+        }
+        let rect = self.view.image_rect(viewport, size);
+        let scaling = self.scaling();
+        if let Some(filter) = self.preview {
+            // filtered at the size drawn when that is smaller, to stay quick on large images.
+            let drawn = (
+                ((rect.right - rect.left).max(1) as u32).min(size.0),
+                ((rect.bottom - rect.top).max(1) as u32).min(size.1),
+            );
+            if self.filtered.as_ref().map(Dib::size) != Some(drawn) {
+                let filtered = if drawn == size {
+                    filter.apply(img)
+                } else {
+                    let scale = drawn.0 as f32 / size.0 as f32;
+                    filter.apply_scaled(&resample(img, drawn, scaling), scale)
+                };
+                self.filtered = Some(display_dib(&filtered, color));
+            }
+            if let Some(filtered) = &self.filtered {
+                filtered.draw_scaled(hdc, &rect, scaling);
+                return;
+            }
+        }
+        let smooth = scaling == Scaling::Smooth && !resizing;
+        if smooth && rect.right - rect.left > size.0 as i32 {
+            // GDI only enlarges blocky, so the visible part is enlarged bilinearly.
+            if let Some((part, dest)) = visible_part(img, &rect, viewport) {
+                let [left, top, right, bottom] = part;
+                let zoomed_size = (
+                    (dest.right - dest.left) as u32,
+                    (dest.bottom - dest.top) as u32,
+                );
+                // the same pixels at the same size only move while panning.
+                let cached = self.zoomed.as_ref().map(|(part, dib)| (*part, dib.size()));
+                if cached != Some((part, zoomed_size)) {
+                    let zoomed = img
+                        .crop_imm(left, top, right - left, bottom - top)
+                        .resize_exact(zoomed_size.0, zoomed_size.1, imageops::Triangle);
+                    self.zoomed = Some((part, display_dib(&zoomed, color)));
+                }
+                if let Some((_, zoomed)) = &self.zoomed {
+                    zoomed.draw(hdc, &dest);
+                    return;
+                }
+            }
+        }
+        if smooth && self.view.is_fit() && View::fit_scale(viewport, size) < 1.0 {
+            // GDI's HALFTONE is fast but coarse, the fitted view is shown long enough for
+            // Lanczos to be worth it.
+            let fitted_size = (
+                (rect.right - rect.left).max(1) as u32,
+                (rect.bottom - rect.top).max(1) as u32,
+            );
+            if self.fitted.as_ref().map(Dib::size) != Some(fitted_size) {
+                let fitted = resample(img, fitted_size, scaling);
+                self.fitted = Some(display_dib(&fitted, color));
+            }
+            if let Some(fitted) = &self.fitted {
+                fitted.draw(hdc, &rect);
+                return;
+            }
+        }
+        if let Some(dib) = &self.dib {
+            dib.draw_scaled(hdc, &rect, scaling);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dib;
+    use image::{Rgb, RgbImage};
+
+    const RED: Rgb<u8> = Rgb([200, 10, 20]);
+    const BLUE: Rgb<u8> = Rgb([5, 30, 220]);
+
+    /// A tab showing a 2 x 2 checkerboard at twice its size.
+    fn checkerboard() -> Tab {
+        let img = RgbImage::from_fn(2, 2, |x, y| if (x + y) % 2 == 0 { RED } else { BLUE });
+        let mut tab = Tab::new(Path::new("checkerboard.png"), Navigation::default());
+        tab.set_image(Path::new("checkerboard.png"), DynamicImage::ImageRgb8(img));
+        tab.view = View::with_zoom(Some(2.0));
+        tab
+    }
+
+    fn render(tab: &mut Tab, color: Option<&ColorTransform>) -> image::RgbaImage {
+        let viewport = RECT {
+            left: 0,
+            top: 0,
+            right: 4,
+            bottom: 4,
+        };
+        dib::capture(4, 4, |hdc| tab.draw(hdc, &viewport, false, color)).unwrap()
+    }
+
+    fn rgba(Rgb([r, g, b]): Rgb<u8>) -> Rgba<u8> {
+        Rgba([r, g, b, 255])
+    }
+
+    #[test]
+    fn renders_each_pixel_as_a_block() {
+        let mut tab = checkerboard();
+        assert_eq!(tab.scaling(), Scaling::Nearest);
+        let img = render(&mut tab, None);
+        for (x, y, px) in img.enumerate_pixels() {
+            let expected = if (x / 2 + y / 2) % 2 == 0 { RED } else { BLUE };
+            assert_eq!(*px, rgba(expected), "at {x}, {y}");
+        }
+    }
+
+    #[test]
+    fn renders_rotated_and_through_the_color_transform() {
+        let mut tab = checkerboard();
+        tab.rotate(1);
+        tab.view = View::with_zoom(Some(2.0));
+        let color = ColorTransform::from_fn("inverted.icm", |rgb| rgb.map(|v| 255 - v));
+        let img = render(&mut tab, Some(&color));
+        let invert = |Rgb([r, g, b]): Rgb<u8>| Rgb([255 - r, 255 - g, 255 - b]);
+        // a quarter turn of a checkerboard swaps the colors of the corners.
+        assert_eq!(*img.get_pixel(0, 0), rgba(invert(BLUE)));
+        assert_eq!(*img.get_pixel(3, 0), rgba(invert(RED)));
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{ensure, Result};
+use std::mem;
+use windows::{
+    core::PWSTR,
+    Win32::{
+        Foundation::{HWND, LPARAM, WPARAM},
+        Graphics::Gdi::HFONT,
+        UI::{
+            Controls::{
+                InitCommonControlsEx, ICC_TAB_CLASSES, INITCOMMONCONTROLSEX, TCIF_TEXT, TCITEMW,
+                TCM_DELETEALLITEMS, TCM_GETCURSEL, TCM_INSERTITEMW, TCM_SETCURSEL, TCS_FOCUSNEVER,
+                WC_TABCONTROLW,
+            },
+            WindowsAndMessaging::{
+                CreateWindowExW, MoveWindow, SendMessageW, ShowWindow, HMENU, SW_HIDE, SW_SHOW,
+                WINDOW_EX_STYLE, WINDOW_STYLE, WM_SETFONT, WS_CHILD, WS_CLIPSIBLINGS,
+            },
+        },
+    },
+};
+
+/// Height of the tab strip at 96 DPI.
+pub const HEIGHT: i32 = 28;
+
+/// Creates the tab control, hidden until a second tab opens.
+pub fn create(parent: HWND, id: i32, font: HFONT) -> Result<HWND> {
+    let icc = INITCOMMONCONTROLSEX {
+        dwSize: mem::size_of::<INITCOMMONCONTROLSEX>() as u32,
+        dwICC: ICC_TAB_CLASSES,
+    };
+    unsafe { InitCommonControlsEx(&icc) };
+    let h_tab = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            WC_TABCONTROLW,
+            None,
+            // keep the keyboard on the main window, the arrow keys belong to the viewer.
+            WS_CHILD | WS_CLIPSIBLINGS | WINDOW_STYLE(TCS_FOCUSNEVER),
+            0,
+            0,
+            0,
+            0,
+            parent,
+            HMENU(id as isize),
+            None,
+            None,
+        )
+    };
+    ensure!(h_tab.0 != 0, "failed to create the tab strip.");
+    unsafe {
+        SendMessageW(
+            h_tab,
+            WM_SETFONT,
+            WPARAM(font.0 as usize),
+            LPARAM::default(),
+        )
+    };
+    Ok(h_tab)
+}
+
+/// Replaces the tabs with `titles` and selects `active`. Shows the strip only for two or more.
+pub fn sync(h_tab: HWND, titles: &[String], active: usize) {
+    unsafe {
+        SendMessageW(h_tab, TCM_DELETEALLITEMS, WPARAM(0), LPARAM(0));
+        for (i, title) in titles.iter().enumerate() {
+            let mut text: Vec<u16> = title.encode_utf16().chain(Some(0)).collect();
+            let item = TCITEMW {
+                mask: TCIF_TEXT,
+                pszText: PWSTR::from_raw(text.as_mut_ptr()),
+                ..Default::default()
+            };
+            SendMessageW(
+                h_tab,
+                TCM_INSERTITEMW,
+                WPARAM(i),
+                LPARAM(&item as *const _ as isize),
+            );
+        }
+        SendMessageW(h_tab, TCM_SETCURSEL, WPARAM(active), LPARAM(0));
+        ShowWindow(h_tab, if titles.len() > 1 { SW_SHOW } else { SW_HIDE });
+    }
+}
+
+/// The index of the selected tab.
+pub fn selection(h_tab: HWND) -> Option<usize> {
+    let index = unsafe { SendMessageW(h_tab, TCM_GETCURSEL, WPARAM(0), LPARAM(0)) }.0;
+    usize::try_from(index).ok()
+}
+
+pub fn move_to(h_tab: HWND, left: i32, top: i32, width: i32, height: i32) {
+    unsafe { MoveWindow(h_tab, left, top, width, height, true) };
+}
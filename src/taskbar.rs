@@ -0,0 +1,83 @@
+use std::sync::OnceLock;
+use windows::{
+    w,
+    Win32::{
+        Foundation::HWND,
+        System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER},
+        UI::{
+            Shell::{
+                ITaskbarList3, TaskbarList, TBPFLAG, TBPF_INDETERMINATE, TBPF_NOPROGRESS,
+                TBPF_NORMAL, TBPF_PAUSED,
+            },
+            WindowsAndMessaging::RegisterWindowMessageW,
+        },
+    },
+};
+
+/// The message Explorer sends once our taskbar button exists.
+pub fn button_created_message() -> u32 {
+    static MESSAGE: OnceLock<u32> = OnceLock::new();
+    *MESSAGE.get_or_init(|| unsafe { RegisterWindowMessageW(w!("TaskbarButtonCreated")) })
+}
+
+/// Progress shown on the window's taskbar button.
+///
+/// Nothing is shown until the button exists, and every call silently does nothing when
+/// `ITaskbarList3` isn't available.
+#[derive(Default)]
+pub struct Taskbar {
+    h_wnd: Option<HWND>,
+    list: Option<ITaskbarList3>,
+    failed: bool,
+}
+
+impl Taskbar {
+    /// Call on `TaskbarButtonCreated`. Explorer sends it again after it restarts.
+    pub fn button_created(&mut self, h_wnd: HWND) {
+        self.h_wnd = Some(h_wnd);
+    }
+
+    fn list(&mut self) -> Option<(HWND, &ITaskbarList3)> {
+        let h_wnd = self.h_wnd?;
+        if self.list.is_none() && !self.failed {
+            let list = unsafe {
+                CoCreateInstance::<_, ITaskbarList3>(&TaskbarList, None, CLSCTX_INPROC_SERVER)
+            }
+            .and_then(|list| unsafe { list.HrInit() }.map(|_| list));
+            self.failed = list.is_err();
+            self.list = list.ok();
+        }
+        self.list.as_ref().map(|list| (h_wnd, list))
+    }
+
+    fn set(&mut self, state: TBPFLAG, done: u64, total: u64) {
+        if let Some((h_wnd, list)) = self.list() {
+            unsafe {
+                list.SetProgressState(h_wnd, state).ok();
+                // a value would turn the moving bar back into a normal one.
+                if state != TBPF_NOPROGRESS && state != TBPF_INDETERMINATE {
+                    list.SetProgressValue(h_wnd, done, total).ok();
+                }
+            }
+        }
+    }
+
+    /// Determinate progress of a running operation.
+    pub fn set_progress(&mut self, done: u64, total: u64) {
+        self.set(TBPF_NORMAL, done, total);
+    }
+
+    /// A moving bar for an operation of unknown length, such as decoding.
+    pub fn set_busy(&mut self) {
+        self.set(TBPF_INDETERMINATE, 0, 0);
+    }
+
+    /// A paused-style bar, used for the position within a slideshow.
+    pub fn set_position(&mut self, position: u64, total: u64) {
+        self.set(TBPF_PAUSED, position, total);
+    }
+
+    pub fn clear(&mut self) {
+        self.set(TBPF_NOPROGRESS, 0, 0);
+    }
+}
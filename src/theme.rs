@@ -0,0 +1,203 @@
+use crate::settings::settings;
+use std::ffi::c_void;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use windows::{
+    w,
+    Win32::{
+        Foundation::{BOOL, COLORREF, ERROR_SUCCESS, HWND},
+        Graphics::{
+            Dwm::{DwmSetWindowAttribute, DWMWA_USE_IMMERSIVE_DARK_MODE, DWMWINDOWATTRIBUTE},
+            Gdi::{
+                CreateSolidBrush, DeleteObject, GetSysColor, InvalidateRect, SetBkColor,
+                SetTextColor, COLOR_MENUBAR, HBRUSH, HDC,
+            },
+        },
+        System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD},
+        UI::Controls::{
+            Dialogs::{ChooseColorW, CC_FULLOPEN, CC_RGBINIT, CHOOSECOLORW},
+            SetWindowTheme,
+        },
+    },
+};
+
+/// The `theme` setting.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum Theme {
+    /// Follow "Choose your app mode" in the Windows settings.
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn parse(value: &str) -> Option<Theme> {
+        match value {
+            "auto" => Some(Theme::Auto),
+            "light" => Some(Theme::Light),
+            "dark" => Some(Theme::Dark),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Theme::Auto => "auto",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+}
+
+/// The background colors the B key cycles through, `None` follows the theme.
+pub const BACKGROUNDS: [Option<[u8; 3]>; 4] = [
+    None,
+    Some([0x80, 0x80, 0x80]),
+    Some([0x00, 0x00, 0x00]),
+    Some([0xff, 0xff, 0xff]),
+];
+
+/// A dark neutral, close to what Windows uses for dark window backgrounds.
+const DARK_BACKGROUND: COLORREF = COLORREF(0x0020_2020);
+/// Edit boxes in the dark theme, a step lighter than the window, and their text.
+const DARK_EDIT: COLORREF = COLORREF(0x002d_2d2d);
+const DARK_TEXT: COLORREF = COLORREF(0x00f0_f0f0);
+
+static DARK: AtomicBool = AtomicBool::new(false);
+
+fn system_is_dark() -> bool {
+    let mut value = 1u32;
+    let mut size = mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("AppsUseLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut _ as *mut c_void),
+            Some(&mut size),
+        )
+    };
+    result == ERROR_SUCCESS && value == 0
+}
+
+/// Resolves the `theme` setting against the system. Call at startup and whenever the system
+/// theme or the setting changes; [`is_dark`] returns the result.
+pub fn refresh() {
+    let dark = match settings().theme {
+        Theme::Auto => system_is_dark(),
+        Theme::Light => false,
+        Theme::Dark => true,
+    };
+    DARK.store(dark, Ordering::Relaxed);
+}
+
+pub fn is_dark() -> bool {
+    DARK.load(Ordering::Relaxed)
+}
+
+fn to_colorref([r, g, b]: [u8; 3]) -> COLORREF {
+    COLORREF(r as u32 | (g as u32) << 8 | (b as u32) << 16)
+}
+
+fn from_colorref(color: COLORREF) -> [u8; 3] {
+    [color.0 as u8, (color.0 >> 8) as u8, (color.0 >> 16) as u8]
+}
+
+/// The color of the client background, around the image and behind transparent pixels: the
+/// `background` setting, or the theme's surround.
+pub fn background_color() -> COLORREF {
+    match settings().background {
+        Some(rgb) => to_colorref(rgb),
+        None if is_dark() => DARK_BACKGROUND,
+        None => COLORREF(unsafe { GetSysColor(COLOR_MENUBAR) }),
+    }
+}
+
+/// [`background_color`] as red, green and blue.
+pub fn background_rgb() -> [u8; 3] {
+    from_colorref(background_color())
+}
+
+/// A brush of [`background_color`], recreated when the color changes.
+pub fn background() -> HBRUSH {
+    static BRUSH: Mutex<Option<(COLORREF, HBRUSH)>> = Mutex::new(None);
+    let color = background_color();
+    let mut brush = BRUSH.lock().unwrap_or_else(|e| e.into_inner());
+    match *brush {
+        Some((current, h_brush)) if current == color => h_brush,
+        old => {
+            if let Some((_, h_brush)) = old {
+                unsafe { DeleteObject(h_brush) };
+            }
+            let h_brush = unsafe { CreateSolidBrush(color) };
+            *brush = Some((color, h_brush));
+            h_brush
+        }
+    }
+}
+
+/// Sets light text on a dark fill in `hdc` of an edit box, for `WM_CTLCOLOREDIT` in the dark
+/// theme, and returns the brush of that fill.
+pub fn dark_edit(hdc: HDC) -> HBRUSH {
+    static BRUSH: Mutex<Option<HBRUSH>> = Mutex::new(None);
+    unsafe {
+        SetTextColor(hdc, DARK_TEXT);
+        SetBkColor(hdc, DARK_EDIT);
+    }
+    let mut brush = BRUSH.lock().unwrap_or_else(|e| e.into_inner());
+    *brush.get_or_insert_with(|| unsafe { CreateSolidBrush(DARK_EDIT) })
+}
+
+/// The preset after the current `background` setting. From a custom color it goes back to
+/// following the theme.
+pub fn next_background(current: Option<[u8; 3]>) -> Option<[u8; 3]> {
+    BACKGROUNDS
+        .iter()
+        .position(|&preset| preset == current)
+        .and_then(|i| BACKGROUNDS[(i + 1) % BACKGROUNDS.len()])
+}
+
+/// Lets the user pick a background color, starting from the current one. `None` if cancelled.
+pub fn choose_background(h_wnd: HWND) -> Option<[u8; 3]> {
+    let mut custom = [COLORREF(0x00ff_ffff); 16];
+    for (slot, preset) in custom.iter_mut().zip(BACKGROUNDS.iter().flatten()) {
+        *slot = to_colorref(*preset);
+    }
+    let mut cc = CHOOSECOLORW {
+        lStructSize: mem::size_of::<CHOOSECOLORW>() as u32,
+        hwndOwner: h_wnd,
+        rgbResult: background_color(),
+        lpCustColors: custom.as_mut_ptr(),
+        Flags: CC_RGBINIT | CC_FULLOPEN,
+        ..Default::default()
+    };
+    unsafe { ChooseColorW(&mut cc) }
+        .as_bool()
+        .then(|| from_colorref(cc.rgbResult))
+}
+
+/// Switches the title bar of `h_wnd` and the look of `controls` to the current theme.
+pub fn apply(h_wnd: HWND, controls: &[HWND]) {
+    let dark = BOOL::from(is_dark());
+    let size = mem::size_of::<BOOL>() as u32;
+    let value = &dark as *const _ as *const c_void;
+    unsafe {
+        if DwmSetWindowAttribute(h_wnd, DWMWA_USE_IMMERSIVE_DARK_MODE, value, size).is_err() {
+            // Windows 10 before 20H1 used an undocumented value.
+            DwmSetWindowAttribute(h_wnd, DWMWINDOWATTRIBUTE(19), value, size).ok();
+        }
+        for &control in controls {
+            let theme = if is_dark() {
+                w!("DarkMode_Explorer")
+            } else {
+                w!("Explorer")
+            };
+            SetWindowTheme(control, theme, None).ok();
+            InvalidateRect(control, None, true);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{long_path, navigation, open_image, tab};
+use anyhow::{bail, Context, Result};
+use image::{DynamicImage, ImageFormat};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+/// Thumbnails asked for on the command line, rendered without a window.
+pub struct Job {
+    /// The longest edge, in pixels.
+    size: u32,
+    input: PathBuf,
+    output: PathBuf,
+    /// `input` and `output` are folders, every supported file in `input` is rendered.
+    dir: bool,
+}
+
+/// `--thumbnail 256 input.lz4i output.png` or `--thumbnail-dir 256 input output`, an error
+/// when the values are missing or invalid.
+pub fn arg() -> Option<Result<Job>> {
+    let mut args = env::args_os().skip(1);
+    let flag = args.find(|arg| arg == "--thumbnail" || arg == "--thumbnail-dir")?;
+    let values: Vec<OsString> = args.take(3).collect();
+    Some(job(flag == "--thumbnail-dir", values))
+}
+
+fn job(dir: bool, values: Vec<OsString>) -> Result<Job> {
+    let Ok([size, input, output]) = <[OsString; 3]>::try_from(values) else {
+        bail!("usage: pinion --thumbnail SIZE INPUT OUTPUT.png, or --thumbnail-dir SIZE INPUT_DIR OUTPUT_DIR");
+    };
+    let size = size
+        .to_str()
+        .and_then(|size| size.parse().ok())
+        .filter(|&size| size > 0)
+        .context("the thumbnail size must be a number of pixels, such as 256")?;
+    Ok(Job {
+        size,
+        input: input.into(),
+        output: output.into(),
+        dir,
+    })
+}
+
+/// Runs `job`, reporting to the console pinion was started from. Returns the exit code, 0
+/// when every thumbnail was written.
+pub fn run(job: Result<Job>) -> i32 {
+    // pinion is a windows subsystem program, without a console of its own.
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+    let failed = job.and_then(|job| {
+        if job.dir {
+            render_dir(&job)
+        } else {
+            render(&job.input, &job.output, job.size).map(|()| 0)
+        }
+    });
+    match failed {
+        Ok(0) => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            eprintln!("pinion: {e:#}");
+            1
+        }
+    }
+}
+
+/// `img` at most `size` pixels on its longest edge, resized as the fitted view shows it.
+/// Smaller images are kept as they are.
+fn thumbnail(img: &DynamicImage, size: u32) -> DynamicImage {
+    let longest = img.width().max(img.height());
+    if longest <= size {
+        return img.clone();
+    }
+    let scale = size as f64 / longest as f64;
+    let fitted = (
+        ((img.width() as f64 * scale).round() as u32).max(1),
+        ((img.height() as f64 * scale).round() as u32).max(1),
+    );
+    tab::resample(img, fitted, tab::guess_scaling(img))
+}
+
+/// Decodes `input` as opening it does and writes its thumbnail to `output` as PNG.
+fn render(input: &Path, output: &Path, size: u32) -> Result<()> {
+    let img = open_image(input, &mut |_, _| {})
+        .with_context(|| format!("cannot open {}", input.display()))?;
+    thumbnail(&img, size)
+        .save_with_format(long_path::extended(output), ImageFormat::Png)
+        .with_context(|| format!("cannot write {}", output.display()))
+}
+
+/// Writes `name.ext.png` to the output folder for every supported file `name.ext` of the input
+/// folder, skipping those that fail. Returns how many did. The extension stays in the name,
+/// so `a.jpg` and `a.png` don't overwrite each other.
+fn render_dir(job: &Job) -> Result<usize> {
+    let files = navigation::supported_files(&long_path::extended(&job.input))
+        .with_context(|| format!("cannot read {}", job.input.display()))?;
+    fs::create_dir_all(long_path::extended(&job.output))
+        .with_context(|| format!("cannot create {}", job.output.display()))?;
+    let mut failed = 0;
+    for file in &files {
+        let name = file.file_name().unwrap_or_default();
+        let input = job.input.join(name);
+        let mut output_name = name.to_os_string();
+        output_name.push(".png");
+        let output = job.output.join(output_name);
+        if let Err(e) = render(&input, &output, job.size) {
+            eprintln!("pinion: {e:#}");
+            failed += 1;
+        }
+    }
+    println!(
+        "{} thumbnails written, {failed} failed",
+        files.len() - failed
+    );
+    Ok(failed)
+}
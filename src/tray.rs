@@ -0,0 +1,119 @@
+use crate::l;
+use std::sync::OnceLock;
+use windows::{
+    core::PCWSTR,
+    w,
+    Win32::{
+        Foundation::{HWND, LPARAM, POINT, WPARAM},
+        UI::{
+            Shell::{
+                Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE,
+                NOTIFYICONDATAW,
+            },
+            WindowsAndMessaging::{
+                AppendMenuW, CreatePopupMenu, DestroyMenu, GetCursorPos, GetWindowTextW, LoadIconW,
+                PostMessageW, RegisterWindowMessageW, SetForegroundWindow, SetMenuDefaultItem,
+                TrackPopupMenu, IDI_APPLICATION, MF_CHECKED, MF_GRAYED, MF_SEPARATOR, MF_STRING,
+                TPM_NONOTIFY, TPM_RETURNCMD, TPM_RIGHTBUTTON, WM_APP, WM_NULL,
+            },
+        },
+    },
+};
+
+/// The callback message of the icon, `LPARAM` is the mouse message.
+pub const WM_TRAY: u32 = WM_APP + 2;
+
+/// Each window has at most one icon.
+const ICON_ID: u32 = 1;
+
+/// The message Explorer broadcasts after it restarted, all icons are gone then.
+pub fn taskbar_created_message() -> u32 {
+    static MESSAGE: OnceLock<u32> = OnceLock::new();
+    *MESSAGE.get_or_init(|| unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) })
+}
+
+/// A choice from the icon's menu.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Restore = 1,
+    PauseSlideshow,
+    Open,
+    Exit,
+}
+
+fn data(h_wnd: HWND) -> NOTIFYICONDATAW {
+    NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: h_wnd,
+        uID: ICON_ID,
+        ..Default::default()
+    }
+}
+
+/// Adds the icon for `h_wnd` to the notification area, with the window title as its tooltip.
+/// Returns whether it is there.
+pub fn add(h_wnd: HWND) -> bool {
+    let mut data = data(h_wnd);
+    data.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+    data.uCallbackMessage = WM_TRAY;
+    data.hIcon = unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap_or_default();
+    // cut to fit the tooltip.
+    unsafe { GetWindowTextW(h_wnd, &mut data.szTip) };
+    unsafe { Shell_NotifyIconW(NIM_ADD, &data) }.as_bool()
+}
+
+pub fn remove(h_wnd: HWND) {
+    unsafe { Shell_NotifyIconW(NIM_DELETE, &data(h_wnd)) };
+}
+
+/// Shows the icon's menu at the cursor and waits for a choice. Pause Slideshow is checked
+/// when `paused` and unavailable without a slideshow.
+pub fn menu(h_wnd: HWND, slideshow: bool, paused: bool) -> Option<Command> {
+    let menu = unsafe { CreatePopupMenu() }.ok()?;
+    let item = |flags, command: Command, text: &str| unsafe {
+        AppendMenuW(
+            menu,
+            MF_STRING | flags,
+            command as usize,
+            PCWSTR::from_raw(l(text).as_ptr()),
+        );
+    };
+    item(MF_STRING, Command::Restore, "Restore");
+    let flags = match (slideshow, paused) {
+        (false, _) => MF_GRAYED,
+        (true, true) => MF_CHECKED,
+        (true, false) => MF_STRING,
+    };
+    item(flags, Command::PauseSlideshow, "Pause slideshow");
+    item(MF_STRING, Command::Open, "Open…");
+    unsafe { AppendMenuW(menu, MF_SEPARATOR, 0, None) };
+    item(MF_STRING, Command::Exit, "Exit");
+
+    let mut pt = POINT::default();
+    let chosen = unsafe {
+        SetMenuDefaultItem(menu, Command::Restore as u32, 0);
+        GetCursorPos(&mut pt);
+        // without the window in front, the menu stays open when the user clicks elsewhere.
+        SetForegroundWindow(h_wnd);
+        let chosen = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_NONOTIFY | TPM_RIGHTBUTTON,
+            pt.x,
+            pt.y,
+            0,
+            h_wnd,
+            None,
+        );
+        PostMessageW(h_wnd, WM_NULL, WPARAM::default(), LPARAM::default());
+        DestroyMenu(menu);
+        chosen.0
+    };
+    [
+        Command::Restore,
+        Command::PauseSlideshow,
+        Command::Open,
+        Command::Exit,
+    ]
+    .into_iter()
+    .find(|&command| command as i32 == chosen)
+}
@@ -0,0 +1,253 @@
+use windows::Win32::Foundation::RECT;
+
+/// Zoom limits, as display pixels per image pixel.
+pub const MIN_ZOOM: f32 = 0.01;
+pub const MAX_ZOOM: f32 = 32.0;
+
+/// How the image is mapped onto the viewport.
+///
+/// Mouse and touch input both go through these methods, so they always agree on the current
+/// state.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct View {
+    /// `None` fits the image into the viewport without enlarging it.
+    zoom: Option<f32>,
+    /// Offset of the image center from the viewport center, in client pixels.
+    pan: (f32, f32),
+}
+
+/// Where the image lands in client coordinates.
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    pub x: f32,
+    pub y: f32,
+    pub scale: f32,
+}
+
+fn center(viewport: &RECT) -> (f32, f32) {
+    (
+        (viewport.left + viewport.right) as f32 / 2.0,
+        (viewport.top + viewport.bottom) as f32 / 2.0,
+    )
+}
+
+fn extent(viewport: &RECT) -> (f32, f32) {
+    (
+        (viewport.right - viewport.left).max(1) as f32,
+        (viewport.bottom - viewport.top).max(1) as f32,
+    )
+}
+
+impl View {
+    /// A centered view at a fixed `zoom`, or fitting when `None`.
+    pub fn with_zoom(zoom: Option<f32>) -> View {
+        View {
+            zoom: zoom.map(|zoom| zoom.clamp(MIN_ZOOM, MAX_ZOOM)),
+            ..View::default()
+        }
+    }
+
+    pub fn is_fit(&self) -> bool {
+        self.zoom.is_none()
+    }
+
+    /// The fixed zoom, `None` when fitting.
+    pub fn zoom(&self) -> Option<f32> {
+        self.zoom
+    }
+
+    /// Back to fitting the whole image into the viewport.
+    pub fn fit(&mut self) {
+        self.zoom = None;
+        self.pan = (0.0, 0.0);
+    }
+
+    /// The scale `fit` shows an image of `size` at.
+    pub fn fit_scale(viewport: &RECT, size: (u32, u32)) -> f32 {
+        let (vw, vh) = extent(viewport);
+        (vw / size.0.max(1) as f32)
+            .min(vh / size.1.max(1) as f32)
+            .min(1.0)
+    }
+
+    pub fn scale(&self, viewport: &RECT, size: (u32, u32)) -> f32 {
+        self.zoom.unwrap_or_else(|| View::fit_scale(viewport, size))
+    }
+
+    pub fn placement(&self, viewport: &RECT, size: (u32, u32)) -> Placement {
+        let scale = self.scale(viewport, size);
+        let (cx, cy) = center(viewport);
+        Placement {
+            x: cx + self.pan.0 - size.0 as f32 * scale / 2.0,
+            y: cy + self.pan.1 - size.1 as f32 * scale / 2.0,
+            scale,
+        }
+    }
+
+    /// The same view `factor` times as large, for a viewport `factor` times the size of
+    /// `viewport`. The zoom may exceed `MAX_ZOOM`.
+    pub fn magnified(&self, factor: f32, viewport: &RECT, size: (u32, u32)) -> View {
+        View {
+            zoom: Some(self.scale(viewport, size) * factor),
+            pan: (self.pan.0 * factor, self.pan.1 * factor),
+        }
+    }
+
+    /// The image rectangle in client coordinates.
+    pub fn image_rect(&self, viewport: &RECT, size: (u32, u32)) -> RECT {
+        let p = self.placement(viewport, size);
+        RECT {
+            left: p.x.round() as i32,
+            top: p.y.round() as i32,
+            right: (p.x + size.0 as f32 * p.scale).round() as i32,
+            bottom: (p.y + size.1 as f32 * p.scale).round() as i32,
+        }
+    }
+
+    /// The image pixel under the client point `pt`, possibly outside the image.
+    pub fn client_to_image(&self, pt: (f32, f32), viewport: &RECT, size: (u32, u32)) -> (f32, f32) {
+        let p = self.placement(viewport, size);
+        ((pt.0 - p.x) / p.scale, (pt.1 - p.y) / p.scale)
+    }
+
+    /// The client point showing the image pixel `pt`, the inverse of [`View::client_to_image`].
+    pub fn image_to_client(&self, pt: (f32, f32), viewport: &RECT, size: (u32, u32)) -> (f32, f32) {
+        let p = self.placement(viewport, size);
+        (p.x + pt.0 * p.scale, p.y + pt.1 * p.scale)
+    }
+
+    /// Zooms and pans so the image region `rect`, given as left, top, right and bottom in image
+    /// pixels, fills the viewport as far as the zoom limits allow.
+    pub fn frame(&mut self, rect: [f32; 4], viewport: &RECT, size: (u32, u32)) {
+        let [left, top, right, bottom] = rect;
+        let (vw, vh) = extent(viewport);
+        let zoom = (vw / (right - left).max(1.0))
+            .min(vh / (bottom - top).max(1.0))
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        // the region's center relative to the image center, in image pixels.
+        let ix = (left + right) / 2.0 - size.0 as f32 / 2.0;
+        let iy = (top + bottom) / 2.0 - size.1 as f32 / 2.0;
+        self.zoom = Some(zoom);
+        self.pan = (-ix * zoom, -iy * zoom);
+        self.clamp_pan(viewport, size);
+    }
+
+    /// Whether the image is larger than the viewport, so dragging scrolls it.
+    pub fn can_pan(&self, viewport: &RECT, size: (u32, u32)) -> bool {
+        let scale = self.scale(viewport, size);
+        let (vw, vh) = extent(viewport);
+        size.0 as f32 * scale > vw + 0.5 || size.1 as f32 * scale > vh + 0.5
+    }
+
+    /// Sets the zoom while keeping the image point under `anchor` (client coordinates) in place.
+    pub fn set_zoom_at(
+        &mut self,
+        zoom: f32,
+        anchor: (f32, f32),
+        viewport: &RECT,
+        size: (u32, u32),
+    ) {
+        let old = self.scale(viewport, size);
+        let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        let (cx, cy) = center(viewport);
+        // the anchor relative to the image center, in image pixels.
+        let ix = (anchor.0 - cx - self.pan.0) / old;
+        let iy = (anchor.1 - cy - self.pan.1) / old;
+        self.zoom = Some(zoom);
+        self.pan = (anchor.0 - cx - ix * zoom, anchor.1 - cy - iy * zoom);
+        self.clamp_pan(viewport, size);
+    }
+
+    /// Multiplies the current scale by `factor` around `anchor`.
+    pub fn zoom_at(&mut self, factor: f32, anchor: (f32, f32), viewport: &RECT, size: (u32, u32)) {
+        let zoom = self.scale(viewport, size) * factor;
+        self.set_zoom_at(zoom, anchor, viewport, size);
+    }
+
+    pub fn pan_by(&mut self, dx: f32, dy: f32, viewport: &RECT, size: (u32, u32)) {
+        self.pan.0 += dx;
+        self.pan.1 += dy;
+        self.clamp_pan(viewport, size);
+    }
+
+    /// Keeps an image larger than the viewport covering it, and a smaller one centered.
+    fn clamp_pan(&mut self, viewport: &RECT, size: (u32, u32)) {
+        let scale = self.scale(viewport, size);
+        let (vw, vh) = extent(viewport);
+        let max_x = ((size.0 as f32 * scale - vw) / 2.0).max(0.0);
+        let max_y = ((size.1 as f32 * scale - vh) / 2.0).max(0.0);
+        self.pan = (
+            self.pan.0.clamp(-max_x, max_x),
+            self.pan.1.clamp(-max_y, max_y),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: RECT = RECT {
+        left: 0,
+        top: 40,
+        right: 800,
+        bottom: 640,
+    };
+    const SIZE: (u32, u32) = (1000, 500);
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn client_and_image_points_round_trip() {
+        let mut zoomed = View::with_zoom(Some(3.0));
+        zoomed.pan_by(-250.0, 90.0, &VIEWPORT, SIZE);
+        for view in [View::default(), zoomed] {
+            for pt in [(0.0, 0.0), (123.5, 77.25), (1000.0, 500.0), (-10.0, 600.0)] {
+                let client = view.image_to_client(pt, &VIEWPORT, SIZE);
+                assert!(close(view.client_to_image(client, &VIEWPORT, SIZE), pt));
+            }
+        }
+    }
+
+    #[test]
+    fn fitting_centers_the_image() {
+        let view = View::default();
+        assert_eq!(View::fit_scale(&VIEWPORT, SIZE), 0.8);
+        let rect = view.image_rect(&VIEWPORT, SIZE);
+        assert_eq!(
+            (rect.left, rect.top, rect.right, rect.bottom),
+            (0, 140, 800, 540)
+        );
+        assert!(close(
+            view.client_to_image((400.0, 340.0), &VIEWPORT, SIZE),
+            (500.0, 250.0)
+        ));
+        // small images are not enlarged.
+        assert_eq!(View::fit_scale(&VIEWPORT, (100, 100)), 1.0);
+    }
+
+    #[test]
+    fn zooming_keeps_the_anchor_in_place() {
+        let mut view = View::default();
+        let anchor = (600.0, 300.0);
+        let under = view.client_to_image(anchor, &VIEWPORT, SIZE);
+        view.zoom_at(2.0, anchor, &VIEWPORT, SIZE);
+        assert_eq!(view.zoom(), Some(1.6));
+        assert!(close(view.image_to_client(under, &VIEWPORT, SIZE), anchor));
+        view.set_zoom_at(1000.0, anchor, &VIEWPORT, SIZE);
+        assert_eq!(view.zoom(), Some(MAX_ZOOM));
+    }
+
+    #[test]
+    fn panning_stops_at_the_edges() {
+        let mut view = View::with_zoom(Some(1.0));
+        view.pan_by(10_000.0, 10_000.0, &VIEWPORT, SIZE);
+        let rect = view.image_rect(&VIEWPORT, SIZE);
+        // wide enough to cover the viewport across, not down.
+        assert_eq!((rect.left, rect.top), (0, 90));
+        assert!(view.can_pan(&VIEWPORT, SIZE));
+        assert!(!View::default().can_pan(&VIEWPORT, SIZE));
+    }
+}